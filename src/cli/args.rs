@@ -26,9 +26,20 @@ pub enum Commands {
         /// The role(s) this file belongs to
         #[arg(long)]
         role: Vec<String>,
+        /// Add the file even if it looks like it contains credentials
+        #[arg(long)]
+        allow_secrets: bool,
+        /// Track the file as a template: `{{ secret "name" }}` placeholders are
+        /// filled in from `secrets set` values and the result is written as a copy
+        #[arg(long)]
+        template: bool,
     },
     /// Push changes to the remote repository
-    Push,
+    Push {
+        /// Push even if changed files look like they contain credentials
+        #[arg(long)]
+        allow_secrets: bool,
+    },
     /// Pull changes from the remote repository and apply them
     Pull,
     /// Apply the current repository state to local filesystem
@@ -40,6 +51,20 @@ pub enum Commands {
         #[command(subcommand)]
         command: SecretCommands,
     },
+    /// Manage the roles assigned to this machine
+    Role {
+        #[command(subcommand)]
+        command: RoleCommands,
+    },
+    /// Check team-config.toml
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Show a summary of tracked files, pending changes and expiring secrets
+    Status,
+    /// List the machines registered in the repository and how current they are
+    Machines,
     /// Show commit history
     History,
     /// Undo the last change (revert commit)
@@ -48,16 +73,168 @@ pub enum Commands {
         commit: Option<String>,
     },
     /// Diagnose issues with the setup
-    Doctor,
+    Doctor {
+        /// Also decrypt secrets in memory and check them against their destinations
+        #[arg(long)]
+        secrets: bool,
+        /// Repair what can be fixed safely (missing or misdirected links, permissions, stale links)
+        #[arg(long)]
+        fix: bool,
+        /// With --fix, only list the repairs
+        #[arg(long, requires = "fix")]
+        dry_run: bool,
+        /// Print the report as JSON
+        #[arg(long, conflicts_with = "fix")]
+        json: bool,
+        /// Only run this check (repeatable): config, git, sources, roles, files, permissions, secrets, state, key, expiry
+        #[arg(long = "check", value_name = "NAME")]
+        checks: Vec<String>,
+    },
+    /// Explain why an entry is or isn't applied on this machine
+    Explain {
+        /// Destination, repository source or file name of the entry
+        path: String,
+    },
+    /// Run a command with dotenv secrets injected into its environment
+    Exec {
+        /// Secret to load (name, destination or source); defaults to all `.env` secrets
+        #[arg(short, long = "secret")]
+        secrets: Vec<String>,
+        /// The command and its arguments (after `--`)
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RoleCommands {
+    /// Assign a role to this machine
+    Add {
+        /// Role name (e.g. "work", "personal")
+        name: String,
+        /// Apply afterwards so newly enabled entries are linked
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Remove a role from this machine
+    Remove {
+        /// Role name
+        name: String,
+        /// Apply afterwards so newly disabled entries are unlinked
+        #[arg(long)]
+        apply: bool,
+    },
+    /// List roles and the entries each one enables
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Report duplicate or overlapping entries, unknown roles and platforms, and other mistakes
+    Validate {
+        /// Config file to check (defaults to this machine's team-config.toml)
+        path: Option<PathBuf>,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 pub enum SecretCommands {
     /// Initialize secrets (generate key pair)
-    Init,
+    Init {
+        /// Protect the key file with a passphrase
+        #[arg(long)]
+        passphrase: bool,
+    },
+    /// Encrypt the existing key file with a passphrase
+    Protect,
     /// Add a secret file (encrypts and adds to repo)
     Add {
         /// Path to the secret file
         path: PathBuf,
+        /// The role(s) this secret belongs to
+        #[arg(long)]
+        role: Vec<String>,
+        /// The platform(s) this secret applies to (e.g. "linux", "macos")
+        #[arg(long)]
+        platform: Vec<String>,
+        /// Expected owner of the decrypted file (user name or uid)
+        #[arg(long)]
+        owner: Option<String>,
+        /// Expected group of the decrypted file (group name or gid)
+        #[arg(long)]
+        group: Option<String>,
+        /// Never restore the secret with group/other access
+        #[arg(long)]
+        sensitive: bool,
+        /// Date the secret expires (YYYY-MM-DD)
+        #[arg(long)]
+        expires_at: Option<String>,
+        /// How often the secret should be rotated (e.g. 90d, 12w, 6m)
+        #[arg(long)]
+        rotate_every: Option<String>,
+        /// Fetch the value from a backend (pass, gopass, env, vault) instead of encrypting the file
+        #[arg(long, requires = "source")]
+        backend: Option<String>,
+        /// Backend path of the value (e.g. `secret/npm#token` for vault)
+        #[arg(long, requires = "backend")]
+        source: Option<String>,
+    },
+    /// List tracked secrets with their recipients and local status
+    List,
+    /// Decrypt a secret to stdout
+    Show {
+        /// Secret name (file name), destination or repository source
+        name: String,
+    },
+    /// Edit a secret in $EDITOR and re-encrypt it
+    Edit {
+        /// Secret name (file name), destination or repository source
+        name: String,
+    },
+    /// Stop tracking a secret and delete its encrypted copy
+    Remove {
+        /// Secret name (file name), destination or repository source
+        name: String,
+        /// Also delete the decrypted local file
+        #[arg(long)]
+        delete_local: bool,
+    },
+    /// Set an inline value used by `{{ secret "name" }}` placeholders in templates
+    Set {
+        /// Value name
+        name: String,
+        /// The value (prompted for, or read from stdin, when omitted)
+        value: Option<String>,
+    },
+    /// Remove an inline value
+    Unset {
+        /// Value name
+        name: String,
+    },
+    /// Generate a new key, re-encrypt every secret to it and archive the old key
+    Rotate,
+    /// Store secrets ASCII-armored and convert existing binary `.age` files
+    Armor,
+    /// Export the key as a passphrase-encrypted, ASCII-armored backup
+    ExportKey {
+        /// Write the backup to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Import a key backup created with `secrets export-key`
+    ImportKey {
+        /// Path to the armored key backup
+        path: PathBuf,
+        /// Import even if the key decrypts no tracked secret or a different key exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Give another age or SSH public key access to all secrets
+    Grant {
+        /// Public key (`age1...`, `ssh-ed25519 ...`, `ssh-rsa ...`) or path to a `.pub` file
+        recipient: String,
+        /// Only grant access to secrets scoped to this role
+        #[arg(long)]
+        role: Option<String>,
     },
 }
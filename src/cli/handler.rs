@@ -7,12 +7,17 @@ pub fn handle_command(command: Commands) -> Result<()> {
             crate::core::engine::init::init(url, role)?;
             Ok(())
         }
-        Commands::Add { path, role } => {
-            crate::core::engine::add::add(path, role)?;
+        Commands::Add {
+            path,
+            role,
+            allow_secrets,
+            template,
+        } => {
+            crate::core::engine::add::add(path, role, allow_secrets, template)?;
             Ok(())
         }
-        Commands::Push => {
-            crate::core::engine::push::push()?;
+        Commands::Push { allow_secrets } => {
+            crate::core::engine::push::push(allow_secrets)?;
             Ok(())
         }
         Commands::Pull => {
@@ -28,20 +33,134 @@ pub fn handle_command(command: Commands) -> Result<()> {
             Ok(())
         }
         Commands::Secrets { command } => match command {
-            crate::cli::args::SecretCommands::Init => {
+            crate::cli::args::SecretCommands::Init { passphrase } => {
                 let key = crate::core::secret::keys::generate_key()?;
-                crate::core::secret::keys::save_key(&key)?;
+                if passphrase {
+                    let passphrase = crate::core::secret::keys::new_passphrase()?;
+                    crate::core::secret::keys::save_protected_key(&key, &passphrase)?;
+                } else {
+                    crate::core::secret::keys::save_key(&key)?;
+                }
                 println!(
                     "Secret key generated at {:?}",
                     crate::core::secret::keys::get_key_path()?
                 );
                 Ok(())
             }
-            crate::cli::args::SecretCommands::Add { path } => {
-                crate::core::engine::add::add_secret(path)?;
+            crate::cli::args::SecretCommands::Protect => {
+                use crate::core::secret::keys::{self, KeyForm};
+                if keys::get_key_path()?.exists() && keys::key_form()? == KeyForm::Passphrase {
+                    println!("Key file is already passphrase-protected.");
+                    return Ok(());
+                }
+                let identity = match keys::load_key()? {
+                    keys::SecretIdentity::Age(identity) => identity,
+                    keys::SecretIdentity::Ssh { path, .. } => anyhow::bail!(
+                        "No configsync key file; secrets use the SSH key {:?}, which is protected by ssh-keygen.",
+                        path
+                    ),
+                };
+                let passphrase = keys::new_passphrase()?;
+                use secrecy::ExposeSecret;
+                keys::save_protected_key(identity.to_string().expose_secret(), &passphrase)?;
+                println!("Key file is now passphrase-protected.");
                 Ok(())
             }
+            crate::cli::args::SecretCommands::Add {
+                path,
+                role,
+                platform,
+                owner,
+                group,
+                sensitive,
+                expires_at,
+                rotate_every,
+                backend,
+                source,
+            } => {
+                use crate::core::engine::add::{self, SecretOptions};
+                let options = SecretOptions {
+                    role,
+                    platform,
+                    owner,
+                    group,
+                    sensitive,
+                    expires_at,
+                    rotate_every,
+                };
+                match (backend, source) {
+                    (Some(backend), Some(source)) => {
+                        add::add_backend_secret(path, backend, source, options)?
+                    }
+                    _ => add::add_secret(path, options)?,
+                }
+                Ok(())
+            }
+            crate::cli::args::SecretCommands::List => {
+                crate::core::engine::secrets::list()?;
+                Ok(())
+            }
+            crate::cli::args::SecretCommands::Show { name } => {
+                crate::core::engine::secrets::show(&name)?;
+                Ok(())
+            }
+            crate::cli::args::SecretCommands::Edit { name } => {
+                crate::core::engine::secrets::edit(&name)?;
+                Ok(())
+            }
+            crate::cli::args::SecretCommands::Remove { name, delete_local } => {
+                crate::core::engine::secrets::remove(&name, delete_local)?;
+                Ok(())
+            }
+            crate::cli::args::SecretCommands::Set { name, value } => {
+                crate::core::engine::secrets::set_value(&name, value)?;
+                Ok(())
+            }
+            crate::cli::args::SecretCommands::Unset { name } => {
+                crate::core::engine::secrets::unset_value(&name)?;
+                Ok(())
+            }
+            crate::cli::args::SecretCommands::Rotate => {
+                crate::core::engine::rotate::rotate()?;
+                Ok(())
+            }
+            crate::cli::args::SecretCommands::Armor => {
+                crate::core::engine::armor::armor()?;
+                Ok(())
+            }
+            crate::cli::args::SecretCommands::ExportKey { output } => {
+                crate::core::engine::backup::export_key(output)?;
+                Ok(())
+            }
+            crate::cli::args::SecretCommands::ImportKey { path, force } => {
+                crate::core::engine::backup::import_key(path, force)?;
+                Ok(())
+            }
+            crate::cli::args::SecretCommands::Grant { recipient, role } => {
+                crate::core::engine::rotate::grant(&recipient, role.as_deref())?;
+                Ok(())
+            }
+        },
+        Commands::Role { command } => match command {
+            crate::cli::args::RoleCommands::Add { name, apply } => {
+                crate::core::engine::role::add(&name, apply)
+            }
+            crate::cli::args::RoleCommands::Remove { name, apply } => {
+                crate::core::engine::role::remove(&name, apply)
+            }
+            crate::cli::args::RoleCommands::List => crate::core::engine::role::list(),
+        },
+        Commands::Config { command } => match command {
+            crate::cli::args::ConfigCommands::Validate { path } => {
+                crate::core::engine::config::validate(path.as_deref())
+            }
         },
+        Commands::Status => {
+            crate::core::engine::status::status()?;
+            Ok(())
+        }
+        Commands::Explain { path } => crate::core::engine::explain::explain(&path),
+        Commands::Machines => crate::core::engine::machines::list(),
         Commands::History => {
             let config_dir = directories::ProjectDirs::from("com", "configsync", "configsync")
                 .context("Could not determine project directories")?
@@ -68,9 +187,28 @@ pub fn handle_command(command: Commands) -> Result<()> {
             crate::core::engine::apply::apply()?;
             Ok(())
         }
-        Commands::Doctor => {
-            crate::core::doctor::check()?;
+        Commands::Doctor {
+            secrets,
+            fix,
+            dry_run,
+            json,
+            checks,
+        } => {
+            let healthy = crate::core::doctor::check(&crate::core::doctor::Options {
+                secrets,
+                fix,
+                dry_run,
+                json,
+                checks,
+            })?;
+            if !healthy {
+                std::process::exit(1);
+            }
             Ok(())
         }
+        Commands::Exec { secrets, command } => {
+            let code = crate::core::engine::exec::exec(secrets, command)?;
+            std::process::exit(code);
+        }
     }
 }
@@ -1,4 +1,5 @@
 use crate::core::config::schema::TeamConfig;
+use crate::core::config::validate::{self, Problem};
 use anyhow::{Context, Result};
 use std::fs;
 use std::path::Path;
@@ -6,12 +7,35 @@ use std::path::Path;
 pub struct ConfigLoader;
 
 impl ConfigLoader {
+    /// Loads the config, failing on syntax errors and on validation errors.
+    /// Warnings are left to `config validate` and `doctor`.
     pub fn load<P: AsRef<Path>>(path: P) -> Result<TeamConfig> {
-        let content = fs::read_to_string(path.as_ref()).context("Failed to read config file")?;
-        let config: TeamConfig = toml::from_str(&content).context("Failed to parse config file")?;
+        let (config, problems) = Self::load_with_problems(path.as_ref())?;
+        let errors: Vec<String> = problems
+            .iter()
+            .filter(|p| p.is_error())
+            .map(|p| p.located(path.as_ref()))
+            .collect();
+        if !errors.is_empty() {
+            anyhow::bail!(
+                "Invalid config file:\n{}\nRun `configsync config validate` to check it.",
+                errors.join("\n")
+            );
+        }
         Ok(config)
     }
 
+    /// Parses the config and validates it, returning every problem found.
+    pub fn load_with_problems<P: AsRef<Path>>(path: P) -> Result<(TeamConfig, Vec<Problem>)> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).context("Failed to read config file")?;
+        let config: TeamConfig = toml::from_str(&content).context("Failed to parse config file")?;
+        let config_dir = path.parent().unwrap_or(Path::new("."));
+        let known_roles = crate::core::machines::known_roles(config_dir);
+        let problems = validate::validate(&config, &content, config_dir, &known_roles);
+        Ok((config, problems))
+    }
+
     pub fn save<P: AsRef<Path>>(config: &TeamConfig, path: P) -> Result<()> {
         let content = toml::to_string_pretty(config).context("Failed to serialize config")?;
         fs::write(path.as_ref(), content).context("Failed to write config file")?;
@@ -1,13 +1,19 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TeamConfig {
     pub team: Team,
     pub repository: Repository,
+    /// Roles that imply others, e.g. `work-laptop = ["work", "laptop"]`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub roles: BTreeMap<String, Vec<String>>,
     #[serde(default)]
     pub files: Vec<FileConfig>,
     #[serde(default)]
     pub secrets: SecretsConfig,
+    #[serde(default, skip_serializing_if = "ScanConfig::is_empty")]
+    pub scan: ScanConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -40,24 +46,170 @@ pub struct FileConfig {
     pub critical: bool,
     #[serde(default)]
     pub protect: bool,
+    /// Role expressions (`work`, `work && linux && !ci`); the entry applies
+    /// when any of them is true.
     #[serde(default)]
     pub roles: Option<Vec<String>>,
+    /// Secret backend for `type = "secret"` entries; `source` is then a backend path.
+    #[serde(default)]
+    pub backend: Option<String>,
+    /// Octal mode restored on decrypted secrets (e.g. "640"); 600 when unset.
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// Expected owner (user name or uid) of a decrypted secret.
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Expected group (group name or gid) of a decrypted secret.
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Never grant group/other access, whatever `mode` says.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sensitive: bool,
+    /// Date the secret stops working (YYYY-MM-DD); warned about before it passes.
+    #[serde(default)]
+    pub expires_at: Option<String>,
+    /// How often the secret should be rotated, e.g. "90d".
+    #[serde(default)]
+    pub rotate_every: Option<String>,
+    /// Date the secret's content was last updated; set by `secrets add` and `secrets edit`.
+    #[serde(default)]
+    pub rotated_at: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl FileConfig {
+    /// True when `platforms` is empty, contains "*", or names the current OS.
+    pub fn matches_platform(&self) -> bool {
+        self.platforms.is_empty()
+            || self
+                .platforms
+                .iter()
+                .any(|p| p == "*" || p == std::env::consts::OS || (p == "unix" && cfg!(unix)))
+    }
+
+    /// Short name used to refer to an entry on the command line: the destination's file name.
+    pub fn name(&self) -> &str {
+        std::path::Path::new(&self.destination)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(&self.destination)
+    }
+
+    /// True when `source` is a relative path that stays inside the repository
+    /// (no `..`, no root or drive prefix).
+    pub fn source_in_repo(&self) -> bool {
+        std::path::Path::new(&self.source).components().all(|c| {
+            matches!(
+                c,
+                std::path::Component::Normal(_) | std::path::Component::CurDir
+            )
+        })
+    }
+
+    /// True when `name` is the entry's short name, destination or repository source.
+    pub fn matches_name(&self, name: &str) -> bool {
+        self.name() == name
+            || self.destination == name
+            || self.source == name
+            || shellexpand::tilde(&self.destination) == shellexpand::tilde(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum FileType {
     File,
     Directory,
     Secret,
+    /// A directory stored as one encrypted tarball (`secrets/<name>.tar.age`).
+    SecretDir,
+    /// A plaintext file whose `{{ secret "name" }}` placeholders are filled in on apply.
+    Template,
+}
+
+impl FileType {
+    /// The name used for this type in `team-config.toml`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileType::File => "file",
+            FileType::Directory => "directory",
+            FileType::Secret => "secret",
+            FileType::SecretDir => "secretdir",
+            FileType::Template => "template",
+        }
+    }
+
+    /// How entries of this type are referred to in messages.
+    pub fn label(&self) -> &'static str {
+        match self {
+            FileType::File => "File",
+            FileType::Directory => "Directory",
+            FileType::Secret => "Secret",
+            FileType::SecretDir => "Secret directory",
+            FileType::Template => "Template",
+        }
+    }
+
+    /// True for entries that are decrypted onto disk rather than symlinked.
+    pub fn is_secret(&self) -> bool {
+        matches!(self, FileType::Secret | FileType::SecretDir)
+    }
+
+    /// True for entries written to their destination as a copy (secrets and
+    /// rendered templates), so the copy's mode and owner matter.
+    pub fn is_copy(&self) -> bool {
+        self.is_secret() || matches!(self, FileType::Template)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct SecretsConfig {
+    /// When true, secrets without an explicit `backend` are fetched from `vault_type`.
+    #[serde(default)]
     pub vault_enabled: bool,
+    /// Default backend for secrets: "age", "pass", "gopass", "env" or "vault".
+    #[serde(default)]
     pub vault_type: String,
+    /// Base URL of the Vault-compatible KV server (falls back to `VAULT_ADDR`).
+    #[serde(default)]
+    pub vault_address: Option<String>,
+    /// Dotenv file used by the "env" backend when a source has no `file#` prefix.
+    #[serde(default)]
+    pub env_file: Option<String>,
     #[serde(default)]
     pub encrypted_files: Vec<String>,
+    /// Additional age or SSH public keys (e.g. teammates) that every secret is encrypted to.
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    /// Per-role recipient sets. Secrets scoped to one of these roles are encrypted
+    /// only to that set, so machines outside it cannot decrypt them at all.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub role_recipients: BTreeMap<String, Vec<String>>,
+    /// Write `.age` files ASCII-armored so they diff as text.
+    #[serde(default)]
+    pub armor: bool,
+}
+
+/// Allow-list for the plaintext secret scanner run by `add`, `push` and `watch`.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ScanConfig {
+    /// Glob patterns of repository paths that are never scanned.
+    #[serde(default)]
+    pub allow_paths: Vec<String>,
+    /// Regexes; matched text that fits one of these is not reported.
+    #[serde(default)]
+    pub allow_patterns: Vec<String>,
+    /// Rules to turn off, e.g. "high-entropy".
+    #[serde(default)]
+    pub disabled_rules: Vec<String>,
+}
+
+impl ScanConfig {
+    /// True when nothing is allow-listed or disabled, so `[scan]` can be left out.
+    pub fn is_empty(&self) -> bool {
+        self.allow_paths.is_empty()
+            && self.allow_patterns.is_empty()
+            && self.disabled_rules.is_empty()
+    }
 }
 
 impl Default for TeamConfig {
@@ -72,8 +224,10 @@ impl Default for TeamConfig {
                 branch: "main".to_string(),
                 auto_update_interval: 300,
             },
+            roles: BTreeMap::new(),
             files: vec![],
             secrets: SecretsConfig::default(),
+            scan: ScanConfig::default(),
         }
     }
 }
@@ -0,0 +1,509 @@
+use crate::core::config::schema::{FileConfig, FileType, TeamConfig};
+use crate::core::roles::{self, RoleExpr};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use toml_edit::{ImDocument, Item, Value};
+
+/// Values `platforms` may contain.
+pub const PLATFORMS: &[&str] = &[
+    "*", "unix", "linux", "macos", "windows", "freebsd", "openbsd", "netbsd", "android", "ios",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// The config cannot be applied safely; loading it fails.
+    Error,
+    /// Probably a mistake, but every command still works.
+    Warning,
+}
+
+/// Something wrong with `team-config.toml` beyond its syntax.
+#[derive(Debug)]
+pub struct Problem {
+    pub level: Level,
+    /// Key of the entry the problem is about (`source`, `roles`, ...).
+    pub key: &'static str,
+    pub message: String,
+    /// 1-based line and column of the offending value.
+    pub location: Option<(usize, usize)>,
+}
+
+impl Problem {
+    pub fn is_error(&self) -> bool {
+        self.level == Level::Error
+    }
+
+    /// `path:line:column: message`, the way compilers and editors expect it.
+    pub fn located(&self, path: &Path) -> String {
+        match self.location {
+            Some((line, column)) => {
+                format!("{}:{}:{}: {}", path.display(), line, column, self.message)
+            }
+            None => format!("{}: {}", path.display(), self.message),
+        }
+    }
+}
+
+/// Finds where entries' keys are in the original text.
+struct Spans<'a> {
+    content: &'a str,
+    doc: Option<ImDocument<&'a str>>,
+}
+
+impl<'a> Spans<'a> {
+    fn new(content: &'a str) -> Self {
+        Self {
+            content,
+            doc: ImDocument::parse(content).ok(),
+        }
+    }
+
+    /// Line and column of `key` in the `index`th `[[files]]` entry, or of the
+    /// entry itself when the key is absent.
+    fn entry(&self, index: usize, key: &str) -> Option<(usize, usize)> {
+        let span = match self.doc.as_ref()?.get("files")? {
+            Item::ArrayOfTables(entries) => {
+                let table = entries.get(index)?;
+                table.get(key).and_then(Item::span).or_else(|| table.span())
+            }
+            Item::Value(Value::Array(entries)) => {
+                let entry = entries.get(index)?;
+                match entry {
+                    Value::InlineTable(table) => table.get(key).and_then(Value::span),
+                    _ => None,
+                }
+                .or_else(|| entry.span())
+            }
+            _ => None,
+        }?;
+        Some(self.line_column(span.start))
+    }
+
+    /// Line and column of `role`'s key in `[roles]`.
+    fn role(&self, role: &str) -> Option<(usize, usize)> {
+        let span = self
+            .doc
+            .as_ref()?
+            .get("roles")?
+            .as_table()?
+            .key(role)?
+            .span()?;
+        Some(self.line_column(span.start))
+    }
+
+    fn line_column(&self, offset: usize) -> (usize, usize) {
+        let before = &self.content[..offset.min(self.content.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        (line, column)
+    }
+}
+
+/// Checks `config` (parsed from `content`) for mistakes the TOML schema cannot
+/// express. `config_dir` is the repository holding the sources; `known_roles`
+/// are the roles machines are known to have.
+pub fn validate(
+    config: &TeamConfig,
+    content: &str,
+    config_dir: &Path,
+    known_roles: &BTreeSet<String>,
+) -> Vec<Problem> {
+    let spans = Spans::new(content);
+    let mut problems = Vec::new();
+    for (role, implied) in &config.roles {
+        for name in std::iter::once(role).chain(implied) {
+            if let Err(e) = roles::check_name(name) {
+                problems.push(Problem {
+                    level: Level::Warning,
+                    key: "roles",
+                    message: e.to_string(),
+                    location: spans.role(role),
+                });
+            }
+        }
+    }
+
+    let mut report = |level, index: usize, key: &'static str, message: String| {
+        problems.push(Problem {
+            level,
+            key,
+            message,
+            location: spans.entry(index, key),
+        })
+    };
+    let line_of = |index: usize| {
+        spans
+            .entry(index, "destination")
+            .map_or_else(|| format!("#{}", index + 1), |(line, _)| line.to_string())
+    };
+
+    let mut known: BTreeSet<&str> = known_roles.iter().map(String::as_str).collect();
+    known.extend(PLATFORMS);
+    for (role, implied) in &config.roles {
+        known.insert(role);
+        known.extend(implied.iter().map(String::as_str));
+    }
+
+    for (index, file) in config.files.iter().enumerate() {
+        for platform in &file.platforms {
+            if !PLATFORMS.contains(&platform.as_str()) {
+                report(
+                    Level::Warning,
+                    index,
+                    "platforms",
+                    format!(
+                        "Unknown platform `{}` (expected one of: {})",
+                        platform,
+                        PLATFORMS.join(", ")
+                    ),
+                );
+            }
+        }
+
+        for source in file.roles.iter().flatten() {
+            match RoleExpr::parse(source) {
+                Ok(expr) => {
+                    for name in expr.names() {
+                        if !known.contains(name) {
+                            report(
+                                Level::Warning,
+                                index,
+                                "roles",
+                                format!(
+                                    "Role `{}` is not defined in [roles] or assigned to any registered machine",
+                                    name
+                                ),
+                            );
+                        }
+                    }
+                }
+                Err(e) => report(
+                    Level::Error,
+                    index,
+                    "roles",
+                    format!("Invalid role expression `{}`: {}", source, e),
+                ),
+            }
+        }
+
+        let repo_secret = crate::core::secret::backend::is_repo_secret(file, &config.secrets);
+        if repo_secret && !file.source.ends_with(".age") {
+            report(
+                Level::Error,
+                index,
+                "source",
+                format!(
+                    "Secret source `{}` is not an encrypted `.age` file",
+                    file.source
+                ),
+            );
+        }
+        if repo_source(file, config)
+            && file.source_in_repo()
+            && !config_dir.join(&file.source).exists()
+        {
+            report(
+                Level::Warning,
+                index,
+                "source",
+                format!("Source `{}` does not exist in the repository", file.source),
+            );
+        }
+        if file.file_type == FileType::Directory
+            && file.source_in_repo()
+            && config_dir.join(&file.source).is_file()
+        {
+            report(
+                Level::Error,
+                index,
+                "type",
+                format!(
+                    "Entry has `type = \"directory\"` but source `{}` is a file",
+                    file.source
+                ),
+            );
+        }
+
+        for (other_index, other) in config.files[..index].iter().enumerate() {
+            // Entries no single machine can match never collide.
+            if !platforms_overlap(file, other)
+                || !roles::can_overlap(file.roles.as_deref(), other.roles.as_deref(), &config.roles)
+            {
+                continue;
+            }
+            let (dest, other_dest) = (destination(file), destination(other));
+            if dest == other_dest {
+                report(
+                    Level::Error,
+                    index,
+                    "destination",
+                    format!(
+                        "Destination `{}` is already used by the entry at line {}",
+                        file.destination,
+                        line_of(other_index)
+                    ),
+                );
+            } else if is_directory(other) && dest.starts_with(&other_dest) {
+                report(
+                    Level::Error,
+                    index,
+                    "destination",
+                    format!(
+                        "Destination `{}` is inside `{}`, which the entry at line {} tracks as a directory",
+                        file.destination,
+                        other.destination,
+                        line_of(other_index)
+                    ),
+                );
+            } else if is_directory(file) && other_dest.starts_with(&dest) {
+                report(
+                    Level::Error,
+                    index,
+                    "destination",
+                    format!(
+                        "Directory `{}` contains `{}`, which the entry at line {} tracks separately",
+                        file.destination,
+                        other.destination,
+                        line_of(other_index)
+                    ),
+                );
+            }
+            if repo_source(file, config) && file.source == other.source {
+                report(
+                    Level::Warning,
+                    index,
+                    "source",
+                    format!(
+                        "Source `{}` is also used by the entry at line {}",
+                        file.source,
+                        line_of(other_index)
+                    ),
+                );
+            }
+        }
+    }
+
+    problems
+}
+
+fn destination(file: &FileConfig) -> PathBuf {
+    PathBuf::from(shellexpand::tilde(&file.destination).into_owned())
+        .components()
+        .collect()
+}
+
+fn is_directory(file: &FileConfig) -> bool {
+    matches!(file.file_type, FileType::Directory | FileType::SecretDir)
+}
+
+/// External secrets' sources are backend paths, which may legitimately repeat.
+fn repo_source(file: &FileConfig, config: &TeamConfig) -> bool {
+    !file.file_type.is_secret()
+        || crate::core::secret::backend::is_repo_secret(file, &config.secrets)
+}
+
+/// Whether some machine could match both entries' `platforms`.
+fn platforms_overlap(a: &FileConfig, b: &FileConfig) -> bool {
+    let any = |f: &FileConfig| f.platforms.is_empty() || f.platforms.iter().any(|p| p == "*");
+    if any(a) || any(b) {
+        return true;
+    }
+    a.platforms.iter().any(|p| {
+        b.platforms
+            .iter()
+            .any(|q| p == q || (p == "unix" && q != "windows") || (q == "unix" && p != "windows"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str =
+        "[team]\nname = \"t\"\nmaintainers = []\n[repository]\nurl = \"\"\nbranch = \"main\"\n";
+
+    /// Validates `HEADER` followed by `files` against a repository holding `sources`.
+    fn check(files: &str, sources: &[&str]) -> Vec<Problem> {
+        let repo = tempfile::tempdir().expect("temp dir");
+        for source in sources {
+            std::fs::write(repo.path().join(source), "").expect("write source");
+        }
+        let content = format!("{}{}", HEADER, files);
+        let config: TeamConfig = toml::from_str(&content).expect("valid config");
+        validate(&config, &content, repo.path(), &BTreeSet::new())
+    }
+
+    fn entry(source: &str, destination: &str, extra: &str) -> String {
+        format!(
+            "[[files]]\nsource = \"{}\"\ndestination = \"{}\"\ntype = \"file\"\n{}",
+            source, destination, extra
+        )
+    }
+
+    #[test]
+    fn clean_config_has_no_problems() {
+        let files = entry(
+            "bashrc",
+            "~/.bashrc",
+            "roles = [\"work\"]\nplatforms = [\"linux\"]\n",
+        );
+        let problems = check(&format!("[roles]\nwork = []\n{}", files), &["bashrc"]);
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[test]
+    fn unknown_role_is_a_warning() {
+        let problems = check(
+            &entry("bashrc", "~/.bashrc", "roles = [\"wrk\"]\n"),
+            &["bashrc"],
+        );
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        let problem = &problems[0];
+        assert_eq!((problem.level, problem.key), (Level::Warning, "roles"));
+        assert!(problem.message.contains("Role `wrk` is not defined"));
+        assert_eq!(problem.location, Some((11, 9)));
+
+        let problems = check(
+            &format!(
+                "[roles]\nwork = []\n{}",
+                entry("bashrc", "~/.bashrc", "roles = [\"work && !wrk\"]\n")
+            ),
+            &["bashrc"],
+        );
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].message.contains("Role `wrk` is not defined"));
+    }
+
+    #[test]
+    fn duplicate_destination_is_an_error() {
+        let files = entry("a", "~/.bashrc", "") + &entry("b", "~/./.bashrc", "");
+        let problems = check(&files, &["a", "b"]);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        let problem = &problems[0];
+        assert!(problem.is_error());
+        assert_eq!(problem.key, "destination");
+        assert_eq!(
+            problem.message,
+            "Destination `~/./.bashrc` is already used by the entry at line 9"
+        );
+        assert_eq!(problem.location, Some((13, 15)));
+
+        // Entries no single machine can match do not conflict.
+        let files = entry(
+            "a",
+            "~/.bashrc",
+            "platforms = [\"linux\"]\nroles = [\"work\"]\n",
+        ) + &entry("b", "~/.bashrc", "roles = [\"!work\"]\n")
+            + &entry(
+                "c",
+                "~/.bashrc",
+                "platforms = [\"windows\"]\nroles = [\"work\"]\n",
+            )
+            + &entry("d", "~/.build", "roles = [\"ci\"]\n")
+            + &entry("e", "~/.build", "roles = [\"!build\"]\n");
+        let problems = check(
+            &format!("[roles]\nwork = []\nci = [\"build\"]\n{}", files),
+            &["a", "b", "c", "d", "e"],
+        );
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[test]
+    fn entries_that_can_apply_together_conflict() {
+        let destinations = |problems: &[Problem]| -> Vec<Option<(usize, usize)>> {
+            problems
+                .iter()
+                .filter(|p| p.key == "destination")
+                .map(|p| p.location)
+                .collect()
+        };
+
+        // An entry without roles applies everywhere.
+        let files =
+            entry("a", "~/.bashrc", "") + &entry("b", "~/.bashrc", "roles = [\"work && !ci\"]\n");
+        let problems = check(
+            &format!("[roles]\nwork = []\nci = []\n{}", files),
+            &["a", "b"],
+        );
+        assert_eq!(destinations(&problems), [Some((16, 15))]);
+
+        // A machine may hold both roles.
+        let files = entry("a", "~/.bashrc", "roles = [\"work\"]\n")
+            + &entry("b", "~/.bashrc", "roles = [\"home\"]\n");
+        let problems = check(
+            &format!("[roles]\nwork = []\nhome = []\n{}", files),
+            &["a", "b"],
+        );
+        assert_eq!(destinations(&problems), [Some((17, 15))]);
+
+        // Nested destinations follow the same rule.
+        let files = "[[files]]\nsource = \"nvim\"\ndestination = \"~/.config/nvim\"\ntype = \"directory\"\nroles = [\"work\"]\n".to_string()
+            + &entry("a", "~/.config/nvim/init.lua", "roles = [\"!work\"]\n")
+            + &entry("b", "~/.config/nvim/lazy.lua", "");
+        let problems = check(&format!("[roles]\nwork = []\n{}", files), &["a", "b"]);
+        let nested: Vec<&str> = problems
+            .iter()
+            .filter(|p| p.key == "destination")
+            .map(|p| p.message.as_str())
+            .collect();
+        assert_eq!(
+            nested,
+            ["Destination `~/.config/nvim/lazy.lua` is inside `~/.config/nvim`, which the entry at line 11 tracks as a directory"]
+        );
+    }
+
+    #[test]
+    fn invalid_role_expression_is_an_error() {
+        let problems = check(
+            &entry("bashrc", "~/.bashrc", "roles = [\"work && (ci\"]\n"),
+            &["bashrc"],
+        );
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].is_error());
+        assert_eq!(
+            problems[0].message,
+            "Invalid role expression `work && (ci`: missing `)`"
+        );
+    }
+
+    #[test]
+    fn unknown_platform_is_a_warning() {
+        let problems = check(
+            &entry("bashrc", "~/.bashrc", "platforms = [\"linux\", \"mac\"]\n"),
+            &["bashrc"],
+        );
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        let problem = &problems[0];
+        assert_eq!((problem.level, problem.key), (Level::Warning, "platforms"));
+        assert!(problem.message.starts_with("Unknown platform `mac`"));
+        assert_eq!(problem.location, Some((11, 13)));
+    }
+
+    #[test]
+    fn missing_source_is_a_warning() {
+        let files = entry("bashrc", "~/.bashrc", "") + &entry("vimrc", "~/.vimrc", "");
+        let problems = check(&files, &["bashrc"]);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        let problem = &problems[0];
+        assert_eq!((problem.level, problem.key), (Level::Warning, "source"));
+        assert_eq!(
+            problem.message,
+            "Source `vimrc` does not exist in the repository"
+        );
+        assert_eq!(problem.location, Some((12, 10)));
+
+        // External secrets name a backend path, not a repository file.
+        let external = "[[files]]\nsource = \"kv/token\"\ndestination = \"~/.token\"\ntype = \"secret\"\nbackend = \"vault\"\n";
+        assert!(check(external, &[]).is_empty());
+    }
+
+    #[test]
+    fn invalid_role_names_are_reported_at_their_key() {
+        let problems = check("[roles]\n\"!ci\" = [\"a&&b\"]\n", &[]);
+        let messages: Vec<&str> = problems.iter().map(|p| p.message.as_str()).collect();
+        assert_eq!(problems.len(), 2, "{:?}", messages);
+        assert!(messages[0].starts_with("Invalid role name `!ci`"));
+        assert!(messages[1].starts_with("Invalid role name `a&&b`"));
+        assert!(problems.iter().all(|p| p.location == Some((8, 1))));
+    }
+}
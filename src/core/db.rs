@@ -0,0 +1,262 @@
+use crate::core::state::LocalState;
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use rusqlite::{params, Connection, OptionalExtension};
+use rusqlite_migration::{Migrations, M};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Schema history. Append new migrations; never edit released ones.
+fn migrations() -> Migrations<'static> {
+    Migrations::new(vec![
+        M::up(
+            "CREATE TABLE roles (
+            name TEXT PRIMARY KEY NOT NULL
+        );
+        CREATE TABLE machine (
+            key TEXT PRIMARY KEY NOT NULL,
+            value TEXT NOT NULL
+        );
+        CREATE TABLE applied (
+            destination TEXT PRIMARY KEY NOT NULL,
+            source TEXT NOT NULL,
+            file_type TEXT NOT NULL,
+            content_hash TEXT,
+            applied_at TEXT NOT NULL,
+            commit_id TEXT
+        );",
+        ),
+        // Lets prune honour `protect` after the entry is gone from the config.
+        M::up("ALTER TABLE applied ADD COLUMN protect INTEGER NOT NULL DEFAULT 0;"),
+    ])
+}
+
+/// One tracked entry as it was last written to this machine.
+#[derive(Debug, Clone)]
+pub struct AppliedEntry {
+    pub destination: String,
+    pub source: String,
+    pub file_type: String,
+    /// SHA-256 of what was written (the repo file for links, plaintext for
+    /// secrets, rendered output for templates). `None` for directories.
+    pub content_hash: Option<String>,
+    pub applied_at: String,
+    pub commit_id: Option<String>,
+    /// The entry's `protect` flag when it was applied.
+    pub protect: bool,
+}
+
+/// Machine-local state (roles, applied entries, machine metadata) in
+/// `<data_local_dir>/state.db`. Never synced.
+pub struct StateDb {
+    conn: Connection,
+}
+
+impl StateDb {
+    pub fn open() -> Result<Self> {
+        let path = Self::get_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create local state directory")?;
+        }
+        let db = Self::open_at(&path)?;
+        db.import_legacy_state(&path.with_file_name("state.toml"))?;
+        Ok(db)
+    }
+
+    pub fn open_at(path: &Path) -> Result<Self> {
+        let mut conn = Connection::open(path)
+            .with_context(|| format!("Failed to open state database {:?}", path))?;
+        migrations()
+            .to_latest(&mut conn)
+            .context("Failed to migrate state database")?;
+        Ok(Self { conn })
+    }
+
+    pub fn get_path() -> Result<PathBuf> {
+        let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+            .context("Could not determine project directories")?;
+        Ok(proj_dirs.data_local_dir().join("state.db"))
+    }
+
+    /// Current schema version (number of migrations applied).
+    pub fn schema_version(&self) -> Result<i64> {
+        Ok(self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))?)
+    }
+
+    /// Moves roles from the old `state.toml` into the database, once.
+    fn import_legacy_state(&self, legacy: &Path) -> Result<()> {
+        if !legacy.exists() {
+            return Ok(());
+        }
+        let content = fs::read_to_string(legacy).context("Failed to read local state file")?;
+        let state: LocalState =
+            toml::from_str(&content).context("Failed to parse local state file")?;
+        let mut roles = self.roles()?;
+        for role in state.roles {
+            if !roles.contains(&role) {
+                roles.push(role);
+            }
+        }
+        self.set_roles(&roles)?;
+        let migrated = legacy.with_extension("toml.migrated");
+        fs::rename(legacy, &migrated).context("Failed to retire local state file")?;
+        eprintln!("Migrated local state to {:?}", Self::get_path()?);
+        Ok(())
+    }
+
+    pub fn roles(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT name FROM roles ORDER BY rowid")?;
+        let roles = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(roles)
+    }
+
+    /// Replaces the machine's roles, keeping their order.
+    pub fn set_roles(&self, roles: &[String]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM roles", [])?;
+        for role in roles {
+            tx.execute("INSERT OR IGNORE INTO roles (name) VALUES (?1)", [role])?;
+        }
+        tx.commit().context("Failed to save roles")?;
+        Ok(())
+    }
+
+    pub fn record_applied(&self, entry: &AppliedEntry) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO applied
+                    (destination, source, file_type, content_hash, applied_at, commit_id, protect)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    entry.destination,
+                    entry.source,
+                    entry.file_type,
+                    entry.content_hash,
+                    entry.applied_at,
+                    entry.commit_id,
+                    entry.protect
+                ],
+            )
+            .context("Failed to record applied entry")?;
+        Ok(())
+    }
+
+    pub fn applied(&self, destination: &str) -> Result<Option<AppliedEntry>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT destination, source, file_type, content_hash, applied_at, commit_id, protect
+                 FROM applied WHERE destination = ?1",
+                [destination],
+                applied_from_row,
+            )
+            .optional()?)
+    }
+
+    pub fn applied_entries(&self) -> Result<Vec<AppliedEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT destination, source, file_type, content_hash, applied_at, commit_id, protect
+             FROM applied ORDER BY destination",
+        )?;
+        let entries = stmt
+            .query_map([], applied_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    pub fn forget_applied(&self, destination: &str) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM applied WHERE destination = ?1", [destination])
+            .context("Failed to update applied entries")?;
+        Ok(())
+    }
+
+    pub fn machine(&self) -> Result<BTreeMap<String, String>> {
+        let mut stmt = self.conn.prepare("SELECT key, value FROM machine")?;
+        let values = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<BTreeMap<String, String>>>()?;
+        Ok(values)
+    }
+
+    pub fn machine_value(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row("SELECT value FROM machine WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()?)
+    }
+
+    pub fn set_machine_value(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO machine (key, value) VALUES (?1, ?2)",
+            [key, value],
+        )?;
+        Ok(())
+    }
+
+    /// Stores host details, and a stable id the first time. Only `init`, `push`
+    /// and `apply` call this, so read-only commands never write the database.
+    pub fn record_machine(&self) -> Result<()> {
+        let hostname = hostname();
+        if self.machine_value("machine_id")?.is_none() {
+            let seed = format!("{}:{}", hostname, chrono::Utc::now().to_rfc3339());
+            let id = format!("{:x}", Sha256::digest(seed.as_bytes()));
+            self.set_machine_value("machine_id", &id[..16])?;
+            self.set_machine_value("created_at", &now())?;
+        }
+        self.set_machine_value("hostname", &hostname)?;
+        self.set_machine_value("os", std::env::consts::OS)?;
+        self.set_machine_value("arch", std::env::consts::ARCH)?;
+        self.set_machine_value("configsync_version", env!("CARGO_PKG_VERSION"))?;
+        Ok(())
+    }
+}
+
+fn applied_from_row(row: &rusqlite::Row) -> rusqlite::Result<AppliedEntry> {
+    Ok(AppliedEntry {
+        destination: row.get(0)?,
+        source: row.get(1)?,
+        file_type: row.get(2)?,
+        content_hash: row.get(3)?,
+        applied_at: row.get(4)?,
+        commit_id: row.get(5)?,
+        protect: row.get(6)?,
+    })
+}
+
+/// RFC 3339 timestamp used for everything stored in the database.
+pub fn now() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Hex SHA-256 of `data`, as stored in `applied.content_hash`.
+pub fn content_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+pub fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        // SAFETY: the buffer is valid for its full length.
+        if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } == 0 {
+            let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+            if let Ok(name) = std::str::from_utf8(&buf[..len]) {
+                if !name.is_empty() {
+                    return name.to_string();
+                }
+            }
+        }
+    }
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
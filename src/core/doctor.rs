@@ -1,14 +1,292 @@
 use crate::core::config::loader::ConfigLoader;
-use crate::core::config::schema::FileType;
+use crate::core::config::schema::{FileConfig, FileType, TeamConfig};
+use crate::core::db::{AppliedEntry, StateDb};
+use crate::core::engine::apply::Applier;
+use crate::core::engine::prune::{self, Stale};
+use crate::core::fs::{perms, symlink};
+use crate::core::git::repository::GitRepository;
+use crate::core::machines::MACHINES_DIR;
+use crate::core::roles::RoleResolver;
+use crate::core::secret::backend::Backends;
+use crate::core::secret::expiry;
+use crate::core::secret::values::{Values, VALUES_SOURCE};
 use crate::core::state::LocalState;
 use anyhow::{Context, Result};
 use directories::ProjectDirs;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::fs;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
 
-pub fn check() -> Result<()> {
-    println!("🩺 ConfigSync Doctor\n");
-    let mut issues_found = false;
+/// Checks that can be selected with `--check`, in report order. `config`
+/// always runs, since every other check needs the loaded config.
+pub const CHECKS: &[&str] = &[
+    "config",
+    "git",
+    "sources",
+    "roles",
+    "files",
+    "permissions",
+    "secrets",
+    "state",
+    "key",
+    "expiry",
+];
 
+pub struct Options {
+    /// Decrypt secrets and render templates to compare them with their destinations.
+    pub secrets: bool,
+    pub fix: bool,
+    pub dry_run: bool,
+    pub json: bool,
+    /// Only run these checks; all of them (except `secrets`) when empty.
+    pub checks: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Ok,
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn icon(self) -> &'static str {
+        match self {
+            Severity::Ok => "✅",
+            Severity::Info => "ℹ️",
+            Severity::Warning => "⚠️",
+            Severity::Error => "❌",
+        }
+    }
+}
+
+/// One line of the doctor report.
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    /// One of `CHECKS`.
+    pub check: &'static str,
+    pub severity: Severity,
+    /// The file or directory the finding is about.
+    pub subject: Option<String>,
+    pub message: String,
+    /// What resolves it, when doctor knows.
+    pub fix: Option<String>,
+    /// Whether `doctor --fix` makes that fix.
+    pub repairable: bool,
+    /// Index of the queued repair.
+    #[serde(skip)]
+    repair: Option<usize>,
+}
+
+impl Finding {
+    fn new(check: &'static str, severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            severity,
+            subject: None,
+            message: message.into(),
+            fix: None,
+            repairable: false,
+            repair: None,
+        }
+    }
+
+    fn about(mut self, subject: impl AsRef<Path>) -> Self {
+        self.subject = Some(subject.as_ref().to_string_lossy().into_owned());
+        self
+    }
+
+    fn suggest(mut self, fix: impl Into<String>) -> Self {
+        self.fix = Some(fix.into());
+        self
+    }
+}
+
+/// Findings from the selected checks, printed as they are made unless the
+/// report is going out as JSON.
+struct Report {
+    json: bool,
+    checks: Vec<String>,
+    findings: Vec<Finding>,
+    fixes: Vec<Fix>,
+}
+
+impl Report {
+    fn selected(&self, check: &str) -> bool {
+        check == "config" || self.checks.is_empty() || self.checks.iter().any(|c| c == check)
+    }
+
+    fn section(&self, check: &str, title: &str) {
+        if !self.json && self.selected(check) {
+            println!("\n{}", title);
+        }
+    }
+
+    fn add(&mut self, finding: Finding) {
+        if !self.selected(finding.check) {
+            return;
+        }
+        if !self.json {
+            println!("{} {}", finding.severity.icon(), finding.message);
+            // Repairs are summed up at the end instead.
+            if let Some(fix) = finding.fix.as_deref().filter(|_| !finding.repairable) {
+                println!("   ↳ {}", fix);
+            }
+        }
+        self.findings.push(finding);
+    }
+
+    /// Adds a finding that `--fix` can repair with `fix`.
+    fn repair(&mut self, mut finding: Finding, fix: Fix, config: &TeamConfig) {
+        if !self.selected(finding.check) {
+            return;
+        }
+        finding.fix = Some(fix.describe(config));
+        finding.repairable = true;
+        finding.repair = Some(self.fixes.len());
+        self.fixes.push(fix);
+        self.add(finding);
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|f| f.severity == severity)
+            .count()
+    }
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+    ok: bool,
+    errors: usize,
+    warnings: usize,
+    findings: &'a [Finding],
+}
+
+/// A repair `doctor --fix` makes without touching anything the user changed.
+enum Fix {
+    /// Re-apply an entry whose destination is missing (or a template that is a symlink).
+    Restore(usize),
+    /// Replace a link that points at a missing file or another repository path.
+    Relink(usize),
+    /// Restore the recorded mode, owner and group of a decrypted copy.
+    Permissions(usize),
+    /// Reset the key file to 600.
+    KeyPermissions(std::path::PathBuf),
+    /// Remove a link or copy configsync wrote for an entry that no longer applies.
+    RemoveStale(AppliedEntry),
+}
+
+impl Fix {
+    fn describe(&self, config: &TeamConfig) -> String {
+        match self {
+            Fix::Restore(i) => format!("restore {}", config.files[*i].destination),
+            Fix::Relink(i) => format!(
+                "relink {} -> {}",
+                config.files[*i].destination, config.files[*i].source
+            ),
+            Fix::Permissions(i) => format!("reset permissions of {}", config.files[*i].destination),
+            Fix::KeyPermissions(path) => format!("chmod 600 {:?}", path),
+            Fix::RemoveStale(entry) => format!("remove {} (no longer applies)", entry.destination),
+        }
+    }
+}
+
+/// What the checks loaded, for `--fix`.
+struct Loaded {
+    config: TeamConfig,
+    config_dir: PathBuf,
+    db: Option<StateDb>,
+}
+
+/// Runs the selected checks and prints the report. Returns whether it is free
+/// of errors (after repairs, with `--fix`).
+pub fn check(options: &Options) -> Result<bool> {
+    if let Some(unknown) = options
+        .checks
+        .iter()
+        .find(|c| !CHECKS.contains(&c.as_str()))
+    {
+        anyhow::bail!(
+            "Unknown check '{}'. Available checks: {}",
+            unknown,
+            CHECKS.join(", ")
+        );
+    }
+
+    if !options.json {
+        println!("🩺 ConfigSync Doctor\n");
+    }
+    let mut report = Report {
+        json: options.json,
+        checks: options.checks.clone(),
+        findings: Vec::new(),
+        fixes: Vec::new(),
+    };
+    let deep = options.secrets || options.checks.iter().any(|c| c == "secrets");
+    let loaded = diagnose(&mut report, deep)?;
+
+    // Errors whose repair succeeded no longer count.
+    let mut repaired = vec![false; report.fixes.len()];
+    if !options.json {
+        println!("\n----------------------------------------");
+    }
+    if options.fix {
+        if let Some(loaded) = &loaded {
+            repaired = run_fixes(
+                &report.fixes,
+                &loaded.config,
+                &loaded.config_dir,
+                loaded.db.as_ref(),
+                options.dry_run,
+            );
+        }
+    }
+    let errors = report
+        .findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .filter(|f| !f.repair.is_some_and(|i| repaired[i]))
+        .count();
+
+    if options.json {
+        let summary = Summary {
+            ok: errors == 0,
+            errors,
+            warnings: report.count(Severity::Warning),
+            findings: &report.findings,
+        };
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&summary).context("Failed to serialize report")?
+        );
+    } else if report.count(Severity::Error) + report.count(Severity::Warning) > 0 {
+        println!("⚠️ Issues found. Please review the output above.");
+        if options.fix && !options.dry_run {
+            println!("Re-run `configsync doctor` to see what is left.");
+        } else if !report.fixes.is_empty() && !options.fix {
+            println!(
+                "{} can be repaired automatically: run `configsync doctor --fix` (add --dry-run to preview).",
+                report.fixes.len()
+            );
+        } else {
+            println!("Try running `configsync apply` to fix missing links/files.");
+        }
+    } else {
+        println!("✅ All systems operational.");
+    }
+
+    Ok(errors == 0)
+}
+
+/// Makes every finding. Returns `None` when the config cannot be loaded and
+/// nothing past the `config` check could run.
+fn diagnose(report: &mut Report, check_secrets: bool) -> Result<Option<Loaded>> {
     // 1. Check Config Directory & File
     let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
         .context("Could not determine project directories")?;
@@ -16,84 +294,297 @@ pub fn check() -> Result<()> {
     let config_path = config_dir.join("team-config.toml");
 
     if !config_dir.exists() {
-        println!("❌ Config directory missing: {:?}", config_dir);
-        return Ok(());
-    } else {
-        println!("✅ Config directory exists: {:?}", config_dir);
+        report.add(
+            Finding::new(
+                "config",
+                Severity::Error,
+                format!("Config directory missing: {:?}", config_dir),
+            )
+            .about(config_dir)
+            .suggest("run `configsync init`"),
+        );
+        return Ok(None);
     }
+    report.add(
+        Finding::new(
+            "config",
+            Severity::Ok,
+            format!("Config directory exists: {:?}", config_dir),
+        )
+        .about(config_dir),
+    );
 
     if !config_path.exists() {
-        println!("❌ Config file missing: {:?}", config_path);
-        return Ok(());
-    } else {
-        println!("✅ Config file exists");
+        report.add(
+            Finding::new(
+                "config",
+                Severity::Error,
+                format!("Config file missing: {:?}", config_path),
+            )
+            .about(&config_path)
+            .suggest("run `configsync init`"),
+        );
+        return Ok(None);
     }
+    report.add(Finding::new("config", Severity::Ok, "Config file exists").about(&config_path));
 
-    // 2. Parsability Check
-    let config = match ConfigLoader::load(&config_path) {
-        Ok(c) => c,
+    // 2. Parsability and semantic validation
+    let (config, problems) = match ConfigLoader::load_with_problems(&config_path) {
+        Ok(loaded) => loaded,
         Err(e) => {
-            println!("❌ Config file invalid: {}", e);
-            return Ok(());
+            report.add(
+                Finding::new(
+                    "config",
+                    Severity::Error,
+                    format!("Config file invalid: {:#}", e),
+                )
+                .about(&config_path),
+            );
+            return Ok(None);
         }
     };
-    println!("✅ Config file passes validation");
+    if problems.is_empty() {
+        report.add(Finding::new(
+            "config",
+            Severity::Ok,
+            "Config file passes validation",
+        ));
+    }
+    for problem in &problems {
+        report.add(
+            Finding::new(
+                if problem.key == "roles" {
+                    "roles"
+                } else {
+                    "config"
+                },
+                if problem.is_error() {
+                    Severity::Error
+                } else {
+                    Severity::Warning
+                },
+                problem.located(&config_path),
+            )
+            .about(&config_path),
+        );
+    }
 
     // 3. Git Repo Status
-    // We can use our GitRepository wrapper or direct git2 check.
-    // Let's use GitRepository::open if possible, or build a simple check here.
-    match crate::core::git::repository::GitRepository::open(config_dir) {
-        Ok(_) => println!("✅ Git repository valid"),
+    let repo = match GitRepository::open(config_dir) {
+        Ok(repo) => {
+            report.add(Finding::new("git", Severity::Ok, "Git repository valid"));
+            check_repository(report, &repo, config_dir);
+            Some(repo)
+        }
         Err(e) => {
-            println!("❌ Git repository issue: {}", e);
-            issues_found = true;
+            report.add(
+                Finding::new(
+                    "git",
+                    Severity::Error,
+                    format!("Git repository issue: {}", e),
+                )
+                .about(config_dir),
+            );
+            None
         }
-    }
+    };
+    check_sources(report, &config, config_dir, repo.as_ref());
 
     // 4. File Symlink Checks
-    println!("\nChecking {} tracked files...", config.files.len());
-    let state = LocalState::load().unwrap_or_default();
-
-    for file in &config.files {
-        // Role check
-        if let Some(ref required_roles) = file.roles {
-            if !required_roles.is_empty() {
-                let has_role = required_roles.iter().any(|r| state.has_role(r));
-                if !has_role {
-                    // Skip checking files not meant for this machine role, unless we want to be strict?
-                    // Skipping is better, otherwise Doctor complains about missing files intended for other roles.
-                    continue;
-                }
-            }
+    if ["files", "permissions", "secrets"]
+        .iter()
+        .any(|c| report.selected(c))
+        && !report.json
+    {
+        println!("\nChecking {} tracked files...", config.files.len());
+    }
+    let db = match StateDb::open() {
+        Ok(db) => Some(db),
+        Err(e) => {
+            report.add(Finding::new(
+                "state",
+                Severity::Error,
+                format!("Local state database unavailable: {:#}", e),
+            ));
+            None
+        }
+    };
+    let state = db
+        .as_ref()
+        .and_then(|db| LocalState::from_db(db).ok())
+        .unwrap_or_default();
+    let resolver = RoleResolver::for_machine(&state, &config);
+    let mut backends = Backends::new(config_dir, &config.secrets);
+    let mut values = None;
+
+    for (index, file) in config.files.iter().enumerate() {
+        // Skip files not meant for this machine's roles or platform, otherwise
+        // Doctor complains about missing files intended for other machines.
+        if !resolver.matches(file) {
+            continue;
         }
 
         let source_path = config_dir.join(&file.source);
         let expanded_dest = shellexpand::tilde(&file.destination);
         let dest_path = std::path::PathBuf::from(expanded_dest.into_owned());
 
-        if !source_path.exists() {
-            println!("❌ Source missing in repo: {:?}", source_path);
-            issues_found = true;
+        let is_external_secret = file.file_type.is_secret()
+            && !crate::core::secret::backend::is_repo_secret(file, &config.secrets);
+
+        if !is_external_secret && !source_path.exists() {
+            report.add(
+                Finding::new(
+                    "files",
+                    Severity::Error,
+                    format!("Source missing in repo: {:?}", source_path),
+                )
+                .about(&source_path)
+                .suggest("restore it in the repository or remove the entry"),
+            );
             continue;
         }
 
         if !dest_path.exists() {
-            println!("❌ Destination missing: {:?}", dest_path);
-            issues_found = true;
+            // A dangling link at the destination has to go before apply can relink.
+            let dangling = fs::symlink_metadata(&dest_path)
+                .map(|m| m.file_type().is_symlink())
+                .unwrap_or(false);
+            report.repair(
+                Finding::new(
+                    "files",
+                    Severity::Error,
+                    format!("Destination missing: {:?}", dest_path),
+                )
+                .about(&dest_path),
+                if dangling {
+                    Fix::Relink(index)
+                } else {
+                    Fix::Restore(index)
+                },
+                &config,
+            );
             continue;
         }
 
+        // Copies edited in place since the last apply, detected without decrypting.
+        if !check_secrets && matches!(file.file_type, FileType::Secret | FileType::Template) {
+            if let Some(db) = &db {
+                if let Some(hash) = db
+                    .applied(&dest_path.to_string_lossy())
+                    .ok()
+                    .flatten()
+                    .and_then(|entry| entry.content_hash)
+                {
+                    if let Ok(local) = fs::read(&dest_path).map(Zeroizing::new) {
+                        if crate::core::db::content_hash(&local) != hash {
+                            report.add(
+                                Finding::new(
+                                    "files",
+                                    Severity::Warning,
+                                    format!(
+                                        "{} {:?} has changed since it was last applied",
+                                        file.file_type.label(),
+                                        dest_path
+                                    ),
+                                )
+                                .about(&dest_path)
+                                .suggest(if file.file_type == FileType::Template {
+                                    "run `configsync apply` to restore it, or edit the template in the repository"
+                                } else {
+                                    "run `configsync apply` to restore it, or store the change with `configsync secrets edit`"
+                                }),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        // Decrypted secrets and rendered templates keep their recorded mode, owner and group.
+        if file.file_type.is_copy() {
+            match perms::check(&dest_path, file) {
+                Ok(problems) => {
+                    for problem in &problems {
+                        report.repair(
+                            Finding::new(
+                                "permissions",
+                                Severity::Warning,
+                                format!("{} {:?} {}", file.file_type.label(), dest_path, problem),
+                            )
+                            .about(&dest_path),
+                            Fix::Permissions(index),
+                            &config,
+                        );
+                    }
+                }
+                Err(e) => report.add(
+                    Finding::new(
+                        "permissions",
+                        Severity::Error,
+                        format!("Failed to check permissions of {:?}: {:#}", dest_path, e),
+                    )
+                    .about(&dest_path),
+                ),
+            }
+        }
+
         // Check if it's a symlink (or junction on Windows) or file type mismatch
         // For Secret types, it's a file copy, not a symlink.
         match file.file_type {
+            FileType::SecretDir => {
+                if !dest_path.is_dir() {
+                    report.add(
+                        Finding::new(
+                            "files",
+                            Severity::Warning,
+                            format!(
+                                "Destination {:?} should be a directory (Secret directory)",
+                                dest_path
+                            ),
+                        )
+                        .about(&dest_path),
+                    );
+                } else if check_secrets {
+                    check_secret_dir(report, file, &dest_path, &mut backends);
+                }
+            }
+            FileType::Template => {
+                let is_symlink = fs::symlink_metadata(&dest_path)
+                    .map(|m| m.file_type().is_symlink())
+                    .unwrap_or(false);
+                if is_symlink || !dest_path.is_file() {
+                    let finding = Finding::new(
+                        "files",
+                        Severity::Warning,
+                        format!(
+                            "Destination {:?} should be a rendered file (Template)",
+                            dest_path
+                        ),
+                    )
+                    .about(&dest_path);
+                    // Apply replaces a symlink; anything else is the user's.
+                    if is_symlink {
+                        report.repair(finding, Fix::Restore(index), &config);
+                    } else {
+                        report.add(finding);
+                    }
+                } else if check_secrets {
+                    check_template(report, config_dir, file, &dest_path, &mut values);
+                }
+            }
             FileType::Secret => {
                 // Secrets are files, not symlinks.
                 if !dest_path.is_file() {
-                    println!("⚠️ Destination {:?} should be a file (Secret)", dest_path);
-                    issues_found = true;
-                } else {
-                    // Maybe check if content matches decrypted? Too expensive/risky for doctor?
-                    // Just existence is okay for now.
+                    report.add(
+                        Finding::new(
+                            "files",
+                            Severity::Warning,
+                            format!("Destination {:?} should be a file (Secret)", dest_path),
+                        )
+                        .about(&dest_path),
+                    );
+                } else if check_secrets {
+                    check_secret(report, file, &dest_path, &mut backends);
                 }
             }
             _ => {
@@ -101,90 +592,797 @@ pub fn check() -> Result<()> {
                 match fs::symlink_metadata(&dest_path) {
                     Ok(metadata) => {
                         if !metadata.file_type().is_symlink() {
-                            println!(
-                                "❌ Destination {:?} is NOT a symlink (Expected symlink)",
-                                dest_path
+                            report.add(
+                                Finding::new(
+                                    "files",
+                                    Severity::Error,
+                                    format!(
+                                        "Destination {:?} is NOT a symlink (Expected symlink)",
+                                        dest_path
+                                    ),
+                                )
+                                .about(&dest_path)
+                                .suggest("move the file away and run `configsync apply`"),
                             );
-                            issues_found = true;
                         } else {
                             // Check target
                             match fs::read_link(&dest_path) {
                                 Ok(target) => {
-                                    if target != source_path {
-                                        println!(
-                                            "⚠️ Symlink {:?} points to {:?}, expected {:?}",
-                                            dest_path, target, source_path
-                                        );
-                                        // This might be okay if it's relative?
-                                        // Canonicalize both?
-                                        if let (Ok(p1), Ok(p2)) = (
+                                    // A different but equivalent path (relative, or
+                                    // through another link) is fine.
+                                    let wrong = target != source_path
+                                        && match (
                                             fs::canonicalize(&target),
                                             fs::canonicalize(&source_path),
                                         ) {
-                                            if p1 != p2 {
-                                                issues_found = true;
-                                            }
+                                            (Ok(p1), Ok(p2)) => p1 != p2,
+                                            _ => true,
+                                        };
+                                    if wrong {
+                                        let finding = Finding::new(
+                                            "files",
+                                            Severity::Warning,
+                                            format!(
+                                                "Symlink {:?} points to {:?}, expected {:?}",
+                                                dest_path, target, source_path
+                                            ),
+                                        )
+                                        .about(&dest_path);
+                                        // Only links into the repository (or to nothing)
+                                        // are ours to move.
+                                        if !target.exists() || target.starts_with(config_dir) {
+                                            report.repair(finding, Fix::Relink(index), &config);
                                         } else {
-                                            issues_found = true;
+                                            report.add(finding.suggest(
+                                                "it points outside the repository; move it away and run `configsync apply`",
+                                            ));
                                         }
                                     }
                                 }
-                                Err(_) => {
-                                    println!("❌ Failed to read link target for {:?}", dest_path);
-                                    issues_found = true;
-                                }
+                                Err(_) => report.add(
+                                    Finding::new(
+                                        "files",
+                                        Severity::Error,
+                                        format!("Failed to read link target for {:?}", dest_path),
+                                    )
+                                    .about(&dest_path),
+                                ),
                             }
                         }
                     }
-                    Err(_) => {
-                        println!("❌ Failed to read metadata for {:?}", dest_path);
-                        issues_found = true;
-                    }
+                    Err(_) => report.add(
+                        Finding::new(
+                            "files",
+                            Severity::Error,
+                            format!("Failed to read metadata for {:?}", dest_path),
+                        )
+                        .about(&dest_path),
+                    ),
+                }
+            }
+        }
+    }
+
+    // 5. Local state
+    if let Some(db) = &db {
+        report.section("state", "Checking local state...");
+        let db_path = StateDb::get_path().unwrap_or_default();
+        match db.schema_version() {
+            Ok(version) => report.add(
+                Finding::new(
+                    "state",
+                    Severity::Ok,
+                    format!("State database: {:?} (schema v{})", db_path, version),
+                )
+                .about(&db_path),
+            ),
+            Err(e) => report.add(
+                Finding::new(
+                    "state",
+                    Severity::Error,
+                    format!("Failed to read state database: {:#}", e),
+                )
+                .about(&db_path),
+            ),
+        }
+        match db.machine_value("last_apply_at") {
+            Ok(Some(at)) => report.add(Finding::new(
+                "state",
+                Severity::Ok,
+                format!(
+                    "Last applied {} ({} entries recorded)",
+                    at,
+                    db.applied_entries().map(|e| e.len()).unwrap_or(0)
+                ),
+            )),
+            Ok(None) => report.add(
+                Finding::new(
+                    "state",
+                    Severity::Info,
+                    "Nothing has been applied on this machine yet",
+                )
+                .suggest("run `configsync apply`"),
+            ),
+            Err(e) => report.add(
+                Finding::new(
+                    "state",
+                    Severity::Error,
+                    format!("Failed to read state database: {:#}", e),
+                )
+                .about(&db_path),
+            ),
+        }
+        for entry in prune::stale_entries(&config, &resolver, db).unwrap_or_default() {
+            let stale = |message: &str| {
+                Finding::new(
+                    "state",
+                    Severity::Warning,
+                    format!(
+                        "{:?} no longer applies to this machine {}",
+                        entry.destination, message
+                    ),
+                )
+                .about(&entry.destination)
+            };
+            match prune::classify(&entry, config_dir) {
+                Stale::Remove => {
+                    let finding = stale("(run `configsync apply` to remove it)");
+                    report.repair(finding, Fix::RemoveStale(entry), &config);
+                }
+                Stale::Protected => {
+                    report.add(stale("but is protected; remove it manually"));
+                }
+                Stale::Modified => {
+                    report.add(stale("and was changed locally; remove it manually"));
                 }
+                Stale::Gone => {}
             }
         }
     }
 
-    // 5. Secrets Key Check
+    // 6. Secrets Key Check
     // If there are any secrets in config, we should check for key.
     let has_secrets = config
         .files
         .iter()
-        .any(|f| matches!(f.file_type, FileType::Secret));
+        .any(|f| crate::core::secret::backend::is_repo_secret(f, &config.secrets));
     if has_secrets {
-        println!("\nChecking Secrets Key...");
+        report.section("key", "Checking Secrets Key...");
         if let Ok(path) = crate::core::secret::keys::get_key_path() {
             if path.exists() {
-                println!("✅ Key file exists: {:?}", path);
+                report.add(
+                    Finding::new("key", Severity::Ok, format!("Key file exists: {:?}", path))
+                        .about(&path),
+                );
                 #[cfg(unix)]
                 {
                     use std::os::unix::fs::PermissionsExt;
                     let perms = fs::metadata(&path)?.permissions();
                     if perms.mode() & 0o777 != 0o600 {
-                        println!(
-                            "⚠️ Key file permissions unsafe: {:o} (Should be 600)",
-                            perms.mode() & 0o777
+                        report.repair(
+                            Finding::new(
+                                "key",
+                                Severity::Warning,
+                                format!(
+                                    "Key file permissions unsafe: {:o} (Should be 600)",
+                                    perms.mode() & 0o777
+                                ),
+                            )
+                            .about(&path),
+                            Fix::KeyPermissions(path.clone()),
+                            &config,
                         );
-                        issues_found = true;
                     } else {
-                        println!("✅ Key file permissions safe (600)");
+                        report.add(
+                            Finding::new("key", Severity::Ok, "Key file permissions safe (600)")
+                                .about(&path),
+                        );
                     }
                 }
+                match crate::core::secret::keys::key_form() {
+                    Ok(crate::core::secret::keys::KeyForm::Passphrase) => report.add(
+                        Finding::new("key", Severity::Ok, "Key file is passphrase-protected")
+                            .about(&path),
+                    ),
+                    Ok(crate::core::secret::keys::KeyForm::Plain) => report.add(
+                        Finding::new("key", Severity::Info, "Key file is stored unencrypted")
+                            .about(&path)
+                            .suggest("run `configsync secrets protect` to add a passphrase"),
+                    ),
+                    Err(e) => report.add(
+                        Finding::new(
+                            "key",
+                            Severity::Error,
+                            format!("Failed to read key file: {}", e),
+                        )
+                        .about(&path),
+                    ),
+                }
+            } else if let Some(ssh_key) = crate::core::secret::keys::ssh_key_paths()
+                .into_iter()
+                .find(|p| p.exists())
+            {
+                report.add(
+                    Finding::new(
+                        "key",
+                        Severity::Ok,
+                        format!("Using SSH identity: {:?}", ssh_key),
+                    )
+                    .about(&ssh_key),
+                );
             } else {
-                println!("❌ Key file missing! Secrets cannot be decrypted.");
-                issues_found = true;
+                report.add(
+                    Finding::new(
+                        "key",
+                        Severity::Error,
+                        "Key file missing! Secrets cannot be decrypted.",
+                    )
+                    .about(&path)
+                    .suggest("run `configsync secrets import-key` or `configsync secrets init`"),
+                );
             }
         }
     }
 
-    println!("\n----------------------------------------");
-    if issues_found {
-        println!("⚠️ Issues found. Please review the output above.");
-        // Suggest fixes?
-        println!("Try running `configsync apply` to fix missing links/files.");
-    } else {
-        println!("✅ All systems operational.");
+    // 7. Secret expiry and rotation reminders
+    if config
+        .files
+        .iter()
+        .any(|f| f.expires_at.is_some() || f.rotate_every.is_some())
+    {
+        report.section("expiry", "Checking secret expiry...");
+        let today = expiry::today();
+        let repo = GitRepository::open(config_dir).ok();
+        match expiry::reminders(&config, &resolver, repo.as_ref(), today) {
+            Ok(reminders) if reminders.is_empty() => report.add(Finding::new(
+                "expiry",
+                Severity::Ok,
+                "No secrets expiring or due for rotation soon",
+            )),
+            Ok(reminders) => {
+                for reminder in reminders {
+                    report.add(
+                        Finding::new(
+                            "expiry",
+                            if reminder.overdue {
+                                Severity::Error
+                            } else {
+                                Severity::Warning
+                            },
+                            format!(
+                                "Secret '{}' {}",
+                                reminder.file.name(),
+                                reminder.describe(today)
+                            ),
+                        )
+                        .about(&reminder.file.destination)
+                        .suggest(
+                            "rotate it and store the new value with `configsync secrets edit`",
+                        ),
+                    );
+                }
+            }
+            Err(e) => report.add(Finding::new("expiry", Severity::Error, format!("{:#}", e))),
+        }
+    }
+
+    Ok(Some(Loaded {
+        config,
+        config_dir: config_dir.to_path_buf(),
+        db,
+    }))
+}
+
+/// Repository files that belong to configsync itself rather than to an entry.
+const REPO_FILES: &[&str] = &[
+    "team-config.toml",
+    ".gitignore",
+    ".gitattributes",
+    "README.md",
+];
+
+/// Working tree, remote and branch state of the config repository.
+fn check_repository(report: &mut Report, repo: &GitRepository, config_dir: &Path) {
+    if let Some(operation) = repo.operation_in_progress() {
+        report.add(
+            Finding::new(
+                "git",
+                Severity::Error,
+                format!("A git {} is in progress in the repository", operation),
+            )
+            .about(config_dir)
+            .suggest(format!(
+                "finish or abort the {} with git in {:?}",
+                operation, config_dir
+            )),
+        );
+    }
+    if repo.is_head_detached() {
+        report.add(
+            Finding::new(
+                "git",
+                Severity::Error,
+                "HEAD is detached; push and pull need a branch",
+            )
+            .about(config_dir)
+            .suggest(format!("check out a branch with git in {:?}", config_dir)),
+        );
+    }
+
+    match repo.uncommitted_files() {
+        Ok(files) if files.is_empty() => {
+            report.add(Finding::new("git", Severity::Ok, "Working tree clean"))
+        }
+        Ok(files) => {
+            let mut shown: Vec<String> = files
+                .iter()
+                .take(5)
+                .map(|f| f.to_string_lossy().into_owned())
+                .collect();
+            if files.len() > shown.len() {
+                shown.push(format!("and {} more", files.len() - shown.len()));
+            }
+            report.add(
+                Finding::new(
+                    "git",
+                    Severity::Info,
+                    format!(
+                        "{} uncommitted change(s): {}",
+                        files.len(),
+                        shown.join(", ")
+                    ),
+                )
+                .about(config_dir)
+                .suggest("run `configsync push` to commit and share them"),
+            );
+        }
+        Err(e) => report.add(Finding::new(
+            "git",
+            Severity::Error,
+            format!("Failed to read repository status: {:#}", e),
+        )),
+    }
+
+    let Some(url) = repo.origin_url() else {
+        report.add(
+            Finding::new(
+                "git",
+                Severity::Info,
+                "No `origin` remote; changes stay on this machine",
+            )
+            .suggest("add one with `git remote add origin <url>`"),
+        );
+        return;
+    };
+    report.add(Finding::new(
+        "git",
+        Severity::Ok,
+        format!("Remote origin: {}", url),
+    ));
+    match repo.ahead_behind() {
+        Ok(Some((upstream, 0, 0))) => report.add(Finding::new(
+            "git",
+            Severity::Ok,
+            format!("Up to date with {} (as of the last fetch)", upstream),
+        )),
+        Ok(Some((upstream, ahead, 0))) => report.add(
+            Finding::new(
+                "git",
+                Severity::Info,
+                format!("{} commit(s) ahead of {}", ahead, upstream),
+            )
+            .suggest("run `configsync push`"),
+        ),
+        Ok(Some((upstream, 0, behind))) => report.add(
+            Finding::new(
+                "git",
+                Severity::Warning,
+                format!("{} commit(s) behind {}", behind, upstream),
+            )
+            .suggest("run `configsync pull`"),
+        ),
+        Ok(Some((upstream, ahead, behind))) => report.add(
+            Finding::new(
+                "git",
+                Severity::Error,
+                format!(
+                    "Diverged from {}: {} commit(s) ahead, {} behind",
+                    upstream, ahead, behind
+                ),
+            )
+            .about(config_dir)
+            .suggest(format!(
+                "merge or rebase with git in {:?}, then run `configsync apply`",
+                config_dir
+            )),
+        ),
+        Ok(None) => report.add(Finding::new(
+            "git",
+            Severity::Info,
+            "The current branch has not been fetched from origin yet",
+        )),
+        Err(e) => report.add(Finding::new(
+            "git",
+            Severity::Error,
+            format!("Failed to compare with origin: {:#}", e),
+        )),
+    }
+}
+
+/// Entry sources that point outside the repository, and repository files no
+/// entry uses.
+fn check_sources(
+    report: &mut Report,
+    config: &TeamConfig,
+    config_dir: &Path,
+    repo: Option<&GitRepository>,
+) {
+    let repo_sources: Vec<&FileConfig> = config
+        .files
+        .iter()
+        .filter(|f| {
+            !f.file_type.is_secret()
+                || crate::core::secret::backend::is_repo_secret(f, &config.secrets)
+        })
+        .collect();
+    let mut escaping = 0;
+    for file in &repo_sources {
+        if !file.source_in_repo() {
+            escaping += 1;
+            report.add(
+                Finding::new(
+                    "sources",
+                    Severity::Error,
+                    format!(
+                        "Source {:?} of {} is outside the repository",
+                        file.source, file.destination
+                    ),
+                )
+                .about(&file.source)
+                .suggest("use a path relative to the repository root without `..`"),
+            );
+        }
+    }
+
+    let Some(repo) = repo else {
+        return;
+    };
+    let files = match repo.files() {
+        Ok(files) => files,
+        Err(e) => {
+            report.add(Finding::new(
+                "sources",
+                Severity::Error,
+                format!("Failed to list repository files: {:#}", e),
+            ));
+            return;
+        }
+    };
+    let referenced: Vec<PathBuf> = repo_sources
+        .iter()
+        .filter(|f| f.source_in_repo())
+        .map(|f| Path::new(&f.source).components().collect())
+        .collect();
+    let orphans: Vec<&PathBuf> = files
+        .iter()
+        .filter(|path| {
+            !REPO_FILES.iter().any(|f| path.as_path() == Path::new(f))
+                && path.as_path() != Path::new(VALUES_SOURCE)
+                && !path.starts_with(MACHINES_DIR)
+                && !referenced.iter().any(|source| path.starts_with(source))
+        })
+        .collect();
+    for path in &orphans {
+        report.add(
+            Finding::new(
+                "sources",
+                Severity::Warning,
+                format!("{:?} is in the repository but no entry uses it", path),
+            )
+            .about(config_dir.join(path))
+            .suggest("track it with `configsync add`, or delete it from the repository"),
+        );
+    }
+    if escaping == 0 && orphans.is_empty() {
+        report.add(Finding::new(
+            "sources",
+            Severity::Ok,
+            "Every entry source is inside the repository and every repository file is used",
+        ));
+    }
+}
+
+/// Lists the repairs and, unless `dry_run`, makes them. Conflicts were never
+/// queued, so they stay as reported above. Returns which repairs succeeded.
+fn run_fixes(
+    fixes: &[Fix],
+    config: &TeamConfig,
+    config_dir: &Path,
+    db: Option<&StateDb>,
+    dry_run: bool,
+) -> Vec<bool> {
+    if fixes.is_empty() {
+        println!("Nothing to repair automatically.");
+        return Vec::new();
+    }
+    if dry_run {
+        println!("Would make {} fix(es):", fixes.len());
+        for fix in fixes {
+            println!("  🔧 {}", fix.describe(config));
+        }
+        return vec![false; fixes.len()];
+    }
+
+    println!("Making {} fix(es):", fixes.len());
+    let mut applier = db.map(|db| Applier::new(config_dir, config, db));
+    let mut repaired = Vec::with_capacity(fixes.len());
+    for fix in fixes {
+        let dest_of =
+            |i: usize| PathBuf::from(shellexpand::tilde(&config.files[i].destination).into_owned());
+        let result = match fix {
+            Fix::Restore(i) => match applier.as_mut() {
+                Some(applier) => applier.apply_entry(&config.files[*i]),
+                None => Err(anyhow::anyhow!("local state database unavailable")),
+            },
+            Fix::Relink(i) => match applier.as_mut() {
+                Some(applier) => symlink::remove_symlink(dest_of(*i))
+                    .and_then(|_| applier.apply_entry(&config.files[*i])),
+                None => Err(anyhow::anyhow!("local state database unavailable")),
+            },
+            Fix::Permissions(i) => perms::apply(&dest_of(*i), &config.files[*i]),
+            Fix::KeyPermissions(path) => set_key_permissions(path),
+            Fix::RemoveStale(entry) => match db {
+                Some(db) => prune::remove_entry(entry, db),
+                None => Err(anyhow::anyhow!("local state database unavailable")),
+            },
+        };
+        match &result {
+            Ok(()) => println!("🔧 Fixed: {}", fix.describe(config)),
+            Err(e) => println!("❌ Could not {}: {:#}", fix.describe(config), e),
+        }
+        repaired.push(result.is_ok());
     }
+    repaired
+}
+
+#[cfg(unix)]
+fn set_key_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("Failed to set permissions on {:?}", path))
+}
 
+#[cfg(not(unix))]
+fn set_key_permissions(_path: &Path) -> Result<()> {
     Ok(())
 }
+
+/// Decrypts a secret in memory and compares its hash with the destination.
+/// Never prints plaintext.
+fn check_secret(report: &mut Report, file: &FileConfig, dest_path: &Path, backends: &mut Backends) {
+    let finding = match backends.fetch(file) {
+        Ok(plaintext) => {
+            let plaintext = Zeroizing::new(plaintext);
+            match fs::read(dest_path).map(Zeroizing::new) {
+                Ok(local) if Sha256::digest(&*plaintext) == Sha256::digest(&*local) => {
+                    Finding::new(
+                        "secrets",
+                        Severity::Ok,
+                        format!("Secret {:?} matches the repository", dest_path),
+                    )
+                }
+                Ok(_) => Finding::new(
+                    "secrets",
+                    Severity::Warning,
+                    format!(
+                        "Secret {:?} has drifted from the repository version",
+                        dest_path
+                    ),
+                )
+                .suggest("run `configsync apply`, or `configsync secrets add` it again"),
+                Err(e) => Finding::new(
+                    "secrets",
+                    Severity::Error,
+                    format!("Failed to read secret {:?}: {}", dest_path, e),
+                ),
+            }
+        }
+        Err(e) => undecryptable(file, e),
+    };
+    report.add(finding.about(dest_path));
+}
+
+/// Like `check_secret`, for directories: compares each archived file with its local copy.
+fn check_secret_dir(
+    report: &mut Report,
+    file: &FileConfig,
+    dest_path: &Path,
+    backends: &mut Backends,
+) {
+    let drifted = backends.fetch(file).and_then(|plaintext| {
+        let plaintext = Zeroizing::new(plaintext);
+        crate::core::secret::archive::drifted(&plaintext, dest_path)
+    });
+    match drifted {
+        Ok(drifted) if drifted.is_empty() => report.add(
+            Finding::new(
+                "secrets",
+                Severity::Ok,
+                format!("Secret directory {:?} matches the repository", dest_path),
+            )
+            .about(dest_path),
+        ),
+        Ok(drifted) => {
+            for path in drifted {
+                let path = dest_path.join(path);
+                report.add(
+                    Finding::new(
+                        "secrets",
+                        Severity::Warning,
+                        format!("Secret {:?} has drifted from the repository version", path),
+                    )
+                    .about(&path)
+                    .suggest("run `configsync apply`, or `configsync secrets add` it again"),
+                );
+            }
+        }
+        Err(e) => report.add(undecryptable(file, e).about(dest_path)),
+    }
+}
+
+fn undecryptable(file: &FileConfig, e: anyhow::Error) -> Finding {
+    Finding::new(
+        "secrets",
+        Severity::Error,
+        format!(
+            "Cannot decrypt secret {:?} (wrong key, or this key is not a recipient): {:#}",
+            file.source, e
+        ),
+    )
+    .suggest("ask a teammate to `configsync secrets grant` this machine's public key")
+}
+
+/// Renders a template in memory and compares its hash with the destination.
+fn check_template(
+    report: &mut Report,
+    config_dir: &Path,
+    file: &FileConfig,
+    dest_path: &Path,
+    values: &mut Option<Values>,
+) {
+    let rendered = match crate::core::secret::values::render_file(config_dir, &file.source, values)
+    {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            report.add(
+                Finding::new(
+                    "secrets",
+                    Severity::Error,
+                    format!("Cannot render template {:?}: {:#}", file.source, e),
+                )
+                .about(config_dir.join(&file.source)),
+            );
+            return;
+        }
+    };
+    let finding = match fs::read(dest_path).map(Zeroizing::new) {
+        Ok(local) if Sha256::digest(rendered.as_bytes()) == Sha256::digest(&*local) => {
+            Finding::new(
+                "secrets",
+                Severity::Ok,
+                format!("Template {:?} matches the repository", dest_path),
+            )
+        }
+        Ok(_) => Finding::new(
+            "secrets",
+            Severity::Warning,
+            format!(
+                "Template {:?} has drifted from the repository version",
+                dest_path
+            ),
+        )
+        .suggest("run `configsync apply`"),
+        Err(e) => Finding::new(
+            "secrets",
+            Severity::Error,
+            format!("Failed to read {:?}: {}", dest_path, e),
+        ),
+    };
+    report.add(finding.about(dest_path));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(checks: &[&str]) -> Report {
+        Report {
+            json: true,
+            checks: checks.iter().map(|c| c.to_string()).collect(),
+            findings: Vec::new(),
+            fixes: Vec::new(),
+        }
+    }
+
+    fn config() -> TeamConfig {
+        toml::from_str(
+            "[team]\nname = \"t\"\nmaintainers = []\n[repository]\nurl = \"\"\nbranch = \"main\"\n[[files]]\nsource = \"bashrc\"\ndestination = \"~/.bashrc\"\ntype = \"file\"\n",
+        )
+        .expect("valid config")
+    }
+
+    #[test]
+    fn selection_keeps_config_and_drops_other_checks() {
+        let all = report(&[]);
+        assert!(CHECKS.iter().all(|c| all.selected(c)));
+
+        let mut report = report(&["files"]);
+        assert!(report.selected("config") && report.selected("files"));
+        assert!(!report.selected("git"));
+        report.add(Finding::new(
+            "config",
+            Severity::Ok,
+            "Config file passes validation",
+        ));
+        report.add(Finding::new("git", Severity::Error, "Git repository issue"));
+        report.add(Finding::new("files", Severity::Warning, "Broken link"));
+        report.repair(
+            Finding::new("git", Severity::Error, "Not repaired"),
+            Fix::Restore(0),
+            &config(),
+        );
+        let checks: Vec<&str> = report.findings.iter().map(|f| f.check).collect();
+        assert_eq!(checks, ["config", "files"]);
+        assert!(report.fixes.is_empty());
+        assert_eq!(report.count(Severity::Error), 0);
+        assert_eq!(report.count(Severity::Warning), 1);
+    }
+
+    #[test]
+    fn repairs_are_queued_with_their_description() {
+        let config = config();
+        let mut report = report(&[]);
+        report.add(
+            Finding::new("files", Severity::Error, "Source missing in repo")
+                .suggest("restore it in the repository or remove the entry"),
+        );
+        report.repair(
+            Finding::new("files", Severity::Error, "Destination missing").about("/home/u/.bashrc"),
+            Fix::Relink(0),
+            &config,
+        );
+
+        let [manual, repaired] = &report.findings[..] else {
+            panic!("expected two findings");
+        };
+        assert!(!manual.repairable && manual.repair.is_none());
+        assert!(repaired.repairable);
+        assert_eq!(repaired.repair, Some(0));
+        assert_eq!(repaired.fix.as_deref(), Some("relink ~/.bashrc -> bashrc"));
+        assert_eq!(repaired.subject.as_deref(), Some("/home/u/.bashrc"));
+        assert_eq!(report.fixes.len(), 1);
+        assert_eq!(report.count(Severity::Error), 2);
+    }
+
+    #[test]
+    fn findings_serialize_without_the_repair_index() {
+        let mut report = report(&[]);
+        report.repair(
+            Finding::new("permissions", Severity::Warning, "Mode is 644"),
+            Fix::Permissions(0),
+            &config(),
+        );
+        let json = serde_json::to_value(&report.findings[0]).expect("serializable");
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "check": "permissions",
+                "severity": "warning",
+                "subject": null,
+                "message": "Mode is 644",
+                "fix": "reset permissions of ~/.bashrc",
+                "repairable": true,
+            })
+        );
+    }
+}
@@ -1,12 +1,21 @@
 use crate::core::config::loader::ConfigLoader;
 use crate::core::config::schema::{FileConfig, FileType};
-use crate::core::fs::symlink;
+use crate::core::db::StateDb;
+use crate::core::fs::{perms, symlink};
+use crate::core::scan;
+use crate::core::secret::{expiry, values};
 use anyhow::{Context, Result};
 use directories::ProjectDirs;
 use std::fs;
 use std::path::Path;
+use zeroize::Zeroizing;
 
-pub fn add<P: AsRef<Path>>(path: P, role: Vec<String>) -> Result<()> {
+pub fn add<P: AsRef<Path>>(
+    path: P,
+    role: Vec<String>,
+    allow_secrets: bool,
+    template: bool,
+) -> Result<()> {
     let path = path
         .as_ref()
         .canonicalize()
@@ -39,6 +48,45 @@ pub fn add<P: AsRef<Path>>(path: P, role: Vec<String>) -> Result<()> {
         anyhow::bail!("File {:?} already exists in repository", file_name);
     }
 
+    // Refuse to commit credentials in plaintext.
+    let label = file_name.to_string_lossy().to_string();
+    let findings = scan::Scanner::new(&config.scan)?.scan_path(&path, &label)?;
+    if !findings.is_empty() {
+        scan::report(&findings);
+        if !allow_secrets {
+            anyhow::bail!(
+                "Refusing to add {:?} in plaintext. {}",
+                path,
+                scan::OVERRIDE_HINT
+            );
+        }
+        println!("Continuing because --allow-secrets was given.");
+    }
+
+    // Templates are rendered back to the destination, so every placeholder
+    // needs a value before anything is moved.
+    let rendered = if template {
+        if !path.is_file() {
+            anyhow::bail!("Only files can be added as templates");
+        }
+        let text = fs::read_to_string(&path).context("Template must be a UTF-8 text file")?;
+        let names = values::placeholders(&text);
+        if names.is_empty() {
+            println!(
+                "Warning: {:?} contains no {{{{ secret \"name\" }}}} placeholders.",
+                path
+            );
+            Some(Zeroizing::new(text))
+        } else {
+            let identity = crate::core::secret::keys::load_key()
+                .context("Failed to load secret key. Have you run `configsync secrets init`?")?;
+            Some(values::Values::load(config_dir, &identity)?.render(&text)?)
+        }
+    } else {
+        None
+    };
+    let mode = perms::recorded_mode(&path)?;
+
     // 4. Move file to repo
     println!("Moving {:?} to {:?}", path, repo_path);
     if path.is_dir() {
@@ -48,9 +96,14 @@ pub fn add<P: AsRef<Path>>(path: P, role: Vec<String>) -> Result<()> {
         fs::rename(&path, &repo_path).context("Failed to move file")?;
     }
 
-    // 5. Create symlink back
-    println!("Creating symlink from {:?} to {:?}", repo_path, path);
-    symlink::create_symlink(&repo_path, &path)?;
+    // 5. Create symlink back (or write the rendered template)
+    if let Some(rendered) = &rendered {
+        println!("Rendering {:?} to {:?}", repo_path, path);
+        fs::write(&path, rendered.as_bytes()).context("Failed to write rendered template")?;
+    } else {
+        println!("Creating symlink from {:?} to {:?}", repo_path, path);
+        symlink::create_symlink(&repo_path, &path)?;
+    }
 
     // 6. Update config
     let source = file_name.to_string_lossy().to_string();
@@ -58,7 +111,9 @@ pub fn add<P: AsRef<Path>>(path: P, role: Vec<String>) -> Result<()> {
                                                           // Ideally we'd make it relative to HOME if possible for portability.
                                                           // For MVP, absolute is okay-ish, or try to replace HOME with ~
 
-    let file_type = if repo_path.is_dir() {
+    let file_type = if template {
+        FileType::Template
+    } else if repo_path.is_dir() {
         FileType::Directory
     } else {
         FileType::File
@@ -74,6 +129,14 @@ pub fn add<P: AsRef<Path>>(path: P, role: Vec<String>) -> Result<()> {
         critical: false,
         protect: false,
         roles,
+        backend: None,
+        mode: if template { mode } else { None },
+        owner: None,
+        group: None,
+        sensitive: false,
+        expires_at: None,
+        rotate_every: None,
+        rotated_at: None,
     });
 
     ConfigLoader::save(&config, &config_path)?;
@@ -82,7 +145,42 @@ pub fn add<P: AsRef<Path>>(path: P, role: Vec<String>) -> Result<()> {
     Ok(())
 }
 
-pub fn add_secret<P: AsRef<Path>>(path: P) -> Result<()> {
+/// Flags shared by `secrets add` for encrypted and backend secrets.
+#[derive(Debug, Default)]
+pub struct SecretOptions {
+    pub role: Vec<String>,
+    pub platform: Vec<String>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub sensitive: bool,
+    pub expires_at: Option<String>,
+    pub rotate_every: Option<String>,
+}
+
+impl SecretOptions {
+    /// Rejects malformed expiry settings before anything is written.
+    fn validate(&self) -> Result<()> {
+        if let Some(date) = self.expires_at.as_deref() {
+            expiry::parse_date(date)?;
+        }
+        if let Some(interval) = self.rotate_every.as_deref() {
+            expiry::parse_interval(interval)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn add_secret<P: AsRef<Path>>(path: P, options: SecretOptions) -> Result<()> {
+    options.validate()?;
+    let SecretOptions {
+        role,
+        platform,
+        owner,
+        group,
+        sensitive,
+        expires_at,
+        rotate_every,
+    } = options;
     let path = path
         .as_ref()
         .canonicalize()
@@ -101,23 +199,62 @@ pub fn add_secret<P: AsRef<Path>>(path: P) -> Result<()> {
     // 2. Load config
     let mut config = ConfigLoader::load(&config_path)?;
 
+    let destination = path.to_string_lossy().to_string();
+    let existing = config
+        .files
+        .iter()
+        .position(|f| f.destination == destination);
+    let roles = if !role.is_empty() {
+        Some(role)
+    } else {
+        existing.and_then(|i| config.files[i].roles.clone())
+    };
+    // Re-adding keeps the entry's platforms unless some are given (`*` resets them).
+    let platforms_given = !platform.is_empty();
+    let platforms = if platforms_given {
+        platform
+    } else {
+        vec!["*".to_string()]
+    };
+
     // 3. Load Keys
     let identity = crate::core::secret::keys::load_key()
         .context("Failed to load secret key. Have you run `configsync secrets init`?")?;
-    let public_key = crate::core::secret::keys::get_public_key(&identity);
+    // Role-scoped secrets go only to that role's recipient set when one is configured.
+    let recipients = crate::core::secret::keys::recipients_for(
+        &identity,
+        &config.secrets,
+        &config.roles,
+        roles.as_deref(),
+    )?;
 
-    // 4. Encrypt
+    // 4. Encrypt (directories as a single tarball)
+    let is_dir = path.is_dir();
     println!("Reading {:?}", path);
-    let content = fs::read(&path).context("Failed to read secret file")?;
+    let content = if is_dir {
+        crate::core::secret::archive::pack(&path)?
+    } else {
+        Zeroizing::new(fs::read(&path).context("Failed to read secret file")?)
+    };
     println!("Encrypting...");
-    let encrypted = crate::core::secret::cipher::encrypt(&content, &public_key)?;
+    let encrypted =
+        crate::core::secret::cipher::encrypt(&content, &recipients, config.secrets.armor)?;
 
     // 5. Save to Repo
     let file_name = path.file_name().context("Invalid path")?;
     let secret_dir = config_dir.join("secrets");
     fs::create_dir_all(&secret_dir)?;
 
-    let encrypted_filename = format!("{}.age", file_name.to_string_lossy());
+    let encrypted_filename = if is_dir {
+        format!("{}.tar.age", file_name.to_string_lossy())
+    } else {
+        format!("{}.age", file_name.to_string_lossy())
+    };
+    let file_type = if is_dir {
+        FileType::SecretDir
+    } else {
+        FileType::Secret
+    };
     let repo_path = secret_dir.join(&encrypted_filename);
 
     println!("Saving encrypted file to {:?}", repo_path);
@@ -125,22 +262,69 @@ pub fn add_secret<P: AsRef<Path>>(path: P) -> Result<()> {
 
     // 6. Update Config
     let source = format!("secrets/{}", encrypted_filename);
-    let destination = path.to_string_lossy().to_string();
+    // apply restores this mode instead of forcing 600.
+    let mode = perms::recorded_mode(&path)?;
+    let rotated_at = expiry::today().to_string();
+
+    if !config.secrets.encrypted_files.contains(&source) {
+        config.secrets.encrypted_files.push(source.clone());
+    }
 
     // Check if already exists
-    if config.files.iter().any(|f| f.destination == destination) {
-        println!("File already tracked. Updating encrypted content only.");
-        // We already wrote the file, so we are good.
-        // We might want to ensure the type is set to Secret if it wasn't.
+    if let Some(index) = existing {
+        println!("File already tracked. Updating encrypted content.");
+        let was_repo_secret =
+            crate::core::secret::backend::is_repo_secret(&config.files[index], &config.secrets);
+        let entry = &mut config.files[index];
+        // Switched between file and directory (or from a backend): the old
+        // encrypted copy and what apply recorded for it no longer apply.
+        let previous = std::mem::replace(&mut entry.source, source);
+        if previous != entry.source {
+            if was_repo_secret && entry.source_in_repo() {
+                let previous_path = config_dir.join(&previous);
+                if previous_path.is_file() {
+                    fs::remove_file(&previous_path)
+                        .with_context(|| format!("Failed to remove {:?}", previous_path))?;
+                }
+            }
+            config
+                .secrets
+                .encrypted_files
+                .retain(|source| source != &previous);
+            StateDb::open()?.forget_applied(&destination)?;
+        }
+        entry.backend = None;
+        entry.file_type = file_type;
+        entry.mode = mode;
+        entry.owner = owner.or(entry.owner.take());
+        entry.group = group.or(entry.group.take());
+        entry.sensitive |= sensitive;
+        entry.expires_at = expires_at.or(entry.expires_at.take());
+        entry.rotate_every = rotate_every.or(entry.rotate_every.take());
+        // New content counts as a rotation.
+        entry.rotated_at = Some(rotated_at);
+        entry.roles = roles;
+        if platforms_given {
+            entry.platforms = platforms;
+        }
+        ConfigLoader::save(&config, &config_path)?;
     } else {
         config.files.push(FileConfig {
             source,
             destination,
-            file_type: FileType::Secret,
-            platforms: vec!["*".to_string()],
+            file_type,
+            platforms,
             critical: false,
             protect: false,
-            roles: None, // Secrets are usually machine-specific in this MVP personal-sync model, or we can add roles later
+            roles,
+            backend: None,
+            mode,
+            owner,
+            group,
+            sensitive,
+            expires_at,
+            rotate_every,
+            rotated_at: Some(rotated_at),
         });
         ConfigLoader::save(&config, &config_path)?;
         println!("Added secret {:?} to config.", path);
@@ -148,3 +332,73 @@ pub fn add_secret<P: AsRef<Path>>(path: P) -> Result<()> {
 
     Ok(())
 }
+
+/// Tracks a secret whose value lives in an external backend (pass, env file, Vault).
+/// Nothing is read or encrypted locally; apply fetches the value at run time.
+pub fn add_backend_secret<P: AsRef<Path>>(
+    path: P,
+    backend: String,
+    source: String,
+    options: SecretOptions,
+) -> Result<()> {
+    options.validate()?;
+    let path = std::path::absolute(path.as_ref()).context("Failed to resolve path")?;
+
+    let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+        .context("Could not determine project directories")?;
+    let config_dir = proj_dirs.config_dir();
+    let config_path = config_dir.join("team-config.toml");
+
+    if !config_path.exists() {
+        anyhow::bail!("ConfigSync not initialized. Run `configsync init` first.");
+    }
+
+    let mut config = ConfigLoader::load(&config_path)?;
+
+    if !crate::core::secret::backend::BACKENDS.contains(&backend.as_str()) {
+        anyhow::bail!(
+            "Unknown secret backend '{}'. Expected one of: {}.",
+            backend,
+            crate::core::secret::backend::BACKENDS.join(", ")
+        );
+    }
+
+    let destination = path.to_string_lossy().to_string();
+    if config.files.iter().any(|f| f.destination == destination) {
+        anyhow::bail!("{:?} is already tracked", path);
+    }
+
+    config.files.push(FileConfig {
+        source,
+        destination,
+        file_type: FileType::Secret,
+        platforms: if options.platform.is_empty() {
+            vec!["*".to_string()]
+        } else {
+            options.platform
+        },
+        critical: false,
+        protect: false,
+        roles: if options.role.is_empty() {
+            None
+        } else {
+            Some(options.role)
+        },
+        backend: Some(backend.clone()),
+        mode: None,
+        owner: options.owner,
+        group: options.group,
+        sensitive: options.sensitive,
+        expires_at: options.expires_at,
+        rotate_every: options.rotate_every,
+        // The value lives in the backend; rotation is tracked from when it was added.
+        rotated_at: Some(expiry::today().to_string()),
+    });
+    ConfigLoader::save(&config, &config_path)?;
+    println!(
+        "Added secret {:?} from backend '{}' to config.",
+        path, backend
+    );
+
+    Ok(())
+}
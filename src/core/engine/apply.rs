@@ -1,10 +1,15 @@
 use crate::core::config::loader::ConfigLoader;
-use crate::core::fs::symlink;
+use crate::core::config::schema::{FileConfig, FileType, TeamConfig};
+use crate::core::db::{self, AppliedEntry, StateDb};
+use crate::core::fs::{perms, symlink};
+use crate::core::git::repository::GitRepository;
+use crate::core::secret::backend::{self, Backends};
+use crate::core::secret::values::{self, Values};
 use anyhow::{Context, Result};
 use directories::ProjectDirs;
 use shellexpand;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub fn apply() -> Result<()> {
     // 1. Locate config
@@ -23,71 +28,170 @@ pub fn apply() -> Result<()> {
 
     println!("Applying configurations for team: {}", config.team.name);
 
-    // 2a. Load local state for roles
+    // 2a. Load local state for roles; everything written is recorded against HEAD.
     use crate::core::state::LocalState;
-    let state = LocalState::load().unwrap_or_default();
+    let db = StateDb::open()?;
+    let state = LocalState::from_db(&db)?;
+    let resolver = crate::core::roles::RoleResolver::for_machine(&state, &config);
     println!("Current machine roles: {:?}", state.roles);
+    let mut applier = Applier::new(config_dir, &config, &db);
 
     // 3. Iterate files and symlink
-    for file in config.files {
-        // Role check
-        if let Some(ref required_roles) = file.roles {
-            if !required_roles.is_empty() {
-                let has_role = required_roles.iter().any(|r| state.has_role(r));
-                if !has_role {
-                    println!(
-                        "Skipping {:?} (required roles: {:?})",
-                        file.source, required_roles
-                    );
-                    continue;
-                }
-            }
+    for file in &config.files {
+        // Role and platform check
+        let explanation = resolver.explain(file);
+        if !explanation.enabled {
+            println!(
+                "Skipping {:?} ({})",
+                file.source,
+                explanation.reasons.join("; ")
+            );
+            continue;
         }
 
-        let source_path = config_dir.join(&file.source);
+        applier.apply_entry(file)?;
+    }
+
+    // 4. Remove what deleted or disabled entries left behind.
+    crate::core::engine::prune::prune(&config, config_dir, &resolver, &db)?;
+
+    db.record_machine()?;
+    db.set_machine_value("last_apply_at", &db::now())?;
+    db.set_machine_value("last_apply_commit", applier.commit().unwrap_or(""))?;
+
+    Ok(())
+}
+
+/// Hash of a linked file's repository copy; directories are not hashed.
+fn link_hash(source_path: &Path) -> Option<String> {
+    if source_path.is_file() {
+        fs::read(source_path)
+            .ok()
+            .map(|data| db::content_hash(&data))
+    } else {
+        None
+    }
+}
+
+/// Writes single entries to this machine and records them in the state
+/// database against the repository's HEAD.
+pub struct Applier<'a> {
+    config_dir: &'a Path,
+    config: &'a TeamConfig,
+    db: &'a StateDb,
+    backends: Backends<'a>,
+    values: Option<Values>,
+    commit: Option<String>,
+}
+
+impl<'a> Applier<'a> {
+    pub fn new(config_dir: &'a Path, config: &'a TeamConfig, db: &'a StateDb) -> Self {
+        Self {
+            config_dir,
+            config,
+            db,
+            backends: Backends::new(config_dir, &config.secrets),
+            values: None,
+            commit: GitRepository::open(config_dir)
+                .ok()
+                .and_then(|repo| repo.head_commit()),
+        }
+    }
+
+    pub fn commit(&self) -> Option<&str> {
+        self.commit.as_deref()
+    }
+
+    /// Links, decrypts or renders one entry. Conflicts (a real file or a
+    /// foreign link at the destination) are reported and left alone.
+    pub fn apply_entry(&mut self, file: &FileConfig) -> Result<()> {
+        let source_path = self.config_dir.join(&file.source);
 
         let expanded_dest = shellexpand::tilde(&file.destination);
         let dest_path = PathBuf::from(expanded_dest.into_owned());
 
-        if !source_path.exists() {
+        let is_external_secret =
+            file.file_type.is_secret() && !backend::is_repo_secret(file, &self.config.secrets);
+
+        if !is_external_secret && !source_path.exists() {
             println!(
                 "Warning: Source file {:?} does not exist. Skipping.",
                 source_path
             );
-            continue;
+            return Ok(());
         }
 
-        use crate::core::config::schema::FileType;
         match file.file_type {
-            FileType::Secret => {
-                println!("Decrypting secret {:?} -> {:?}", source_path, dest_path);
-                // Load key (lazy load? for now just load every time or load once outside loop)
-                // Let's load once outside loop if possible, or just here.
-                let identity_result = crate::core::secret::keys::load_key();
-
-                if let Ok(identity) = identity_result {
-                    let encrypted_content =
-                        std::fs::read(&source_path).context("Failed to read encrypted file")?;
-                    match crate::core::secret::cipher::decrypt(&encrypted_content, &identity) {
-                        Ok(decrypted) => {
-                            if let Some(parent) = dest_path.parent() {
-                                std::fs::create_dir_all(parent)?;
-                            }
-                            std::fs::write(&dest_path, decrypted)?;
-                            #[cfg(unix)]
-                            {
-                                // Secrets should be 600
-                                use std::os::unix::fs::PermissionsExt;
-                                let mut perms = std::fs::metadata(&dest_path)?.permissions();
-                                perms.set_mode(0o600);
-                                std::fs::set_permissions(&dest_path, perms)?;
+            FileType::Secret | FileType::SecretDir => {
+                if is_external_secret {
+                    println!(
+                        "Fetching secret {:?} from {} -> {:?}",
+                        file.source,
+                        backend::backend_name(file, &self.config.secrets),
+                        dest_path
+                    );
+                } else {
+                    println!("Decrypting secret {:?} -> {:?}", source_path, dest_path);
+                }
+
+                // Backends (and the age key) are created lazily, once: unlocking a
+                // passphrase-protected key is slow.
+                match self.backends.fetch(file) {
+                    Ok(decrypted) if file.file_type == FileType::SecretDir => {
+                        let decrypted = zeroize::Zeroizing::new(decrypted);
+                        match crate::core::secret::archive::unpack(&decrypted, &dest_path) {
+                            Ok(()) => {
+                                if let Err(e) = perms::apply(&dest_path, file) {
+                                    println!("Warning: {:#}", e);
+                                }
+                                let hash =
+                                    crate::core::secret::archive::content_digest(&dest_path).ok();
+                                self.record(file, &dest_path, hash);
+                                println!("Restored secret directory.");
                             }
-                            println!("Restored secret.");
+                            Err(e) => println!("Skipping secret: {:#}", e),
                         }
-                        Err(e) => println!("Failed to decrypt: {}", e),
                     }
-                } else {
-                    println!("Skipping secret: No private key found. Run `configsync secrets init` or restore key.");
+                    Ok(decrypted) => {
+                        let decrypted = zeroize::Zeroizing::new(decrypted);
+                        if let Some(parent) = dest_path.parent() {
+                            std::fs::create_dir_all(parent)?;
+                        }
+                        std::fs::write(&dest_path, &*decrypted)?;
+                        // Recorded mode (600 if none), owner and group.
+                        if let Err(e) = perms::apply(&dest_path, file) {
+                            println!("Warning: {:#}", e);
+                        }
+                        let hash = db::content_hash(&decrypted);
+                        self.record(file, &dest_path, Some(hash));
+                        println!("Restored secret.");
+                    }
+                    Err(e) => println!("Skipping secret: {:#}", e),
+                }
+            }
+            FileType::Template => {
+                println!("Rendering {:?} -> {:?}", source_path, dest_path);
+                match values::render_file(self.config_dir, &file.source, &mut self.values) {
+                    Ok(rendered) => {
+                        // Never write through a symlink back into the repository copy.
+                        if fs::symlink_metadata(&dest_path)
+                            .map(|m| m.file_type().is_symlink())
+                            .unwrap_or(false)
+                        {
+                            fs::remove_file(&dest_path)?;
+                        }
+                        if let Some(parent) = dest_path.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        fs::write(&dest_path, rendered.as_bytes())?;
+                        if let Err(e) = perms::apply(&dest_path, file) {
+                            println!("Warning: {:#}", e);
+                        }
+                        let hash = db::content_hash(rendered.as_bytes());
+                        self.record(file, &dest_path, Some(hash));
+                        println!("Rendered template.");
+                    }
+                    Err(e) => println!("Skipping template: {:#}", e),
                 }
             }
             _ => {
@@ -114,41 +218,62 @@ pub fn apply() -> Result<()> {
 
                                 if same_target {
                                     println!("Already linked. Skipping.");
-                                    continue;
+                                    self.record(file, &dest_path, link_hash(&source_path));
+                                    return Ok(());
                                 }
 
                                 println!(
                                     "Failed: Destination exists and points elsewhere ({:?}).",
                                     resolved_target
                                 );
-                                continue;
+                                return Ok(());
                             }
                             Err(e) => {
                                 println!("Failed: Could not read existing symlink: {}", e);
-                                continue;
+                                return Ok(());
                             }
                         }
                     }
                     Ok(_) => {
                         println!("Failed: Destination exists and is not a symlink.");
-                        continue;
+                        return Ok(());
                     }
                     Err(e) => {
                         if e.kind() != std::io::ErrorKind::NotFound {
                             println!("Failed: Could not inspect destination: {}", e);
-                            continue;
+                            return Ok(());
                         }
                         // Destination does not exist; create symlink below.
                     }
                 }
 
                 match symlink::create_symlink(&source_path, &dest_path) {
-                    Ok(_) => println!("OK"),
+                    Ok(_) => {
+                        self.record(file, &dest_path, link_hash(&source_path));
+                        println!("OK")
+                    }
                     Err(e) => println!("Failed: {}", e),
                 }
             }
         }
+
+        Ok(())
     }
 
-    Ok(())
+    /// Records a written entry in the state database. Failures only warn: the
+    /// destination is already in place.
+    fn record(&self, file: &FileConfig, dest_path: &Path, content_hash: Option<String>) {
+        let entry = AppliedEntry {
+            destination: dest_path.to_string_lossy().to_string(),
+            source: file.source.clone(),
+            file_type: file.file_type.as_str().to_string(),
+            content_hash,
+            applied_at: db::now(),
+            commit_id: self.commit.clone(),
+            protect: file.protect,
+        };
+        if let Err(e) = self.db.record_applied(&entry) {
+            println!("Warning: {:#}", e);
+        }
+    }
 }
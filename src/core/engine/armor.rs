@@ -0,0 +1,62 @@
+use crate::core::config::loader::ConfigLoader;
+use crate::core::secret::{backend, cipher, values};
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use std::fs;
+
+/// Enables `secrets.armor` and converts existing binary `.age` files in place.
+pub fn armor() -> Result<()> {
+    let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+        .context("Could not determine project directories")?;
+    let config_dir = proj_dirs.config_dir();
+    let config_path = config_dir.join("team-config.toml");
+
+    if !config_path.exists() {
+        anyhow::bail!("ConfigSync not initialized. Run `configsync init` first.");
+    }
+
+    let mut config = ConfigLoader::load(&config_path)?;
+
+    let sources = config
+        .files
+        .iter()
+        .filter(|f| backend::is_repo_secret(f, &config.secrets))
+        .map(|f| f.source.as_str())
+        .chain(std::iter::once(values::VALUES_SOURCE));
+
+    let mut converted = 0;
+    for source in sources {
+        let source_path = config_dir.join(source);
+        if source == values::VALUES_SOURCE && !source_path.exists() {
+            continue;
+        }
+        let data = match fs::read(&source_path) {
+            Ok(data) => data,
+            Err(e) => {
+                println!("Skipping {:?}: {}", source_path, e);
+                continue;
+            }
+        };
+        if cipher::is_armored(&data) {
+            continue;
+        }
+
+        println!("Armoring {:?}", source_path);
+        let armored = cipher::armor(&data)?;
+        fs::write(&source_path, armored)
+            .with_context(|| format!("Failed to write {:?}", source_path))?;
+        converted += 1;
+    }
+
+    if !config.secrets.armor {
+        config.secrets.armor = true;
+        ConfigLoader::save(&config, &config_path)?;
+        println!("Enabled armored output for new secrets.");
+    }
+
+    println!(
+        "Converted {} secret(s). Run `configsync push` to commit the change.",
+        converted
+    );
+    Ok(())
+}
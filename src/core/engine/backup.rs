@@ -0,0 +1,135 @@
+use crate::core::config::loader::ConfigLoader;
+use crate::core::secret::{backend, cipher, keys};
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use secrecy::ExposeSecret;
+use std::fs;
+use std::path::PathBuf;
+
+/// Writes the identity as a passphrase-encrypted, ASCII-armored age file
+/// (to `output`, or stdout so it can be printed for a paper backup).
+pub fn export_key(output: Option<PathBuf>) -> Result<()> {
+    let identity = match keys::load_key()
+        .context("Failed to load secret key. Have you run `configsync secrets init`?")?
+    {
+        keys::SecretIdentity::Age(identity) => identity,
+        keys::SecretIdentity::Ssh { path, .. } => anyhow::bail!(
+            "No configsync key file to export; secrets use the SSH key {:?}.",
+            path
+        ),
+    };
+
+    let passphrase = keys::new_backup_passphrase().context("Failed to read export passphrase")?;
+    let armored = cipher::encrypt_with_passphrase(
+        identity.to_string().expose_secret().as_bytes(),
+        &passphrase,
+    )?;
+
+    match output {
+        Some(path) => {
+            fs::write(&path, &armored).context("Failed to write key backup")?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mut perms = fs::metadata(&path)?.permissions();
+                perms.set_mode(0o600);
+                fs::set_permissions(&path, perms)?;
+            }
+            eprintln!("Key backup written to {:?}", path);
+        }
+        None => print!("{}", String::from_utf8_lossy(&armored)),
+    }
+
+    Ok(())
+}
+
+/// Restores an identity produced by `export_key`, refusing keys that cannot
+/// decrypt any tracked secret. A passphrase-protected key file stays protected.
+pub fn import_key(input: PathBuf, force: bool) -> Result<()> {
+    let armored = fs::read(&input).with_context(|| format!("Failed to read {:?}", input))?;
+    let passphrase = keys::read_backup_passphrase()?;
+    let decrypted = cipher::decrypt_with_passphrase(&armored, &passphrase)
+        .context("Failed to decrypt key backup (wrong passphrase?)")?;
+    let key = String::from_utf8(decrypted).context("Key backup is not valid UTF-8")?;
+    let key = key.trim().to_string();
+    let identity = key
+        .parse::<age::x25519::Identity>()
+        .map_err(|e| anyhow::anyhow!("Key backup does not contain a valid age key: {}", e))?;
+    let identity = keys::SecretIdentity::Age(identity);
+
+    // Verify against tracked secrets before touching the existing key.
+    let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+        .context("Could not determine project directories")?;
+    let config_dir = proj_dirs.config_dir();
+    let config_path = config_dir.join("team-config.toml");
+
+    if config_path.exists() {
+        let config = ConfigLoader::load(&config_path)?;
+        let secrets: Vec<_> = config
+            .files
+            .iter()
+            .filter(|f| backend::is_repo_secret(f, &config.secrets))
+            .collect();
+
+        if secrets.is_empty() {
+            println!("No tracked secrets to verify the key against.");
+        } else {
+            let decryptable = secrets
+                .iter()
+                .filter(|f| {
+                    fs::read(config_dir.join(&f.source))
+                        .ok()
+                        .map(|data| cipher::decrypt(&data, &identity).is_ok())
+                        .unwrap_or(false)
+                })
+                .count();
+            if decryptable == 0 && !force {
+                anyhow::bail!(
+                    "The imported key cannot decrypt any of the {} tracked secret(s). Use --force to import anyway.",
+                    secrets.len()
+                );
+            }
+            println!(
+                "Key decrypts {} of {} tracked secret(s).",
+                decryptable,
+                secrets.len()
+            );
+        }
+    }
+
+    let key_path = keys::get_key_path()?;
+    let mut protection = None;
+    if key_path.exists() {
+        let existing = keys::load_key_file(&key_path);
+        if let Ok(existing) = &existing {
+            if existing.to_string().expose_secret() == &key {
+                println!("This key is already installed at {:?}", key_path);
+                return Ok(());
+            }
+        }
+        if !force {
+            anyhow::bail!(
+                "A different key already exists at {:?}. Use --force to replace it (the old key is archived).",
+                key_path
+            );
+        }
+        if keys::key_form()? == keys::KeyForm::Passphrase {
+            // Keep the passphrase the key was unlocked with; if it could not
+            // be unlocked, ask for a new one rather than writing plaintext.
+            protection = Some(if existing.is_ok() {
+                keys::passphrase()?
+            } else {
+                keys::new_passphrase()?
+            });
+        }
+        let archived = keys::archive_key()?;
+        println!("Existing key archived at {:?}", archived);
+    }
+
+    match protection {
+        Some(passphrase) => keys::save_protected_key(&key, &passphrase)?,
+        None => keys::save_key(&key)?,
+    }
+    println!("Key imported to {:?}", key_path);
+    Ok(())
+}
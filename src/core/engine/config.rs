@@ -0,0 +1,51 @@
+use crate::core::config::loader::ConfigLoader;
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use std::path::{Path, PathBuf};
+
+/// Checks `path` (this machine's `team-config.toml` by default) and prints
+/// every problem with its line. Fails when any of them is an error, so the
+/// team repository can run it as a pre-commit hook.
+pub fn validate(path: Option<&Path>) -> Result<()> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => default_path()?,
+    };
+    if !path.exists() {
+        anyhow::bail!("Config file not found: {:?}", path);
+    }
+
+    let (config, problems) = ConfigLoader::load_with_problems(&path)
+        .with_context(|| format!("Failed to load {:?}", path))?;
+    for problem in &problems {
+        let icon = if problem.is_error() { "❌" } else { "⚠️" };
+        println!("{} {}", icon, problem.located(&path));
+    }
+
+    let errors = problems.iter().filter(|p| p.is_error()).count();
+    let warnings = problems.len() - errors;
+    if errors > 0 {
+        anyhow::bail!(
+            "{} has {} error(s) and {} warning(s)",
+            path.display(),
+            errors,
+            warnings
+        );
+    }
+    if warnings > 0 {
+        println!("{} warning(s), no errors.", warnings);
+    } else {
+        println!(
+            "✅ {} is valid ({} entries)",
+            path.display(),
+            config.files.len()
+        );
+    }
+    Ok(())
+}
+
+fn default_path() -> Result<PathBuf> {
+    let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+        .context("Could not determine project directories")?;
+    Ok(proj_dirs.config_dir().join("team-config.toml"))
+}
@@ -0,0 +1,105 @@
+use crate::core::config::loader::ConfigLoader;
+use crate::core::config::schema::{FileConfig, FileType};
+use crate::core::roles::RoleResolver;
+use crate::core::secret::backend::Backends;
+use crate::core::state::LocalState;
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use std::process::Command;
+use zeroize::Zeroizing;
+
+/// Runs `command` with the variables from dotenv-formatted secrets in its
+/// environment. Secrets are decrypted in memory only and the buffers are
+/// zeroized once the child has been spawned. Returns the child's exit code.
+pub fn exec(names: Vec<String>, command: Vec<String>) -> Result<i32> {
+    let (program, args) = command.split_first().context("No command given")?;
+
+    let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+        .context("Could not determine project directories")?;
+    let config_dir = proj_dirs.config_dir();
+    let config_path = config_dir.join("team-config.toml");
+
+    if !config_path.exists() {
+        anyhow::bail!("ConfigSync not initialized. Run `configsync init` first.");
+    }
+
+    let config = ConfigLoader::load(&config_path)?;
+    let state = LocalState::load().unwrap_or_default();
+    let resolver = RoleResolver::for_machine(&state, &config);
+    let selected = select_secrets(&config.files, &names, &resolver)?;
+
+    let mut backends = Backends::new(config_dir, &config.secrets);
+    let mut vars: Vec<(String, Zeroizing<String>)> = Vec::new();
+    for file in selected {
+        let plaintext = Zeroizing::new(
+            backends
+                .fetch(file)
+                .with_context(|| format!("Failed to decrypt secret '{}'", file.name()))?,
+        );
+        for item in dotenvy::from_read_iter(plaintext.as_slice()) {
+            let (key, value) = item
+                .with_context(|| format!("Secret '{}' is not a valid dotenv file", file.name()))?;
+            vars.push((key, Zeroizing::new(value)));
+        }
+    }
+
+    let mut child = Command::new(program);
+    child.args(args);
+    for (key, value) in &vars {
+        child.env(key, value.as_str());
+    }
+    let spawned = child.spawn();
+    drop(child);
+    drop(vars);
+
+    let status = spawned
+        .with_context(|| format!("Failed to run {:?}", program))?
+        .wait()
+        .context("Failed to wait for command")?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Picks the secrets named on the command line, or every dotenv-style secret
+/// (`*.env`, `.env*`) when none are named. Either way only secrets that apply
+/// to this machine are used.
+fn select_secrets<'a>(
+    files: &'a [FileConfig],
+    names: &[String],
+    resolver: &RoleResolver,
+) -> Result<Vec<&'a FileConfig>> {
+    let secrets = files
+        .iter()
+        .filter(|f| matches!(f.file_type, FileType::Secret));
+
+    if !names.is_empty() {
+        let secrets: Vec<_> = secrets.collect();
+        return names
+            .iter()
+            .map(|name| {
+                let file = secrets
+                    .iter()
+                    .find(|f| f.matches_name(name))
+                    .copied()
+                    .with_context(|| format!("No tracked secret named '{}'", name))?;
+                let explanation = resolver.explain(file);
+                if !explanation.enabled {
+                    anyhow::bail!(
+                        "Secret '{}' does not apply to this machine: {}",
+                        name,
+                        explanation.reasons.join("; ")
+                    );
+                }
+                Ok(file)
+            })
+            .collect();
+    }
+
+    let selected: Vec<_> = secrets
+        .filter(|f| resolver.matches(f))
+        .filter(|f| f.name().ends_with(".env") || f.name().starts_with(".env"))
+        .collect();
+    if selected.is_empty() {
+        anyhow::bail!("No dotenv secrets found. Pick secrets explicitly with --secret <name>.");
+    }
+    Ok(selected)
+}
@@ -0,0 +1,264 @@
+use crate::core::config::loader::ConfigLoader;
+use crate::core::config::schema::{FileConfig, FileType, TeamConfig};
+use crate::core::db::StateDb;
+use crate::core::engine::prune::{self, Stale};
+use crate::core::fs::perms;
+use crate::core::roles::RoleResolver;
+use crate::core::secret::{backend, cipher, keys, values};
+use crate::core::state::LocalState;
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Walks through the checks `apply` and `doctor` make for the entry whose
+/// destination, source or name is `path`, printing each decision.
+pub fn explain(path: &str) -> Result<()> {
+    let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+        .context("Could not determine project directories")?;
+    let config_dir = proj_dirs.config_dir();
+    let config_path = config_dir.join("team-config.toml");
+    if !config_path.exists() {
+        anyhow::bail!("ConfigSync not initialized. Run `configsync init` first.");
+    }
+
+    let config = ConfigLoader::load(&config_path)?;
+    let db = StateDb::open()?;
+    let state = LocalState::from_db(&db)?;
+    let resolver = RoleResolver::for_machine(&state, &config);
+
+    // Accept relative paths as typed in the shell, as well as `~/...`.
+    let absolute = std::path::absolute(shellexpand::tilde(path).as_ref())
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let entries: Vec<&FileConfig> = config
+        .files
+        .iter()
+        .filter(|f| f.matches_name(path) || f.matches_name(&absolute))
+        .collect();
+
+    if entries.is_empty() {
+        return explain_untracked(&absolute, config_dir, &config, &resolver, &db);
+    }
+    for (i, file) in entries.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        explain_entry(file, config_dir, &config, &resolver, &db)?;
+    }
+    Ok(())
+}
+
+fn explain_entry(
+    file: &FileConfig,
+    config_dir: &Path,
+    config: &TeamConfig,
+    resolver: &RoleResolver,
+    db: &StateDb,
+) -> Result<()> {
+    let source_path = config_dir.join(&file.source);
+    let dest_path = PathBuf::from(shellexpand::tilde(&file.destination).into_owned());
+    println!(
+        "{} -> {} ({})",
+        file.source,
+        file.destination,
+        file.file_type.as_str()
+    );
+
+    // 1. Roles and platform
+    let explanation = resolver.explain(file);
+    let (role_reasons, platform_reasons) = explanation
+        .reasons
+        .split_at(explanation.reasons.len() - usize::from(!explanation.platform_ok));
+    println!(
+        "{} Roles: {}",
+        mark(explanation.roles_ok),
+        if explanation.roles_ok {
+            "match this machine"
+        } else {
+            "do not match this machine"
+        }
+    );
+    for reason in role_reasons {
+        println!("     {}", reason);
+    }
+    if explanation.platform_ok {
+        println!(
+            "✅ Platform: {} matches {:?}",
+            std::env::consts::OS,
+            file.platforms
+        );
+    } else {
+        println!("❌ Platform: {}", platform_reasons.join("; "));
+    }
+    if !explanation.enabled {
+        println!("=> apply skips this entry on this machine.");
+        return Ok(());
+    }
+
+    // 2. Source
+    let external = file.file_type.is_secret() && !backend::is_repo_secret(file, &config.secrets);
+    if external {
+        println!(
+            "ℹ️ Source: fetched from the '{}' backend at apply time",
+            backend::backend_name(file, &config.secrets)
+        );
+    } else if source_path.exists() {
+        println!("✅ Source: {:?} exists", source_path);
+    } else {
+        println!(
+            "❌ Source: {:?} is missing from the repository",
+            source_path
+        );
+        println!("=> apply warns and skips this entry.");
+        return Ok(());
+    }
+
+    // 3. Key for anything that has to be decrypted
+    let needs_key = match file.file_type {
+        FileType::Secret | FileType::SecretDir => !external,
+        FileType::Template => fs::read_to_string(&source_path)
+            .map(|text| !values::placeholders(&text).is_empty())
+            .unwrap_or(false),
+        _ => false,
+    };
+    if needs_key {
+        let Some(key) = key_source() else {
+            println!("❌ Key: no key file or SSH identity; the entry cannot be decrypted");
+            println!("=> apply skips this entry until `configsync secrets import-key` or `secrets init`.");
+            return Ok(());
+        };
+        match try_decrypt(file, config_dir) {
+            Ok(()) => println!("✅ Key: {:?} decrypts this entry", key),
+            Err(e) => {
+                println!("❌ Key: {:?} cannot decrypt this entry: {:#}", key, e);
+                println!("=> apply skips this entry until the key can be unlocked or a teammate runs `configsync secrets grant` for it.");
+                return Ok(());
+            }
+        }
+    }
+
+    // 4. Destination
+    let verdict = match fs::symlink_metadata(&dest_path) {
+        Err(_) => {
+            println!("ℹ️ Destination: {:?} does not exist", dest_path);
+            "apply creates it".to_string()
+        }
+        Ok(metadata) if file.file_type.is_copy() => {
+            if metadata.file_type().is_symlink() && file.file_type == FileType::Template {
+                println!("ℹ️ Destination: {:?} is a symlink", dest_path);
+                "apply replaces the symlink with the rendered file".to_string()
+            } else {
+                println!("✅ Destination: {:?} exists", dest_path);
+                for problem in perms::check(&dest_path, file).unwrap_or_default() {
+                    println!("⚠️ Destination {}", problem);
+                }
+                "apply overwrites it with the repository version".to_string()
+            }
+        }
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            let target = fs::read_link(&dest_path).unwrap_or_default();
+            let same = fs::canonicalize(&dest_path).ok() == fs::canonicalize(&source_path).ok();
+            if same {
+                println!("✅ Destination: {:?} links to {:?}", dest_path, target);
+                "already linked; apply leaves it alone".to_string()
+            } else {
+                println!(
+                    "❌ Destination: {:?} links to {:?}, not {:?}",
+                    dest_path, target, source_path
+                );
+                "apply refuses to replace a link that points elsewhere".to_string()
+            }
+        }
+        Ok(_) => {
+            println!(
+                "❌ Destination: {:?} exists and is not a symlink",
+                dest_path
+            );
+            "apply refuses to overwrite it; move it away and re-run apply".to_string()
+        }
+    };
+
+    // 5. What this machine last did with it
+    match db.applied(&dest_path.to_string_lossy())? {
+        Some(entry) => println!(
+            "ℹ️ Last applied {}{}",
+            entry.applied_at,
+            entry
+                .commit_id
+                .map(|c| format!(" from commit {}", &c[..c.len().min(7)]))
+                .unwrap_or_default()
+        ),
+        None => println!("ℹ️ Never applied on this machine"),
+    }
+
+    println!("=> {}.", verdict);
+    Ok(())
+}
+
+/// Paths that are not tracked may still be left over from an entry this
+/// machine applied earlier.
+fn explain_untracked(
+    absolute: &str,
+    config_dir: &Path,
+    config: &TeamConfig,
+    resolver: &RoleResolver,
+    db: &StateDb,
+) -> Result<()> {
+    let stale = prune::stale_entries(config, resolver, db)?
+        .into_iter()
+        .find(|entry| entry.destination == absolute);
+    let Some(entry) = stale else {
+        anyhow::bail!(
+            "No tracked entry matches {:?}. Give a destination, repository source or file name.",
+            absolute
+        );
+    };
+
+    println!(
+        "{} -> {} ({})",
+        entry.source, entry.destination, entry.file_type
+    );
+    println!(
+        "ℹ️ No longer applies to this machine; last applied {}",
+        entry.applied_at
+    );
+    let verdict = match prune::classify(&entry, config_dir) {
+        Stale::Remove => "apply removes it",
+        Stale::Protected => "it was protected, so apply only reports it",
+        Stale::Modified => "it changed since apply wrote it, so apply only reports it",
+        Stale::Gone => "it is already gone; apply forgets it",
+    };
+    println!("=> {}.", verdict);
+    Ok(())
+}
+
+fn mark(ok: bool) -> &'static str {
+    if ok {
+        "✅"
+    } else {
+        "❌"
+    }
+}
+
+/// Decrypts the entry in memory the way `apply` would, discarding the result.
+fn try_decrypt(file: &FileConfig, config_dir: &Path) -> Result<()> {
+    if file.file_type == FileType::Template {
+        values::render_file(config_dir, &file.source, &mut None)?;
+        return Ok(());
+    }
+    let identity = keys::load_key()?;
+    let encrypted = fs::read(config_dir.join(&file.source))
+        .with_context(|| format!("Failed to read {:?}", file.source))?;
+    Zeroizing::new(cipher::decrypt(&encrypted, &identity)?);
+    Ok(())
+}
+
+/// The key file, or the SSH identity used when there is none.
+fn key_source() -> Option<PathBuf> {
+    keys::get_key_path()
+        .ok()
+        .filter(|p| p.exists())
+        .or_else(|| keys::ssh_key_paths().into_iter().find(|p| p.exists()))
+}
@@ -5,6 +5,10 @@ use directories::ProjectDirs;
 use std::fs;
 
 pub fn init(url: Option<String>, role: Vec<String>) -> Result<()> {
+    for r in &role {
+        crate::core::roles::check_name(r)?;
+    }
+
     // ProjectDirs::from("com", "organization", "application")
     let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
         .context("Could not determine project directories")?;
@@ -26,9 +30,23 @@ pub fn init(url: Option<String>, role: Vec<String>) -> Result<()> {
 
     fs::create_dir_all(config_dir).context("Failed to create config directory")?;
 
-    if let Some(u) = url {
+    // Roles first, so the machine record committed below includes them.
+    if !role.is_empty() {
+        use crate::core::state::LocalState;
+        let mut state = LocalState::load()?;
+        for r in role {
+            state.add_role(&r);
+            println!("Assigned role '{}' to this machine.", r);
+        }
+        state.save()?;
+    }
+
+    let record = if let Some(u) = url {
         println!("Cloning repository from {}...", u);
-        GitRepository::clone(&u, config_dir)?;
+        let repo = GitRepository::clone(&u, config_dir)?;
+        let record = crate::core::machines::register(config_dir, &repo, true)?;
+        repo.commit_all(&format!("Register machine {}", record.hostname))?;
+        record
     } else {
         println!("Initializing new repository at {:?}", config_dir);
         let repo = GitRepository::init(config_dir)?;
@@ -42,18 +60,14 @@ pub fn init(url: Option<String>, role: Vec<String>) -> Result<()> {
             println!("Created default configuration at {:?}", config_path);
         }
 
+        let record = crate::core::machines::register(config_dir, &repo, true)?;
         repo.commit_all("Initialize ConfigSync repository")?;
+        record
     };
-
-    if !role.is_empty() {
-        use crate::core::state::LocalState;
-        let mut state = LocalState::load()?;
-        for r in role {
-            state.add_role(&r);
-            println!("Assigned role '{}' to this machine.", r);
-        }
-        state.save()?;
-    }
+    println!(
+        "Registered this machine as {} ({}).",
+        record.hostname, record.id
+    );
 
     println!("Initialization complete. Applying configurations...");
     crate::core::engine::apply::apply()?;
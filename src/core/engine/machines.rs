@@ -0,0 +1,79 @@
+use crate::core::db::StateDb;
+use crate::core::git::repository::GitRepository;
+use crate::core::machines::{self, MACHINES_DIR, STALE_DAYS};
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+
+/// Lists the machines registered in the repository, how far each is behind
+/// HEAD, and which have not synced for a while.
+pub fn list() -> Result<()> {
+    let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+        .context("Could not determine project directories")?;
+    let config_dir = proj_dirs.config_dir();
+    if !config_dir.join("team-config.toml").exists() {
+        anyhow::bail!("ConfigSync not initialized. Run `configsync init` first.");
+    }
+
+    let repo = GitRepository::open(config_dir)?;
+    let records = machines::load_all(config_dir)?;
+    if records.is_empty() {
+        println!(
+            "No machines registered. Machines register on `configsync init` and `configsync push`."
+        );
+        return Ok(());
+    }
+    let this_machine = StateDb::open()?.machine_value("machine_id")?;
+    let now = chrono::Utc::now();
+
+    println!("{} machine(s) registered:", records.len());
+    for record in records {
+        let this = if this_machine.as_deref() == Some(record.id.as_str()) {
+            " (this machine)"
+        } else {
+            ""
+        };
+        let roles = if record.roles.is_empty() {
+            "(none)".to_string()
+        } else {
+            record.roles.join(", ")
+        };
+        println!(
+            "\n{} [{}]{}\n  {}/{}, configsync {}, roles: {}",
+            record.hostname,
+            record.id,
+            this,
+            record.os,
+            record.arch,
+            record.configsync_version,
+            roles
+        );
+
+        let commit = record.last_sync_commit.as_deref().unwrap_or("");
+        let short = &commit[..commit.len().min(7)];
+        // The commit that published the record includes everything the machine
+        // pushed with it, so count from there.
+        let relative = format!("{}/{}.toml", MACHINES_DIR, record.id);
+        let since = repo
+            .last_commit_touching(&relative)?
+            .or(record.last_sync_commit.clone());
+        let sync = match since
+            .as_deref()
+            .map(|c| repo.commits_behind(c, MACHINES_DIR))
+            .transpose()?
+            .flatten()
+        {
+            Some(0) => "up to date".to_string(),
+            Some(n) => format!("{} commit(s) behind HEAD", n),
+            None => "last sync commit is not in this history".to_string(),
+        };
+        println!("  last sync {} ({}): {}", record.last_sync_at, short, sync);
+
+        if let Ok(at) = chrono::DateTime::parse_from_rfc3339(&record.last_sync_at) {
+            let days = (now - at.with_timezone(&chrono::Utc)).num_days();
+            if days > STALE_DAYS {
+                println!("  ⚠️ stale: no sync for {} days", days);
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,16 @@
 pub mod add;
 pub mod apply;
+pub mod armor;
+pub mod backup;
+pub mod config;
+pub mod exec;
+pub mod explain;
 pub mod init;
+pub mod machines;
+pub mod prune;
 pub mod pull;
 pub mod push;
+pub mod role;
+pub mod rotate;
+pub mod secrets;
+pub mod status;
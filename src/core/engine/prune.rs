@@ -0,0 +1,116 @@
+use crate::core::config::schema::TeamConfig;
+use crate::core::db::{self, AppliedEntry, StateDb};
+use crate::core::fs::{secure, symlink};
+use crate::core::roles::RoleResolver;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What happens to an applied entry that no longer applies to this machine.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Stale {
+    /// Still exactly what apply wrote: safe to remove.
+    Remove,
+    /// The entry was `protect`ed; only reported.
+    Protected,
+    /// Edited or replaced since apply wrote it; only reported.
+    Modified,
+    /// Already gone from disk; only the record remains.
+    Gone,
+}
+
+/// Entries apply wrote on this machine that are no longer in `team-config.toml`,
+/// or no longer enabled by this machine's roles.
+pub fn stale_entries(
+    config: &TeamConfig,
+    resolver: &RoleResolver,
+    db: &StateDb,
+) -> Result<Vec<AppliedEntry>> {
+    let tracked: HashSet<String> = config
+        .files
+        .iter()
+        .filter(|f| resolver.matches(f))
+        .map(|f| shellexpand::tilde(&f.destination).into_owned())
+        .collect();
+    Ok(db
+        .applied_entries()?
+        .into_iter()
+        .filter(|entry| !tracked.contains(&entry.destination))
+        .collect())
+}
+
+pub fn classify(entry: &AppliedEntry, config_dir: &Path) -> Stale {
+    let dest = PathBuf::from(&entry.destination);
+    let Ok(metadata) = fs::symlink_metadata(&dest) else {
+        return Stale::Gone;
+    };
+    if entry.protect {
+        return Stale::Protected;
+    }
+
+    let unchanged = match entry.file_type.as_str() {
+        "file" | "directory" => {
+            metadata.file_type().is_symlink()
+                && fs::read_link(&dest).ok() == Some(config_dir.join(&entry.source))
+        }
+        "secretdir" => {
+            metadata.is_dir()
+                && crate::core::secret::archive::content_digest(&dest).ok() == entry.content_hash
+        }
+        _ => {
+            metadata.is_file()
+                && fs::read(&dest)
+                    .ok()
+                    .map(|data| db::content_hash(&zeroize::Zeroizing::new(data)))
+                    == entry.content_hash
+        }
+    };
+    if unchanged {
+        Stale::Remove
+    } else {
+        Stale::Modified
+    }
+}
+
+/// Removes links and decrypted copies left behind by entries that were
+/// deleted from the config or disabled for this machine, as long as they are
+/// untouched.
+pub fn prune(
+    config: &TeamConfig,
+    config_dir: &Path,
+    resolver: &RoleResolver,
+    db: &StateDb,
+) -> Result<()> {
+    for entry in stale_entries(config, resolver, db)? {
+        let dest = PathBuf::from(&entry.destination);
+        match classify(&entry, config_dir) {
+            Stale::Gone => db.forget_applied(&entry.destination)?,
+            Stale::Protected => println!(
+                "Keeping {:?}: no longer applies to this machine, but protected. Remove it manually.",
+                dest
+            ),
+            Stale::Modified => println!(
+                "Keeping {:?}: no longer applies to this machine, but changed since configsync wrote it.",
+                dest
+            ),
+            Stale::Remove => match remove_entry(&entry, db) {
+                Ok(()) => println!("Removed {:?} (no longer applies to this machine)", dest),
+                Err(e) => println!("Failed to remove stale {:?}: {:#}", dest, e),
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Deletes what apply wrote for `entry` (unlinking links, wiping copies) and
+/// forgets it. Callers check `classify` first.
+pub fn remove_entry(entry: &AppliedEntry, db: &StateDb) -> Result<()> {
+    let dest = PathBuf::from(&entry.destination);
+    match entry.file_type.as_str() {
+        "file" | "directory" => symlink::remove_symlink(&dest)?,
+        "secretdir" => secure::secure_delete_dir(&dest)?,
+        _ => secure::secure_delete(&dest)?,
+    }
+    db.forget_applied(&entry.destination)
+}
@@ -1,8 +1,10 @@
+use crate::core::config::loader::ConfigLoader;
 use crate::core::git::repository::GitRepository;
+use crate::core::scan;
 use anyhow::{Context, Result};
 use directories::ProjectDirs;
 
-pub fn push() -> Result<()> {
+pub fn push(allow_secrets: bool) -> Result<()> {
     let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
         .context("Could not determine project directories")?;
     let config_dir = proj_dirs.config_dir();
@@ -10,8 +12,35 @@ pub fn push() -> Result<()> {
     println!("Opening repository at {:?}", config_dir);
     let repo = GitRepository::open(config_dir)?;
 
-    println!("Committing changes...");
-    repo.commit_all("Update configurations (configsync)")?;
+    let pending = !repo.uncommitted_files()?.is_empty();
+    let config_path = config_dir.join("team-config.toml");
+    if config_path.exists() {
+        let config = ConfigLoader::load(&config_path)?;
+        let findings = scan::scan_changes(&repo, config_dir, &config.scan)?;
+        if !findings.is_empty() {
+            scan::report(&findings);
+            if !allow_secrets {
+                anyhow::bail!(
+                    "Refusing to push possible plaintext secrets. {}",
+                    scan::OVERRIDE_HINT
+                );
+            }
+            println!("Continuing because --allow-secrets was given.");
+        }
+    }
+
+    // Publish this machine's roles and sync point along with the changes,
+    // once nothing blocks them.
+    if let Err(e) = crate::core::machines::register(config_dir, &repo, pending) {
+        println!("Warning: Failed to update machine record: {:#}", e);
+    }
+
+    if repo.uncommitted_files()?.is_empty() {
+        println!("Nothing to commit.");
+    } else {
+        println!("Committing changes...");
+        repo.commit_all("Update configurations (configsync)")?;
+    }
 
     println!("Pushing to remote...");
     // Just warn on push failure for MVP (e.g. if no remote or offline)
@@ -0,0 +1,160 @@
+use crate::core::config::loader::ConfigLoader;
+use crate::core::config::schema::{FileConfig, TeamConfig};
+use crate::core::roles::{self, RoleResolver};
+use crate::core::state::LocalState;
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use std::collections::BTreeSet;
+
+fn load_config() -> Result<TeamConfig> {
+    let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+        .context("Could not determine project directories")?;
+    let config_path = proj_dirs.config_dir().join("team-config.toml");
+    if !config_path.exists() {
+        anyhow::bail!("ConfigSync not initialized. Run `configsync init` first.");
+    }
+    ConfigLoader::load(&config_path)
+}
+
+/// Entries whose role expressions mention `role`.
+fn entries_for<'a>(config: &'a TeamConfig, role: &str) -> Vec<&'a FileConfig> {
+    config
+        .files
+        .iter()
+        .filter(|f| roles::mentioned_roles(f).contains(role))
+        .collect()
+}
+
+/// Entries that apply under `after` but not under `before`.
+fn newly_enabled<'a>(
+    config: &'a TeamConfig,
+    before: &RoleResolver,
+    after: &RoleResolver,
+) -> Vec<&'a FileConfig> {
+    config
+        .files
+        .iter()
+        .filter(|f| after.matches(f) && !before.matches(f))
+        .collect()
+}
+
+fn print_entries(entries: &[&FileConfig]) {
+    for file in entries {
+        println!("  {} ({})", file.destination, file.file_type.as_str());
+    }
+}
+
+pub fn add(role: &str, apply: bool) -> Result<()> {
+    roles::check_name(role)?;
+    let config = load_config()?;
+    let mut state = LocalState::load()?;
+    let before = RoleResolver::for_machine(&state, &config);
+    if state.has_role(role) {
+        println!("This machine already has role '{}'.", role);
+    } else {
+        state.add_role(role);
+        state.save()?;
+        println!("Assigned role '{}' to this machine.", role);
+    }
+    let after = RoleResolver::for_machine(&state, &config);
+
+    let implied: Vec<_> = after
+        .active()
+        .filter(|(_, source)| *source == format!("implied by {}", role))
+        .map(|(name, _)| name)
+        .collect();
+    if !implied.is_empty() {
+        println!("Role '{}' implies: {}", role, implied.join(", "));
+    }
+
+    let entries = newly_enabled(&config, &before, &after);
+    if entries.is_empty() && entries_for(&config, role).is_empty() {
+        println!("No entries use role '{}' yet.", role);
+    } else {
+        println!("Role '{}' enables {} entries:", role, entries.len());
+        print_entries(&entries);
+    }
+
+    finish(apply)
+}
+
+pub fn remove(role: &str, apply: bool) -> Result<()> {
+    let config = load_config()?;
+    let mut state = LocalState::load()?;
+    let before = RoleResolver::for_machine(&state, &config);
+    if !state.remove_role(role) {
+        anyhow::bail!("This machine does not have role '{}'.", role);
+    }
+    state.save()?;
+    println!("Removed role '{}' from this machine.", role);
+
+    // Entries still enabled through another role stay.
+    let after = RoleResolver::for_machine(&state, &config);
+    let disabled = newly_enabled(&config, &after, &before);
+    if !disabled.is_empty() {
+        println!("{} entries no longer apply:", disabled.len());
+        print_entries(&disabled);
+    }
+
+    finish(apply)
+}
+
+fn finish(apply: bool) -> Result<()> {
+    if apply {
+        crate::core::engine::apply::apply()
+    } else {
+        println!("Run `configsync apply` to update links on this machine.");
+        Ok(())
+    }
+}
+
+/// Lists this machine's roles and every role used in the config, with the
+/// entries each one enables.
+pub fn list() -> Result<()> {
+    let config = load_config()?;
+    let state = LocalState::load()?;
+    let resolver = RoleResolver::for_machine(&state, &config);
+
+    let mut names: BTreeSet<String> = state.roles.iter().cloned().collect();
+    for (role, implied) in &config.roles {
+        names.insert(role.clone());
+        names.extend(implied.iter().cloned());
+    }
+    for file in &config.files {
+        names.extend(roles::mentioned_roles(file));
+    }
+
+    if state.roles.is_empty() {
+        println!("This machine has no roles.");
+    } else {
+        println!("This machine's roles: {}", state.roles.join(", "));
+    }
+    for role in &names {
+        let entries = entries_for(&config, role);
+        if resolver.is_active(role) {
+            println!(
+                "\n* {} ({}, {} entries)",
+                role,
+                resolver.source(role),
+                entries.len()
+            );
+        } else {
+            println!("\n  {} ({} entries)", role, entries.len());
+        }
+        if let Some(implied) = config.roles.get(role) {
+            println!("  implies: {}", implied.join(", "));
+        }
+        print_entries(&entries);
+    }
+
+    let shared = config
+        .files
+        .iter()
+        .filter(|f| f.roles.as_ref().is_none_or(|r| r.is_empty()))
+        .count();
+    println!(
+        "\n{} entries have no role and apply to every machine.",
+        shared
+    );
+    Ok(())
+}
@@ -0,0 +1,310 @@
+use crate::core::config::loader::ConfigLoader;
+use crate::core::config::schema::TeamConfig;
+use crate::core::git::repository::GitRepository;
+use crate::core::secret::{backend, cipher, keys, values};
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use std::fs;
+use std::path::PathBuf;
+
+pub fn rotate() -> Result<()> {
+    // 1. Locate repo/config
+    let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+        .context("Could not determine project directories")?;
+    let config_dir = proj_dirs.config_dir();
+    let config_path = config_dir.join("team-config.toml");
+
+    if !config_path.exists() {
+        anyhow::bail!("ConfigSync not initialized. Run `configsync init` first.");
+    }
+
+    let mut config = ConfigLoader::load(&config_path)?;
+    let original_config = fs::read(&config_path).context("Failed to read config file")?;
+
+    // 2. Load the current key
+    let old_identity = keys::load_key()
+        .context("Failed to load secret key. Have you run `configsync secrets init`?")?;
+    if let keys::SecretIdentity::Ssh { path, .. } = &old_identity {
+        anyhow::bail!(
+            "Secrets are using the SSH key {:?}. Rotation only replaces the configsync key file.",
+            path
+        );
+    }
+    let old_public = keys::get_public_key(&old_identity).to_string();
+    let key_path = keys::get_key_path()?;
+    let original_key = fs::read(&key_path).context("Failed to read key file")?;
+    let key_form = keys::key_form()?;
+
+    // 3. Decrypt everything in memory first; nothing is written unless all secrets decrypt.
+    let secrets = decrypt_all(config_dir, &config, &old_identity)?;
+
+    // 4. Generate the new key and re-encrypt in memory
+    let new_key = keys::generate_key()?;
+    let new_identity = keys::SecretIdentity::Age(
+        new_key
+            .parse::<age::x25519::Identity>()
+            .map_err(|e| anyhow::anyhow!("Failed to parse generated key: {}", e))?,
+    );
+    let new_public = keys::get_public_key(&new_identity).to_string();
+
+    let role_sets = config.secrets.role_recipients.values_mut().flatten();
+    for recipient in config.secrets.recipients.iter_mut().chain(role_sets) {
+        if recipient.trim() == old_public {
+            *recipient = new_public.clone();
+        }
+    }
+
+    let reencrypted = reencrypt_all(&secrets, &new_identity, &config)?;
+
+    // 5. Write files, key and config; roll everything back on the first failure.
+    let restore = |archived: Option<&PathBuf>| {
+        restore_secrets(&secrets);
+        if let Err(e) = fs::write(&config_path, &original_config) {
+            eprintln!("Failed to restore {:?}: {}", config_path, e);
+        }
+        if let Err(e) = fs::write(&key_path, &original_key) {
+            eprintln!("Failed to restore key file {:?}: {}", key_path, e);
+        }
+        // The key is still in place, so its archived copy would only be a duplicate.
+        if let Some(archived) = archived {
+            if let Err(e) = fs::remove_file(archived) {
+                eprintln!("Failed to remove archived key {:?}: {}", archived, e);
+            }
+        }
+    };
+
+    let mut archived = None;
+    let result = (|| -> Result<()> {
+        write_secrets(&secrets, &reencrypted)?;
+        ConfigLoader::save(&config, &config_path)?;
+
+        archived = Some(keys::archive_key()?);
+        match key_form {
+            keys::KeyForm::Plain => keys::save_key(&new_key)?,
+            keys::KeyForm::Passphrase => keys::save_protected_key(&new_key, &keys::passphrase()?)?,
+        }
+
+        let repo = GitRepository::open(config_dir)?;
+        repo.commit_all("Rotate secret key")?;
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => {
+            if let Some(archived) = &archived {
+                println!("Old key archived at {:?}", archived);
+            }
+            println!(
+                "Rotated key for {} secret(s). New public key: {}",
+                secrets.len(),
+                new_public
+            );
+            Ok(())
+        }
+        Err(e) => {
+            println!("Rotation failed, rolling back...");
+            restore(archived.as_ref());
+            Err(e)
+        }
+    }
+}
+
+/// Grants a teammate access by adding their age or SSH public key as a recipient
+/// (globally, or for one role's secrets) and re-encrypting every secret.
+pub fn grant(recipient: &str, role: Option<&str>) -> Result<()> {
+    let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+        .context("Could not determine project directories")?;
+    let config_dir = proj_dirs.config_dir();
+    let config_path = config_dir.join("team-config.toml");
+
+    if !config_path.exists() {
+        anyhow::bail!("ConfigSync not initialized. Run `configsync init` first.");
+    }
+
+    // Accept either the key itself or a path to a published key (e.g. `id_ed25519.pub`).
+    let recipient_path = PathBuf::from(shellexpand::tilde(recipient).into_owned());
+    let recipient = if recipient_path.is_file() {
+        fs::read_to_string(&recipient_path)
+            .with_context(|| format!("Failed to read {:?}", recipient_path))?
+    } else {
+        recipient.to_string()
+    };
+    let recipient = recipient.parse::<keys::SecretRecipient>()?.to_string();
+
+    let mut config = ConfigLoader::load(&config_path)?;
+    let original_config = fs::read(&config_path).context("Failed to read config file")?;
+    // A role without its own set is encrypted to the global recipients.
+    let current = role
+        .and_then(|role| config.secrets.role_recipients.get(role))
+        .unwrap_or(&config.secrets.recipients);
+    if current.iter().any(|r| r.trim() == recipient) {
+        match role {
+            Some(role) => println!(
+                "{} is already a recipient of `{}` secrets.",
+                recipient, role
+            ),
+            None => println!("{} is already a recipient.", recipient),
+        }
+        return Ok(());
+    }
+
+    let identity = keys::load_key()
+        .context("Failed to load secret key. Have you run `configsync secrets init`?")?;
+    let secrets = decrypt_all(config_dir, &config, &identity)?;
+
+    match role {
+        Some(role) => {
+            // A new set replaces the global recipients for the role's secrets, so
+            // it starts with them and this machine to keep everyone's access.
+            let global = &config.secrets.recipients;
+            let own = keys::get_public_key(&identity).to_string();
+            let set = config
+                .secrets
+                .role_recipients
+                .entry(role.to_string())
+                .or_insert_with(|| {
+                    let mut set = vec![own.clone()];
+                    set.extend(global.iter().filter(|r| r.trim() != own).cloned());
+                    set
+                });
+            set.push(recipient.clone());
+        }
+        None => config.secrets.recipients.push(recipient.clone()),
+    }
+    let reencrypted = reencrypt_all(&secrets, &identity, &config)?;
+
+    let result = (|| -> Result<()> {
+        write_secrets(&secrets, &reencrypted)?;
+        ConfigLoader::save(&config, &config_path)?;
+
+        let repo = GitRepository::open(config_dir)?;
+        repo.commit_all("Grant secret access to new recipient")?;
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        println!("Grant failed, rolling back...");
+        restore_secrets(&secrets);
+        if let Err(e) = fs::write(&config_path, &original_config) {
+            eprintln!("Failed to restore {:?}: {}", config_path, e);
+        }
+        return Err(e);
+    }
+
+    println!(
+        "Granted access to {} and re-encrypted {} secret(s).",
+        recipient,
+        secrets.len()
+    );
+    Ok(())
+}
+
+/// A tracked secret held in memory during re-encryption.
+struct DecryptedSecret {
+    path: PathBuf,
+    original: Vec<u8>,
+    plaintext: Vec<u8>,
+    roles: Option<Vec<String>>,
+}
+
+fn decrypt_all(
+    config_dir: &std::path::Path,
+    config: &TeamConfig,
+    identity: &keys::SecretIdentity,
+) -> Result<Vec<DecryptedSecret>> {
+    let own = keys::get_public_key(identity).to_string();
+    let mut secrets = Vec::new();
+    for file in &config.files {
+        if !backend::is_repo_secret(file, &config.secrets) {
+            continue;
+        }
+        let source_path = config_dir.join(&file.source);
+
+        // Role-scoped secrets whose recipient set excludes this machine were never
+        // readable here; leave them to a machine in the set.
+        if let Some(entries) =
+            keys::role_recipient_entries(&config.secrets, &config.roles, file.roles.as_deref())
+        {
+            if !entries.iter().any(|e| e.trim() == own) {
+                println!(
+                    "Skipping {:?}: this machine is not a recipient for roles {:?}",
+                    source_path,
+                    file.roles.as_deref().unwrap_or_default()
+                );
+                continue;
+            }
+        }
+
+        let encrypted = fs::read(&source_path)
+            .with_context(|| format!("Failed to read encrypted file {:?}", source_path))?;
+        let decrypted = cipher::decrypt(&encrypted, identity).with_context(|| {
+            format!(
+                "Failed to decrypt {:?} with the current key. Aborted; nothing was changed.",
+                source_path
+            )
+        })?;
+        secrets.push(DecryptedSecret {
+            path: source_path,
+            original: encrypted,
+            plaintext: decrypted,
+            roles: file.roles.clone(),
+        });
+    }
+
+    // Inline template values are encrypted to the global recipients.
+    let values_path = config_dir.join(values::VALUES_SOURCE);
+    if values_path.exists() {
+        let encrypted =
+            fs::read(&values_path).with_context(|| format!("Failed to read {:?}", values_path))?;
+        let decrypted = cipher::decrypt(&encrypted, identity).with_context(|| {
+            format!(
+                "Failed to decrypt {:?} with the current key. Aborted; nothing was changed.",
+                values_path
+            )
+        })?;
+        secrets.push(DecryptedSecret {
+            path: values_path,
+            original: encrypted,
+            plaintext: decrypted,
+            roles: None,
+        });
+    }
+    Ok(secrets)
+}
+
+fn reencrypt_all(
+    secrets: &[DecryptedSecret],
+    identity: &keys::SecretIdentity,
+    config: &TeamConfig,
+) -> Result<Vec<Vec<u8>>> {
+    secrets
+        .iter()
+        .map(|secret| {
+            let recipients = keys::recipients_for(
+                identity,
+                &config.secrets,
+                &config.roles,
+                secret.roles.as_deref(),
+            )?;
+            cipher::encrypt(&secret.plaintext, &recipients, config.secrets.armor)
+                .with_context(|| format!("Failed to re-encrypt {:?}. Aborted.", secret.path))
+        })
+        .collect()
+}
+
+fn restore_secrets(secrets: &[DecryptedSecret]) {
+    for secret in secrets {
+        if let Err(e) = fs::write(&secret.path, &secret.original) {
+            eprintln!("Failed to restore {:?}: {}", secret.path, e);
+        }
+    }
+}
+
+fn write_secrets(secrets: &[DecryptedSecret], reencrypted: &[Vec<u8>]) -> Result<()> {
+    for (secret, encrypted) in secrets.iter().zip(reencrypted) {
+        println!("Re-encrypting {:?}", secret.path);
+        fs::write(&secret.path, encrypted)
+            .with_context(|| format!("Failed to write encrypted file {:?}", secret.path))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,376 @@
+use crate::core::config::loader::ConfigLoader;
+use crate::core::config::schema::{FileConfig, FileType, TeamConfig};
+use crate::core::db::StateDb;
+use crate::core::fs::secure;
+use crate::core::git::repository::GitRepository;
+use crate::core::secret::values::{Values, VALUES_SOURCE};
+use crate::core::secret::{archive, backend, cipher, expiry, keys};
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use zeroize::Zeroizing;
+
+fn load_config() -> Result<(PathBuf, TeamConfig)> {
+    let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+        .context("Could not determine project directories")?;
+    let config_dir = proj_dirs.config_dir().to_path_buf();
+    let config_path = config_dir.join("team-config.toml");
+
+    if !config_path.exists() {
+        anyhow::bail!("ConfigSync not initialized. Run `configsync init` first.");
+    }
+
+    let config = ConfigLoader::load(&config_path)?;
+    Ok((config_dir, config))
+}
+
+fn find_secret(config: &TeamConfig, name: &str) -> Result<usize> {
+    config
+        .files
+        .iter()
+        .position(|f| f.file_type.is_secret() && f.matches_name(name))
+        .with_context(|| format!("No tracked secret named '{}'", name))
+}
+
+/// Prints every tracked secret with its recipients, last change and local state.
+pub fn list() -> Result<()> {
+    let (config_dir, config) = load_config()?;
+    let secrets: Vec<&FileConfig> = config
+        .files
+        .iter()
+        .filter(|f| f.file_type.is_secret())
+        .collect();
+
+    let has_values = config_dir.join(VALUES_SOURCE).exists();
+    if secrets.is_empty() && !has_values {
+        println!("No secrets tracked. Add one with `configsync secrets add <path>`.");
+        return Ok(());
+    }
+
+    let identity = if has_values
+        || secrets
+            .iter()
+            .any(|f| backend::is_repo_secret(f, &config.secrets))
+    {
+        keys::load_key().ok()
+    } else {
+        None
+    };
+
+    let today = expiry::today();
+    let repo = GitRepository::open(&config_dir).ok();
+    for file in secrets {
+        println!("{}", file.name());
+        println!("  destination: {}", file.destination);
+        print_expiry(file, today, repo.as_ref());
+
+        if !backend::is_repo_secret(file, &config.secrets) {
+            println!(
+                "  source:      {} ({})",
+                file.source,
+                backend::backend_name(file, &config.secrets)
+            );
+            println!("  local:       not compared (external backend)");
+            continue;
+        }
+
+        let source_path = config_dir.join(&file.source);
+        println!("  source:      {}", file.source);
+
+        let extra =
+            keys::role_recipient_entries(&config.secrets, &config.roles, file.roles.as_deref())
+                .unwrap_or_else(|| config.secrets.recipients.iter().collect());
+        if extra.is_empty() {
+            println!("  recipients:  key of the machine that added it");
+        } else {
+            println!("  recipients:  key of the machine that added it, plus:");
+            for recipient in extra {
+                println!("               {}", recipient);
+            }
+        }
+
+        match fs::metadata(&source_path).and_then(|m| m.modified()) {
+            Ok(modified) => println!(
+                "  modified:    {}",
+                chrono::DateTime::<chrono::Local>::from(modified).format("%Y-%m-%d %H:%M")
+            ),
+            Err(_) => println!("  modified:    missing from repository"),
+        }
+
+        println!(
+            "  local:       {}",
+            local_state(file, &source_path, identity.as_ref())
+        );
+    }
+
+    if has_values {
+        match identity.as_ref().map(|id| Values::load(&config_dir, id)) {
+            Some(Ok(values)) => {
+                let names: Vec<&str> = values.names().map(String::as_str).collect();
+                println!("Inline values ({}): {}", VALUES_SOURCE, names.join(", "));
+            }
+            Some(Err(e)) => println!(
+                "Inline values ({}): cannot decrypt ({:#})",
+                VALUES_SOURCE, e
+            ),
+            None => println!("Inline values ({}): not shown (no key)", VALUES_SOURCE),
+        }
+    }
+
+    Ok(())
+}
+
+fn print_expiry(file: &FileConfig, today: chrono::NaiveDate, repo: Option<&GitRepository>) {
+    if let Some(expires_at) = file.expires_at.as_deref() {
+        println!("  expires:     {}", expires_at);
+    }
+    let rotated = expiry::rotated_on(file, repo);
+    if let Some(every) = file.rotate_every.as_deref() {
+        let last = match (&rotated, file.rotated_at.as_deref()) {
+            (_, Some(rotated_at)) => rotated_at.to_string(),
+            (Ok(Some(date)), None) => format!("{}, from git history", date),
+            _ => "unknown".to_string(),
+        };
+        println!("  rotation:    every {} (last {})", every, last);
+    }
+    match rotated.and_then(|rotated| expiry::deadline(file, rotated)) {
+        Ok(Some(deadline)) => println!("  next:        {}", expiry::describe(&deadline, today)),
+        Ok(None) => {}
+        Err(e) => println!("  next:        {:#}", e),
+    }
+}
+
+fn local_state(
+    file: &FileConfig,
+    source_path: &Path,
+    identity: Option<&keys::SecretIdentity>,
+) -> String {
+    let dest_path = PathBuf::from(shellexpand::tilde(&file.destination).into_owned());
+    if !dest_path.exists() {
+        return "missing".to_string();
+    }
+    let Some(identity) = identity else {
+        return "not compared (no key)".to_string();
+    };
+    let plaintext = match fs::read(source_path)
+        .context("Failed to read encrypted file")
+        .and_then(|data| cipher::decrypt(&data, identity))
+    {
+        Ok(plaintext) => Zeroizing::new(plaintext),
+        Err(e) => return format!("cannot decrypt ({:#})", e),
+    };
+    if file.file_type == FileType::SecretDir {
+        return match archive::drifted(&plaintext, &dest_path) {
+            Ok(drifted) if drifted.is_empty() => "matches".to_string(),
+            Ok(drifted) => format!("{} file(s) differ from repository", drifted.len()),
+            Err(e) => format!("cannot read archive ({:#})", e),
+        };
+    }
+    let Ok(local) = fs::read(&dest_path).map(Zeroizing::new) else {
+        return "missing".to_string();
+    };
+    if *plaintext == *local {
+        "matches".to_string()
+    } else {
+        "differs from repository".to_string()
+    }
+}
+
+/// Decrypts a secret and writes it to stdout. Secret directories are listed
+/// (mode and path) instead.
+pub fn show(name: &str) -> Result<()> {
+    let (config_dir, config) = load_config()?;
+    let file = &config.files[find_secret(&config, name)?];
+
+    let mut backends = backend::Backends::new(&config_dir, &config.secrets);
+    let plaintext = Zeroizing::new(backends.fetch(file)?);
+    if file.file_type == FileType::SecretDir {
+        for (mode, path) in archive::list(&plaintext)? {
+            println!("{:o} {}", mode & 0o7777, path.display());
+        }
+        return Ok(());
+    }
+    let mut stdout = std::io::stdout();
+    stdout.write_all(&plaintext)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Decrypts a secret into a private temp file, opens `$EDITOR` and re-encrypts
+/// the result. The temp file is overwritten before it is removed.
+pub fn edit(name: &str) -> Result<()> {
+    let (config_dir, mut config) = load_config()?;
+    let index = find_secret(&config, name)?;
+    let file = &config.files[index];
+
+    if file.file_type == FileType::SecretDir {
+        anyhow::bail!(
+            "'{}' is a secret directory. Edit the files in {} and run `configsync secrets add {}` again.",
+            name,
+            file.destination,
+            file.destination
+        );
+    }
+    if !backend::is_repo_secret(file, &config.secrets) {
+        anyhow::bail!(
+            "Secret '{}' is stored in the '{}' backend; edit it there.",
+            name,
+            backend::backend_name(file, &config.secrets)
+        );
+    }
+
+    let identity = keys::load_key()
+        .context("Failed to load secret key. Have you run `configsync secrets init`?")?;
+    let source_path = config_dir.join(&file.source);
+    let encrypted = fs::read(&source_path).context("Failed to read encrypted file")?;
+    let plaintext = Zeroizing::new(cipher::decrypt(&encrypted, &identity)?);
+
+    // tempfile creates the file readable by the owner only.
+    let temp = tempfile::Builder::new()
+        .prefix("configsync-")
+        .suffix(&format!("-{}", file.name()))
+        .tempfile()
+        .context("Failed to create temporary file")?;
+    let temp_path = temp.into_temp_path();
+    let edited = fs::write(&temp_path, &*plaintext)
+        .context("Failed to write temporary file")
+        .and_then(|_| run_editor(&temp_path))
+        .and_then(|_| fs::read(&temp_path).context("Failed to read edited file"))
+        .map(Zeroizing::new);
+    secure::secure_delete(&temp_path)?;
+    let edited = edited?;
+
+    if *edited == *plaintext {
+        println!("No changes.");
+        return Ok(());
+    }
+
+    let recipients = keys::recipients_for(
+        &identity,
+        &config.secrets,
+        &config.roles,
+        file.roles.as_deref(),
+    )?;
+    let encrypted = cipher::encrypt(&edited, &recipients, config.secrets.armor)?;
+    fs::write(&source_path, encrypted).context("Failed to write encrypted file")?;
+
+    let source = file.source.clone();
+    if !config.secrets.encrypted_files.contains(&source) {
+        config.secrets.encrypted_files.push(source);
+    }
+    config.files[index].rotated_at = Some(expiry::today().to_string());
+    ConfigLoader::save(&config, config_dir.join("team-config.toml"))?;
+
+    println!(
+        "Secret '{}' updated. Run `configsync apply` to refresh the local copy.",
+        name
+    );
+    Ok(())
+}
+
+fn run_editor(path: &Path) -> Result<()> {
+    let editor = std::env::var("EDITOR")
+        .or_else(|_| std::env::var("VISUAL"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().context("$EDITOR is empty")?;
+    let status = Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to start editor {:?}", program))?;
+    if !status.success() {
+        anyhow::bail!("Editor exited with {}; secret left unchanged.", status);
+    }
+    Ok(())
+}
+
+/// Untracks a secret, deleting its encrypted copy and optionally the local plaintext.
+pub fn remove(name: &str, delete_local: bool) -> Result<()> {
+    let (config_dir, mut config) = load_config()?;
+    let index = find_secret(&config, name)?;
+    let is_repo_secret = backend::is_repo_secret(&config.files[index], &config.secrets);
+    let file = config.files.remove(index);
+
+    if is_repo_secret {
+        let source_path = config_dir.join(&file.source);
+        if source_path.exists() {
+            fs::remove_file(&source_path)
+                .with_context(|| format!("Failed to remove {:?}", source_path))?;
+        }
+    }
+    config
+        .secrets
+        .encrypted_files
+        .retain(|source| source != &file.source);
+    ConfigLoader::save(&config, config_dir.join("team-config.toml"))?;
+    println!("Removed secret '{}' from config.", name);
+
+    let dest_path = PathBuf::from(shellexpand::tilde(&file.destination).into_owned());
+    // The local copy is now the user's: apply must not prune it on this machine.
+    StateDb::open()?.forget_applied(&dest_path.to_string_lossy())?;
+
+    if delete_local {
+        if dest_path.is_file() {
+            secure::secure_delete(&dest_path)?;
+            println!("Deleted local copy {:?}", dest_path);
+        } else if dest_path.is_dir() && file.file_type == FileType::SecretDir {
+            secure::secure_delete_dir(&dest_path)?;
+            println!("Deleted local copy {:?}", dest_path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Stores an inline value for template placeholders in `secrets/values.age`.
+pub fn set_value(name: &str, value: Option<String>) -> Result<()> {
+    let (config_dir, config) = load_config()?;
+    let identity = keys::load_key()
+        .context("Failed to load secret key. Have you run `configsync secrets init`?")?;
+    let value = match value {
+        Some(value) => value,
+        None => read_value(name)?,
+    };
+
+    let mut values = Values::load(&config_dir, &identity)?;
+    values.set(name, value);
+    values.save(&config_dir, &identity, &config.secrets)?;
+    println!(
+        "Stored value '{}'. Reference it in a template as {{{{ secret \"{}\" }}}}.",
+        name, name
+    );
+    Ok(())
+}
+
+fn read_value(name: &str) -> Result<String> {
+    use std::io::IsTerminal;
+    if std::io::stdin().is_terminal() {
+        return rpassword::prompt_password(format!("Value for '{}': ", name))
+            .context("Failed to read value");
+    }
+    let mut value = String::new();
+    std::io::stdin()
+        .read_to_string(&mut value)
+        .context("Failed to read value from stdin")?;
+    let trimmed = value.trim_end_matches(['\n', '\r']).len();
+    value.truncate(trimmed);
+    Ok(value)
+}
+
+/// Removes an inline value. Templates still referencing it fail to render.
+pub fn unset_value(name: &str) -> Result<()> {
+    let (config_dir, config) = load_config()?;
+    let identity = keys::load_key()
+        .context("Failed to load secret key. Have you run `configsync secrets init`?")?;
+    let mut values = Values::load(&config_dir, &identity)?;
+    if !values.remove(name) {
+        anyhow::bail!("No inline value named '{}'", name);
+    }
+    values.save(&config_dir, &identity, &config.secrets)?;
+    println!("Removed value '{}'.", name);
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use crate::core::config::loader::ConfigLoader;
+use crate::core::db::StateDb;
+use crate::core::git::repository::GitRepository;
+use crate::core::roles::RoleResolver;
+use crate::core::secret::expiry;
+use crate::core::state::LocalState;
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+
+/// Short overview: roles, tracked entries, pending changes and secret reminders.
+pub fn status() -> Result<()> {
+    let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+        .context("Could not determine project directories")?;
+    let config_dir = proj_dirs.config_dir();
+    let config_path = config_dir.join("team-config.toml");
+
+    if !config_path.exists() {
+        anyhow::bail!("ConfigSync not initialized. Run `configsync init` first.");
+    }
+
+    let config = ConfigLoader::load(&config_path)?;
+    let db = StateDb::open()?;
+    let state = LocalState::from_db(&db)?;
+
+    println!("Repository: {:?}", config_dir);
+    let machine = db.machine()?;
+    let get = |key: &str| machine.get(key).map(String::as_str).unwrap_or("?");
+    println!(
+        "Machine: {} ({}/{}), id {}",
+        get("hostname"),
+        get("os"),
+        get("arch"),
+        get("machine_id")
+    );
+    if state.roles.is_empty() {
+        println!("Machine roles: (none)");
+    } else {
+        println!("Machine roles: {}", state.roles.join(", "));
+    }
+    let resolver = RoleResolver::for_machine(&state, &config);
+    let active: Vec<String> = resolver
+        .active()
+        .filter(|(_, source)| *source != "assigned")
+        .map(|(role, source)| format!("{} ({})", role, source))
+        .collect();
+    println!("Also active: {}", active.join(", "));
+
+    let secrets = config
+        .files
+        .iter()
+        .filter(|f| f.file_type.is_secret())
+        .count();
+    println!(
+        "Tracked: {} file(s), {} secret(s); {} apply to this machine",
+        config.files.len() - secrets,
+        secrets,
+        config.files.iter().filter(|f| resolver.matches(f)).count()
+    );
+
+    match db.machine_value("last_apply_at")? {
+        Some(at) => {
+            let commit = db.machine_value("last_apply_commit")?.unwrap_or_default();
+            let commit = if commit.is_empty() {
+                String::new()
+            } else {
+                format!(" from commit {}", &commit[..commit.len().min(7)])
+            };
+            println!(
+                "Last apply: {}{} ({} entries on this machine)",
+                at,
+                commit,
+                db.applied_entries()?.len()
+            );
+        }
+        None => println!("Last apply: never (run `configsync apply`)"),
+    }
+
+    match GitRepository::open(config_dir).and_then(|repo| repo.changed_files()) {
+        Ok(changed) if changed.is_empty() => println!("No uncommitted changes."),
+        Ok(changed) => println!(
+            "{} uncommitted change(s). Run `configsync push` to sync them.",
+            changed.len()
+        ),
+        Err(e) => println!("Could not read repository status: {:#}", e),
+    }
+
+    let repo = GitRepository::open(config_dir).ok();
+    let (reminders, _) = expiry::print_reminders(&config, &resolver, repo.as_ref())?;
+    if reminders == 0 {
+        println!("No secrets expiring or due for rotation soon.");
+    }
+
+    Ok(())
+}
@@ -1 +1,3 @@
+pub mod perms;
+pub mod secure;
 pub mod symlink;
@@ -0,0 +1,186 @@
+use crate::core::config::schema::{FileConfig, FileType};
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Mode applied to secrets that have none recorded (entries added before modes were tracked).
+pub const DEFAULT_SECRET_MODE: u32 = 0o600;
+
+pub fn parse_mode(mode: &str) -> Result<u32> {
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|m| *m <= 0o7777)
+        .with_context(|| {
+            format!(
+                "Invalid file mode '{}' (expected octal, e.g. \"640\")",
+                mode
+            )
+        })
+}
+
+pub fn format_mode(mode: u32) -> String {
+    format!("{:o}", mode & 0o7777)
+}
+
+/// The mode a secret destination should have: its recorded mode (or 600; 700 for
+/// directories), with group/other access removed for `sensitive` entries.
+pub fn expected_mode(file: &FileConfig) -> Result<u32> {
+    let owner_floor = if file.file_type == FileType::SecretDir {
+        0o700
+    } else {
+        DEFAULT_SECRET_MODE
+    };
+    let mode = match file.mode.as_deref() {
+        Some(mode) => parse_mode(mode)?,
+        None => owner_floor,
+    };
+    if file.sensitive {
+        Ok((mode | owner_floor) & 0o700)
+    } else {
+        Ok(mode)
+    }
+}
+
+/// Sets the expected mode and, when recorded, owner and group on `path`.
+#[cfg(unix)]
+pub fn apply(path: &Path, file: &FileConfig) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = expected_mode(file)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to set mode {} on {:?}", format_mode(mode), path))?;
+
+    if file.owner.is_some() || file.group.is_some() {
+        let uid = file.owner.as_deref().map(uid_for).transpose()?;
+        let gid = file.group.as_deref().map(gid_for).transpose()?;
+        std::os::unix::fs::chown(path, uid, gid).with_context(|| {
+            format!(
+                "Failed to set owner {}:{} on {:?}",
+                file.owner.as_deref().unwrap_or("-"),
+                file.group.as_deref().unwrap_or("-"),
+                path
+            )
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn apply(_path: &Path, _file: &FileConfig) -> Result<()> {
+    Ok(())
+}
+
+/// Describes every way `path` differs from the recorded mode, owner and group.
+#[cfg(unix)]
+pub fn check(path: &Path, file: &FileConfig) -> Result<Vec<String>> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    let metadata = std::fs::metadata(path)?;
+    let mut problems = Vec::new();
+
+    let expected = expected_mode(file)?;
+    let actual = metadata.permissions().mode() & 0o7777;
+    if actual & !expected != 0 {
+        problems.push(format!(
+            "permissions unsafe: {} (Should be {})",
+            format_mode(actual),
+            format_mode(expected)
+        ));
+    } else if actual != expected {
+        problems.push(format!(
+            "permissions changed: {} (Should be {})",
+            format_mode(actual),
+            format_mode(expected)
+        ));
+    }
+
+    if let Some(owner) = file.owner.as_deref() {
+        if uid_for(owner)? != metadata.uid() {
+            problems.push(format!(
+                "owner is {} (Should be {})",
+                user_name(metadata.uid()),
+                owner
+            ));
+        }
+    }
+    if let Some(group) = file.group.as_deref() {
+        if gid_for(group)? != metadata.gid() {
+            problems.push(format!(
+                "group is {} (Should be {})",
+                group_name(metadata.gid()),
+                group
+            ));
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(not(unix))]
+pub fn check(_path: &Path, _file: &FileConfig) -> Result<Vec<String>> {
+    Ok(Vec::new())
+}
+
+/// The mode of `path` as recorded in config, e.g. "640".
+#[cfg(unix)]
+pub fn recorded_mode(path: &Path) -> Result<Option<String>> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(path)?.permissions().mode();
+    Ok(Some(format_mode(mode)))
+}
+
+#[cfg(not(unix))]
+pub fn recorded_mode(_path: &Path) -> Result<Option<String>> {
+    Ok(None)
+}
+
+/// Resolves a user name (or numeric uid) to a uid.
+#[cfg(unix)]
+pub fn uid_for(name: &str) -> Result<u32> {
+    if let Ok(uid) = name.parse() {
+        return Ok(uid);
+    }
+    let c_name = std::ffi::CString::new(name)?;
+    // SAFETY: getpwnam returns NULL or a pointer to a static passwd record,
+    // which is read immediately.
+    let entry = unsafe { libc::getpwnam(c_name.as_ptr()) };
+    if entry.is_null() {
+        anyhow::bail!("Unknown user '{}'", name);
+    }
+    Ok(unsafe { (*entry).pw_uid })
+}
+
+/// Resolves a group name (or numeric gid) to a gid.
+#[cfg(unix)]
+pub fn gid_for(name: &str) -> Result<u32> {
+    if let Ok(gid) = name.parse() {
+        return Ok(gid);
+    }
+    let c_name = std::ffi::CString::new(name)?;
+    // SAFETY: as for getpwnam above.
+    let entry = unsafe { libc::getgrnam(c_name.as_ptr()) };
+    if entry.is_null() {
+        anyhow::bail!("Unknown group '{}'", name);
+    }
+    Ok(unsafe { (*entry).gr_gid })
+}
+
+#[cfg(unix)]
+fn user_name(uid: u32) -> String {
+    // SAFETY: getpwuid returns NULL or a pointer to a static passwd record.
+    let entry = unsafe { libc::getpwuid(uid) };
+    if entry.is_null() {
+        return uid.to_string();
+    }
+    unsafe { std::ffi::CStr::from_ptr((*entry).pw_name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(unix)]
+fn group_name(gid: u32) -> String {
+    // SAFETY: getgrgid returns NULL or a pointer to a static group record.
+    let entry = unsafe { libc::getgrgid(gid) };
+    if entry.is_null() {
+        return gid.to_string();
+    }
+    unsafe { std::ffi::CStr::from_ptr((*entry).gr_name) }
+        .to_string_lossy()
+        .into_owned()
+}
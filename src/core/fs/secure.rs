@@ -0,0 +1,40 @@
+use anyhow::{Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// Overwrites a file with zeros and syncs it before unlinking, so decrypted
+/// content does not linger in the freed blocks.
+pub fn secure_delete(path: &Path) -> Result<()> {
+    let len = fs::metadata(path)
+        .with_context(|| format!("Failed to inspect {:?}", path))?
+        .len();
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {:?} for wiping", path))?;
+    let zeros = vec![0u8; 8192];
+    let mut remaining = len;
+    while remaining > 0 {
+        let chunk = remaining.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    file.sync_all()?;
+    drop(file);
+    fs::remove_file(path).with_context(|| format!("Failed to remove {:?}", path))
+}
+
+/// `secure_delete` for every regular file under `dir`, then removes the tree.
+pub fn secure_delete_dir(dir: &Path) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            secure_delete_dir(&entry.path())?;
+        } else if file_type.is_file() {
+            secure_delete(&entry.path())?;
+        }
+    }
+    fs::remove_dir_all(dir).with_context(|| format!("Failed to remove {:?}", dir))
+}
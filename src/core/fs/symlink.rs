@@ -47,3 +47,13 @@ pub fn create_symlink<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> a
 
     Ok(())
 }
+
+/// Removes a link created by `create_symlink` without touching its target.
+pub fn remove_symlink<P: AsRef<Path>>(link: P) -> anyhow::Result<()> {
+    let link = link.as_ref();
+    // Directory symlinks and junctions on Windows are removed as directories.
+    if fs::remove_file(link).is_err() {
+        fs::remove_dir(link).context(format!("Failed to remove link {:?}", link))?;
+    }
+    Ok(())
+}
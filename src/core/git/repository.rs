@@ -1,6 +1,9 @@
 use anyhow::{Context, Result};
 use git2::Repository;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// Commits `unpushed_files` reads for a branch that was never pushed.
+const UNPUSHED_SCAN_LIMIT: usize = 50;
 
 pub struct GitRepository {
     repo: Repository,
@@ -112,6 +115,282 @@ impl GitRepository {
         Ok(())
     }
 
+    /// Id of the commit HEAD points to, or `None` before the first commit.
+    pub fn head_commit(&self) -> Option<String> {
+        self.repo
+            .head()
+            .ok()
+            .and_then(|head| head.target())
+            .map(|oid| oid.to_string())
+    }
+
+    /// Newest commit on HEAD that added or changed `path` (relative to the work tree).
+    pub fn last_commit_touching(&self, path: &str) -> Result<Option<String>> {
+        let Ok(head) = self.repo.head().and_then(|h| h.peel_to_commit()) else {
+            return Ok(None);
+        };
+        let mut walk = self.repo.revwalk().context("Failed to walk history")?;
+        walk.push(head.id())?;
+        for oid in walk {
+            let commit = self.repo.find_commit(oid?)?;
+            let parent_tree = match commit.parent(0) {
+                Ok(parent) => Some(parent.tree()?),
+                Err(_) => None,
+            };
+            let mut opts = git2::DiffOptions::new();
+            opts.pathspec(path);
+            let diff = self.repo.diff_tree_to_tree(
+                parent_tree.as_ref(),
+                Some(&commit.tree()?),
+                Some(&mut opts),
+            )?;
+            if diff.deltas().len() > 0 {
+                return Ok(Some(commit.id().to_string()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Commit time of `id` in seconds since the Unix epoch.
+    pub fn commit_time(&self, id: &str) -> Option<i64> {
+        let oid = git2::Oid::from_str(id).ok()?;
+        Some(self.repo.find_commit(oid).ok()?.time().seconds())
+    }
+
+    /// Number of commits on HEAD after `since` that change anything outside
+    /// `ignore_dir` (so machines registering themselves do not count), or
+    /// `None` when `since` is not in this repository's history.
+    pub fn commits_behind(&self, since: &str, ignore_dir: &str) -> Result<Option<usize>> {
+        let Ok(since) = git2::Oid::from_str(since) else {
+            return Ok(None);
+        };
+        let Ok(head) = self.repo.head().and_then(|h| h.peel_to_commit()) else {
+            return Ok(None);
+        };
+        if self.repo.find_commit(since).is_err() {
+            return Ok(None);
+        }
+        if head.id() != since && !self.repo.graph_descendant_of(head.id(), since)? {
+            return Ok(None);
+        }
+
+        let mut walk = self.repo.revwalk().context("Failed to walk history")?;
+        walk.push(head.id())?;
+        walk.hide(since)?;
+        let prefix = format!("{}/", ignore_dir);
+        let mut behind = 0;
+        for oid in walk {
+            let commit = self.repo.find_commit(oid?)?;
+            let tree = commit.tree()?;
+            let parent_tree = match commit.parent(0) {
+                Ok(parent) => Some(parent.tree()?),
+                Err(_) => None,
+            };
+            let diff = self
+                .repo
+                .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+            let relevant = diff.deltas().any(|delta| {
+                [delta.old_file().path(), delta.new_file().path()]
+                    .into_iter()
+                    .flatten()
+                    .any(|path| !path.to_string_lossy().starts_with(&prefix))
+            });
+            if relevant {
+                behind += 1;
+            }
+        }
+        Ok(Some(behind))
+    }
+
+    /// Paths (relative to the work tree) of new or modified files that the next
+    /// `commit_all` would record. Deleted files are not included.
+    pub fn changed_files(&self) -> Result<Vec<PathBuf>> {
+        let mut opts = git2::StatusOptions::new();
+        opts.include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(false);
+        let statuses = self
+            .repo
+            .statuses(Some(&mut opts))
+            .context("Failed to read repository status")?;
+        Ok(statuses
+            .iter()
+            .filter(|entry| {
+                !entry
+                    .status()
+                    .intersects(git2::Status::WT_DELETED | git2::Status::INDEX_DELETED)
+            })
+            .filter_map(|entry| entry.path().map(PathBuf::from))
+            .collect())
+    }
+
+    /// Every uncommitted change, including deletions and untracked files.
+    pub fn uncommitted_files(&self) -> Result<Vec<PathBuf>> {
+        let mut opts = git2::StatusOptions::new();
+        opts.include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(false);
+        let statuses = self
+            .repo
+            .statuses(Some(&mut opts))
+            .context("Failed to read repository status")?;
+        Ok(statuses
+            .iter()
+            .filter_map(|entry| entry.path().map(PathBuf::from))
+            .collect())
+    }
+
+    /// Files in the index plus untracked (not ignored) files, relative to the work tree.
+    pub fn files(&self) -> Result<Vec<PathBuf>> {
+        let index = self.repo.index().context("Failed to read git index")?;
+        let mut files: Vec<PathBuf> = index
+            .iter()
+            .map(|entry| PathBuf::from(String::from_utf8_lossy(&entry.path).into_owned()))
+            .collect();
+        let mut opts = git2::StatusOptions::new();
+        opts.include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(false);
+        let statuses = self
+            .repo
+            .statuses(Some(&mut opts))
+            .context("Failed to read repository status")?;
+        files.extend(
+            statuses
+                .iter()
+                .filter(|entry| entry.status().contains(git2::Status::WT_NEW))
+                .filter_map(|entry| entry.path().map(PathBuf::from)),
+        );
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+
+    /// URL of the `origin` remote, if one is configured.
+    pub fn origin_url(&self) -> Option<String> {
+        self.repo
+            .find_remote("origin")
+            .ok()
+            .and_then(|remote| remote.url().map(str::to_string))
+    }
+
+    pub fn is_head_detached(&self) -> bool {
+        self.repo.head_detached().unwrap_or(false)
+    }
+
+    /// The git operation left unfinished in the work tree ("merge", "rebase", ...).
+    pub fn operation_in_progress(&self) -> Option<&'static str> {
+        use git2::RepositoryState as State;
+        match self.repo.state() {
+            State::Clean => None,
+            State::Merge => Some("merge"),
+            State::Revert | State::RevertSequence => Some("revert"),
+            State::CherryPick | State::CherryPickSequence => Some("cherry-pick"),
+            State::Bisect => Some("bisect"),
+            State::Rebase | State::RebaseInteractive | State::RebaseMerge => Some("rebase"),
+            State::ApplyMailbox | State::ApplyMailboxOrRebase => Some("am"),
+        }
+    }
+
+    /// The branch's upstream on origin and how many commits HEAD is ahead of
+    /// and behind it, as of the last fetch. `None` when HEAD is not a branch
+    /// or the upstream has never been fetched.
+    pub fn ahead_behind(&self) -> Result<Option<(String, usize, usize)>> {
+        if self.is_head_detached() {
+            return Ok(None);
+        }
+        let Ok(head) = self.repo.head() else {
+            return Ok(None);
+        };
+        let Some(local) = head.target() else {
+            return Ok(None);
+        };
+        let Some((upstream, remote)) = self.upstream(head) else {
+            return Ok(None);
+        };
+        let (ahead, behind) = self
+            .repo
+            .graph_ahead_behind(local, remote)
+            .context("Failed to compare with upstream")?;
+        let name = upstream
+            .strip_prefix("refs/remotes/")
+            .unwrap_or(&upstream)
+            .to_string();
+        Ok(Some((name, ahead, behind)))
+    }
+
+    /// The upstream branch of `head` (or `origin/<branch>`) and its commit.
+    fn upstream(&self, head: git2::Reference) -> Option<(String, git2::Oid)> {
+        let branch = head.shorthand().unwrap_or("main").to_string();
+        let upstream = git2::Branch::wrap(head)
+            .upstream()
+            .ok()
+            .and_then(|b| b.get().name().map(str::to_string))
+            .unwrap_or_else(|| format!("refs/remotes/origin/{}", branch));
+        let target = self.repo.find_reference(&upstream).ok()?.target()?;
+        Some((upstream, target))
+    }
+
+    /// Files added or modified by commits that the next `push` would publish
+    /// (`origin/<branch>..HEAD`), with their committed contents. When the
+    /// branch has no upstream, commits already on any `origin` branch are
+    /// skipped and at most `UNPUSHED_SCAN_LIMIT` commits are read. Empty
+    /// without an origin remote.
+    pub fn unpushed_files(&self) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+        if !self.has_origin_remote() {
+            return Ok(Vec::new());
+        }
+        let Ok(head) = self.repo.head() else {
+            return Ok(Vec::new());
+        };
+        let Some(local) = head.target() else {
+            return Ok(Vec::new());
+        };
+
+        let mut walk = self.repo.revwalk().context("Failed to walk history")?;
+        walk.push(local)?;
+        let limit = match self.upstream(head) {
+            Some((_, remote)) => {
+                walk.hide(remote)?;
+                usize::MAX
+            }
+            None => {
+                walk.hide_glob("refs/remotes/origin/*")?;
+                UNPUSHED_SCAN_LIMIT
+            }
+        };
+
+        let mut seen = std::collections::HashSet::new();
+        let mut files = Vec::new();
+        for oid in walk.take(limit) {
+            let commit = self.repo.find_commit(oid?)?;
+            let tree = commit.tree()?;
+            let parent_tree = match commit.parent(0) {
+                Ok(parent) => Some(parent.tree()?),
+                Err(_) => None,
+            };
+            let diff = self
+                .repo
+                .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+            for delta in diff.deltas() {
+                if delta.status() == git2::Delta::Deleted {
+                    continue;
+                }
+                let file = delta.new_file();
+                let Some(path) = file.path() else {
+                    continue;
+                };
+                if !seen.insert(file.id()) {
+                    continue;
+                }
+                if let Ok(blob) = self.repo.find_blob(file.id()) {
+                    files.push((path.to_path_buf(), blob.content().to_vec()));
+                }
+            }
+        }
+        Ok(files)
+    }
+
     pub fn push(&self) -> Result<()> {
         if !self.has_origin_remote() {
             anyhow::bail!(
@@ -0,0 +1,135 @@
+use crate::core::db::{self, StateDb};
+use crate::core::git::repository::GitRepository;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory in the repository holding one `<machine-id>.toml` per machine.
+pub const MACHINES_DIR: &str = "machines";
+
+/// Machines that have not synced for this many days are reported as stale.
+pub const STALE_DAYS: i64 = 30;
+
+/// A push with nothing else to commit still refreshes a record this old, so
+/// a machine that syncs regularly never looks stale.
+pub const REFRESH_DAYS: i64 = 7;
+
+/// What a machine publishes about itself on `init` and `push`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MachineRecord {
+    pub id: String,
+    pub hostname: String,
+    pub os: String,
+    pub arch: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    pub configsync_version: String,
+    /// HEAD of this machine's repository when it last synced.
+    #[serde(default)]
+    pub last_sync_commit: Option<String>,
+    /// RFC 3339 time of the last sync.
+    pub last_sync_at: String,
+}
+
+pub fn record_path(config_dir: &Path, id: &str) -> PathBuf {
+    config_dir.join(MACHINES_DIR).join(format!("{}.toml", id))
+}
+
+impl MachineRecord {
+    /// True when both records describe the machine the same way, ignoring
+    /// when it last synced.
+    fn same_machine(&self, other: &MachineRecord) -> bool {
+        self.id == other.id
+            && self.hostname == other.hostname
+            && self.os == other.os
+            && self.arch == other.arch
+            && self.roles == other.roles
+            && self.configsync_version == other.configsync_version
+    }
+
+    /// True when `last_sync_at` is less than `days` old.
+    fn synced_within(&self, days: i64) -> bool {
+        chrono::DateTime::parse_from_rfc3339(&self.last_sync_at)
+            .is_ok_and(|at| (chrono::Utc::now() - at.with_timezone(&chrono::Utc)).num_days() < days)
+    }
+}
+
+/// Writes this machine's record into the repository (uncommitted). Without
+/// `sync`, an existing record is only rewritten when the machine itself
+/// changed or its last sync is `REFRESH_DAYS` old, so most pushes with
+/// nothing else to commit stay no-ops.
+pub fn register(config_dir: &Path, repo: &GitRepository, sync: bool) -> Result<MachineRecord> {
+    let db = StateDb::open()?;
+    db.record_machine()?;
+    let machine = db.machine()?;
+    let get = |key: &str| machine.get(key).cloned().unwrap_or_default();
+    let record = MachineRecord {
+        id: get("machine_id"),
+        hostname: get("hostname"),
+        os: get("os"),
+        arch: get("arch"),
+        roles: db.roles()?,
+        configsync_version: get("configsync_version"),
+        last_sync_commit: repo.head_commit(),
+        last_sync_at: db::now(),
+    };
+    if record.id.is_empty() {
+        anyhow::bail!("Local state has no machine id");
+    }
+
+    let path = record_path(config_dir, &record.id);
+    if !sync {
+        let existing = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| toml::from_str::<MachineRecord>(&content).ok());
+        if let Some(existing) = existing.filter(|existing| {
+            existing.same_machine(&record) && existing.synced_within(REFRESH_DAYS)
+        }) {
+            return Ok(existing);
+        }
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Failed to create machines directory")?;
+    }
+    let content = toml::to_string_pretty(&record).context("Failed to serialize machine record")?;
+    fs::write(&path, content).context("Failed to write machine record")?;
+    Ok(record)
+}
+
+/// Every machine record in the repository, sorted by hostname.
+pub fn load_all(config_dir: &Path) -> Result<Vec<MachineRecord>> {
+    let dir = config_dir.join(MACHINES_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut records = Vec::new();
+    for entry in fs::read_dir(&dir).context("Failed to read machines directory")? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("toml") {
+            continue;
+        }
+        let content =
+            fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        match toml::from_str::<MachineRecord>(&content) {
+            Ok(record) => records.push(record),
+            Err(e) => println!("Warning: ignoring invalid machine record {:?}: {}", path, e),
+        }
+    }
+    records.sort_by(|a, b| a.hostname.cmp(&b.hostname).then(a.id.cmp(&b.id)));
+    Ok(records)
+}
+
+/// Roles of every registered machine. Unreadable records are skipped
+/// silently; `machines` reports them.
+pub fn known_roles(config_dir: &Path) -> BTreeSet<String> {
+    let Ok(entries) = fs::read_dir(config_dir.join(MACHINES_DIR)) else {
+        return BTreeSet::new();
+    };
+    entries
+        .filter_map(|entry| fs::read_to_string(entry.ok()?.path()).ok())
+        .filter_map(|content| toml::from_str::<MachineRecord>(&content).ok())
+        .flat_map(|record| record.roles)
+        .collect()
+}
@@ -1,12 +1,15 @@
 pub mod config;
+pub mod db;
 pub mod doctor;
 pub mod engine;
 pub mod fs;
 pub mod git;
+pub mod machines;
+pub mod roles;
+pub mod scan;
 pub mod secret;
 pub mod state;
 pub mod watch;
 // pub mod watcher;
-// pub mod db;
 // pub mod secret;
 // pub mod conflict;
@@ -0,0 +1,641 @@
+use crate::core::config::schema::{FileConfig, TeamConfig};
+use crate::core::state::LocalState;
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// A boolean expression over role names, as written in an entry's `roles`
+/// list: `work`, `work && linux && !ci`, `(work || personal) && laptop`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoleExpr {
+    Role(String),
+    Not(Box<RoleExpr>),
+    And(Vec<RoleExpr>),
+    Or(Vec<RoleExpr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Name(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':')
+}
+
+/// Rejects role names that could not be written in a role expression, such as
+/// `a&&b` or `!ci`.
+pub fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(is_name_char) {
+        anyhow::bail!(
+            "Invalid role name `{}`: use letters, digits, `-`, `_`, `.` and `:` only",
+            name
+        );
+    }
+    Ok(())
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '!' => {
+                chars.next();
+                tokens.push(Token::Not);
+            }
+            '&' | '|' => {
+                chars.next();
+                if chars.next() != Some(c) {
+                    anyhow::bail!("expected `{}{}`", c, c);
+                }
+                tokens.push(if c == '&' { Token::And } else { Token::Or });
+            }
+            c if is_name_char(c) => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !is_name_char(c) {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Name(name));
+            }
+            other => anyhow::bail!("unexpected character `{}`", other),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    // or := and ("||" and)*
+    fn or(&mut self) -> Result<RoleExpr> {
+        let mut terms = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            terms.push(self.and()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            RoleExpr::Or(terms)
+        })
+    }
+
+    // and := unary ("&&" unary)*
+    fn and(&mut self) -> Result<RoleExpr> {
+        let mut terms = vec![self.unary()?];
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            terms.push(self.unary()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            RoleExpr::And(terms)
+        })
+    }
+
+    // unary := "!" unary | "(" or ")" | name
+    fn unary(&mut self) -> Result<RoleExpr> {
+        match self.next() {
+            Some(Token::Not) => Ok(RoleExpr::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                if self.next() != Some(Token::Close) {
+                    anyhow::bail!("missing `)`");
+                }
+                Ok(expr)
+            }
+            Some(Token::Name(name)) => Ok(RoleExpr::Role(name)),
+            Some(_) => anyhow::bail!("expected a role name"),
+            None => anyhow::bail!("unexpected end of expression"),
+        }
+    }
+}
+
+impl RoleExpr {
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            anyhow::bail!("unexpected input after the expression");
+        }
+        Ok(expr)
+    }
+
+    pub fn eval(&self, resolver: &RoleResolver) -> bool {
+        self.eval_with(&|name| resolver.is_active(name))
+    }
+
+    /// Evaluates the expression with `active` deciding which roles are held.
+    pub fn eval_with(&self, active: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            RoleExpr::Role(name) => active(name),
+            RoleExpr::Not(inner) => !inner.eval_with(active),
+            RoleExpr::And(terms) => terms.iter().all(|t| t.eval_with(active)),
+            RoleExpr::Or(terms) => terms.iter().any(|t| t.eval_with(active)),
+        }
+    }
+
+    /// Role names the expression mentions, in order of first use.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.collect_names(&mut names);
+        names
+    }
+
+    /// Role names mentioned outside any `!`: the roles a machine can hold for
+    /// the expression to be true because of them.
+    pub fn positive_names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.collect_positive(&mut names);
+        names
+    }
+
+    fn collect_names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            RoleExpr::Role(name) => {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+            RoleExpr::Not(inner) => inner.collect_names(names),
+            RoleExpr::And(terms) | RoleExpr::Or(terms) => {
+                for term in terms {
+                    term.collect_names(names);
+                }
+            }
+        }
+    }
+
+    fn collect_positive<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            RoleExpr::Role(name) => {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+            RoleExpr::Not(_) => {}
+            RoleExpr::And(terms) | RoleExpr::Or(terms) => {
+                for term in terms {
+                    term.collect_positive(names);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for RoleExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter<'_>, terms: &[RoleExpr], op: &str| {
+            for (i, term) in terms.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " {} ", op)?;
+                }
+                match term {
+                    RoleExpr::And(_) | RoleExpr::Or(_) => write!(f, "({})", term)?,
+                    _ => write!(f, "{}", term)?,
+                }
+            }
+            Ok(())
+        };
+        match self {
+            RoleExpr::Role(name) => write!(f, "{}", name),
+            RoleExpr::Not(inner) => match **inner {
+                RoleExpr::And(_) | RoleExpr::Or(_) => write!(f, "!({})", inner),
+                _ => write!(f, "!{}", inner),
+            },
+            RoleExpr::And(terms) => join(f, terms, "&&"),
+            RoleExpr::Or(terms) => join(f, terms, "||"),
+        }
+    }
+}
+
+/// Why an entry does or does not apply to this machine.
+#[derive(Debug)]
+pub struct Explanation {
+    pub enabled: bool,
+    /// Whether the `roles` check passed on its own.
+    pub roles_ok: bool,
+    /// Whether the `platforms` check passed on its own.
+    pub platform_ok: bool,
+    pub reasons: Vec<String>,
+}
+
+/// The machine's effective roles: those assigned to it, everything they imply
+/// through `[roles]`, and the current platform (`linux`, `macos`, `windows`,
+/// plus `unix`).
+#[derive(Debug)]
+pub struct RoleResolver {
+    /// Active role -> where it comes from ("assigned", "implied by x", "platform").
+    active: BTreeMap<String, String>,
+}
+
+impl RoleResolver {
+    pub fn new(assigned: &[String], implies: &BTreeMap<String, Vec<String>>) -> Self {
+        let mut active = BTreeMap::new();
+        active.insert(std::env::consts::OS.to_string(), "platform".to_string());
+        if cfg!(unix) {
+            active.insert("unix".to_string(), "platform".to_string());
+        }
+
+        let mut pending: Vec<(String, String)> = assigned
+            .iter()
+            .map(|role| (role.clone(), "assigned".to_string()))
+            .collect();
+        // Breadth-first so a role keeps the shortest explanation; cycles stop
+        // at roles already seen.
+        let mut i = 0;
+        while i < pending.len() {
+            let (role, source) = pending[i].clone();
+            i += 1;
+            if active.get(&role).is_some_and(|s| s != "platform") {
+                continue;
+            }
+            active.insert(role.clone(), source);
+            for implied in implies.get(&role).into_iter().flatten() {
+                pending.push((implied.clone(), format!("implied by {}", role)));
+            }
+        }
+        Self { active }
+    }
+
+    /// Resolver for this machine's assigned roles and the config's `[roles]` table.
+    pub fn for_machine(state: &LocalState, config: &TeamConfig) -> Self {
+        Self::new(&state.roles, &config.roles)
+    }
+
+    pub fn is_active(&self, role: &str) -> bool {
+        self.active.contains_key(role)
+    }
+
+    /// Active roles with where each comes from.
+    pub fn active(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.active.iter().map(|(r, s)| (r.as_str(), s.as_str()))
+    }
+
+    /// "assigned", "implied by x", "platform" or "not active".
+    pub fn source(&self, role: &str) -> &str {
+        self.active.get(role).map_or("not active", String::as_str)
+    }
+
+    pub fn matches(&self, file: &FileConfig) -> bool {
+        self.explain(file).enabled
+    }
+
+    /// Evaluates an entry's `roles` (any expression may match) and `platforms`.
+    pub fn explain(&self, file: &FileConfig) -> Explanation {
+        let mut reasons = Vec::new();
+        let roles_ok = match file.roles.as_deref() {
+            None | Some([]) => {
+                reasons.push("no roles: applies to every machine".to_string());
+                true
+            }
+            Some(exprs) => {
+                let mut any = false;
+                for source in exprs {
+                    match RoleExpr::parse(source) {
+                        Ok(expr) => {
+                            let value = expr.eval(self);
+                            any |= value;
+                            let detail: Vec<String> = expr
+                                .names()
+                                .into_iter()
+                                .map(|name| format!("{}: {}", name, self.source(name)))
+                                .collect();
+                            reasons.push(format!(
+                                "role `{}` is {} ({})",
+                                expr,
+                                value,
+                                detail.join(", ")
+                            ));
+                        }
+                        Err(e) => reasons.push(format!(
+                            "role expression `{}` is invalid and never matches: {}",
+                            source, e
+                        )),
+                    }
+                }
+                any
+            }
+        };
+
+        let platform_ok = file.matches_platform();
+        if !platform_ok {
+            reasons.push(format!(
+                "platforms {:?} do not include {}",
+                file.platforms,
+                std::env::consts::OS
+            ));
+        }
+
+        Explanation {
+            enabled: roles_ok && platform_ok,
+            roles_ok,
+            platform_ok,
+            reasons,
+        }
+    }
+}
+
+/// Roles an entry's expressions mention (used by `role list`).
+pub fn mentioned_roles(file: &FileConfig) -> BTreeSet<String> {
+    file.roles
+        .iter()
+        .flatten()
+        .filter_map(|source| RoleExpr::parse(source).ok())
+        .flat_map(|expr| {
+            expr.names()
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Roles whose machines may apply an entry scoped by `exprs`: every role the
+/// expressions mention outside a `!`, plus every role that implies one of
+/// them through `[roles]`. Used to pick per-role secret recipients.
+pub fn scoped_roles(exprs: &[String], implies: &BTreeMap<String, Vec<String>>) -> BTreeSet<String> {
+    let mut roles: BTreeSet<String> = exprs
+        .iter()
+        .filter_map(|source| RoleExpr::parse(source).ok())
+        .flat_map(|expr| {
+            expr.positive_names()
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    loop {
+        let before = roles.len();
+        for (role, implied) in implies {
+            if implied.iter().any(|r| roles.contains(r)) {
+                roles.insert(role.clone());
+            }
+        }
+        if roles.len() == before {
+            return roles;
+        }
+    }
+}
+
+/// Whether one machine could match both entries' `roles` lists. An empty list
+/// applies everywhere; invalid expressions never match. Tries every way of
+/// assigning the mentioned roles, following implications through `implies`.
+pub fn can_overlap(
+    a: Option<&[String]>,
+    b: Option<&[String]>,
+    implies: &BTreeMap<String, Vec<String>>,
+) -> bool {
+    let (a, b) = match (a, b) {
+        (None | Some([]), _) | (_, None | Some([])) => return true,
+        (Some(a), Some(b)) => (a, b),
+    };
+    let parse = |exprs: &[String]| -> Vec<RoleExpr> {
+        exprs
+            .iter()
+            .filter_map(|source| RoleExpr::parse(source).ok())
+            .collect()
+    };
+    let (a, b) = (parse(a), parse(b));
+    let mut names: Vec<&str> = Vec::new();
+    for expr in a.iter().chain(&b) {
+        for name in expr.names() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    // Too many to enumerate: assume the worst.
+    if names.len() > 16 {
+        return true;
+    }
+
+    (0u32..1 << names.len()).any(|mask| {
+        let mut active: BTreeSet<&str> = BTreeSet::new();
+        let mut pending: Vec<&str> = (0..names.len())
+            .filter(|i| mask & (1 << i) != 0)
+            .map(|i| names[i])
+            .collect();
+        while let Some(role) = pending.pop() {
+            if active.insert(role) {
+                pending.extend(implies.get(role).into_iter().flatten().map(String::as_str));
+            }
+        }
+        let held = |name: &str| active.contains(name);
+        a.iter().any(|e| e.eval_with(&held)) && b.iter().any(|e| e.eval_with(&held))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role(name: &str) -> RoleExpr {
+        RoleExpr::Role(name.to_string())
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            RoleExpr::parse("a || b && c").unwrap(),
+            RoleExpr::Or(vec![role("a"), RoleExpr::And(vec![role("b"), role("c")])])
+        );
+        assert_eq!(
+            RoleExpr::parse("a && b || c").unwrap(),
+            RoleExpr::Or(vec![RoleExpr::And(vec![role("a"), role("b")]), role("c")])
+        );
+    }
+
+    #[test]
+    fn not_applies_to_the_next_term() {
+        assert_eq!(
+            RoleExpr::parse("!a && b").unwrap(),
+            RoleExpr::And(vec![RoleExpr::Not(Box::new(role("a"))), role("b")])
+        );
+        assert_eq!(
+            RoleExpr::parse("!!a").unwrap(),
+            RoleExpr::Not(Box::new(RoleExpr::Not(Box::new(role("a")))))
+        );
+    }
+
+    #[test]
+    fn parentheses_group() {
+        assert_eq!(
+            RoleExpr::parse("(a || b) && !(c || d)").unwrap(),
+            RoleExpr::And(vec![
+                RoleExpr::Or(vec![role("a"), role("b")]),
+                RoleExpr::Not(Box::new(RoleExpr::Or(vec![role("c"), role("d")]))),
+            ])
+        );
+        assert_eq!(RoleExpr::parse("((work))").unwrap(), role("work"));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for source in ["work", "a || b && c", "(a || b) && !(c || d)", "!a && b"] {
+            let expr = RoleExpr::parse(source).unwrap();
+            assert_eq!(RoleExpr::parse(&expr.to_string()).unwrap(), expr);
+        }
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        for (source, error) in [
+            ("", "unexpected end of expression"),
+            ("a &&", "unexpected end of expression"),
+            ("(a || b", "missing `)`"),
+            ("a b", "unexpected input after the expression"),
+            ("a & b", "expected `&&`"),
+            ("a | b", "expected `||`"),
+            ("a && || b", "expected a role name"),
+            ("a)", "unexpected input after the expression"),
+            ("work$", "unexpected character `$`"),
+        ] {
+            let err = RoleExpr::parse(source).unwrap_err().to_string();
+            assert!(err.contains(error), "{:?}: {}", source, err);
+        }
+    }
+
+    #[test]
+    fn role_names_must_parse_as_expressions() {
+        for name in ["work", "work-laptop", "team_a", "v1.2", "os:linux"] {
+            assert!(check_name(name).is_ok(), "{:?}", name);
+            assert_eq!(RoleExpr::parse(name).unwrap(), role(name));
+        }
+        for name in ["", "a&&b", "!ci", "a||b", "(work)", "work laptop"] {
+            assert!(check_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn evaluates_against_active_roles() {
+        let implies = BTreeMap::from([("laptop".to_string(), vec!["work".to_string()])]);
+        let resolver = RoleResolver::new(&["laptop".to_string()], &implies);
+        let eval = |s: &str| RoleExpr::parse(s).unwrap().eval(&resolver);
+        assert!(eval("work"));
+        assert!(eval("work && !ci"));
+        assert!(!eval("work && ci"));
+        assert!(eval("ci || laptop"));
+    }
+
+    fn entry(roles: &str) -> FileConfig {
+        toml::from_str(&format!(
+            "source = \"a\"\ndestination = \"~/a\"\ntype = \"file\"\n{}",
+            roles
+        ))
+        .expect("valid entry")
+    }
+
+    #[test]
+    fn implied_roles_record_where_they_come_from() {
+        let implies = BTreeMap::from([
+            (
+                "work-laptop".to_string(),
+                vec!["work".to_string(), "laptop".to_string()],
+            ),
+            ("work".to_string(), vec!["vpn".to_string()]),
+            // Cycles stop at roles already seen.
+            ("vpn".to_string(), vec!["work-laptop".to_string()]),
+        ]);
+        let resolver = RoleResolver::new(&["work-laptop".to_string()], &implies);
+        assert_eq!(resolver.source("work-laptop"), "assigned");
+        assert_eq!(resolver.source("work"), "implied by work-laptop");
+        assert_eq!(resolver.source("laptop"), "implied by work-laptop");
+        assert_eq!(resolver.source("vpn"), "implied by work");
+        assert_eq!(resolver.source(std::env::consts::OS), "platform");
+        assert_eq!(resolver.source("ci"), "not active");
+
+        // An assigned role keeps that explanation even when another implies it.
+        let resolver =
+            RoleResolver::new(&["work-laptop".to_string(), "work".to_string()], &implies);
+        assert_eq!(resolver.source("work"), "assigned");
+    }
+
+    #[test]
+    fn explain_reports_each_expression() {
+        let resolver = RoleResolver::new(&["work".to_string()], &BTreeMap::new());
+
+        let explanation = resolver.explain(&entry(""));
+        assert!(explanation.enabled);
+        assert_eq!(explanation.reasons, ["no roles: applies to every machine"]);
+
+        let explanation = resolver.explain(&entry("roles = [\"ci\", \"work && !ci\"]"));
+        assert!(explanation.enabled && explanation.roles_ok);
+        assert_eq!(
+            explanation.reasons,
+            [
+                "role `ci` is false (ci: not active)",
+                "role `work && !ci` is true (work: assigned, ci: not active)",
+            ]
+        );
+
+        let explanation = resolver.explain(&entry("roles = [\"work &&\"]"));
+        assert!(!explanation.enabled && !explanation.roles_ok);
+        assert!(explanation.reasons[0].starts_with("role expression `work &&` is invalid"));
+
+        let explanation = resolver.explain(&entry("roles = [\"work\"]\nplatforms = [\"plan9\"]"));
+        assert!(!explanation.enabled && explanation.roles_ok && !explanation.platform_ok);
+    }
+
+    #[test]
+    fn mentioned_roles_include_negated_names() {
+        let file = entry("roles = [\"work && !ci\", \"home\", \"(broken\"]");
+        let roles: Vec<String> = mentioned_roles(&file).into_iter().collect();
+        assert_eq!(roles, ["ci", "home", "work"]);
+        let expr = RoleExpr::parse("work && !ci || work").unwrap();
+        assert_eq!(expr.names(), ["work", "ci"]);
+        assert_eq!(expr.positive_names(), ["work"]);
+    }
+
+    #[test]
+    fn scoped_roles_follow_expressions_and_implications() {
+        let implies = BTreeMap::from([
+            ("work-laptop".to_string(), vec!["work".to_string()]),
+            ("ci".to_string(), vec!["build".to_string()]),
+        ]);
+        let roles = scoped_roles(&["work && linux && !ci".to_string()], &implies);
+        let expected: BTreeSet<String> = ["linux", "work", "work-laptop"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(roles, expected);
+    }
+}
@@ -0,0 +1,347 @@
+use crate::core::config::schema::ScanConfig;
+use crate::core::git::repository::GitRepository;
+use crate::core::machines::MACHINES_DIR;
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Files larger than this are not scanned (dotfiles are small; big files are usually data).
+const MAX_SCAN_BYTES: u64 = 1024 * 1024;
+/// Lines containing this marker are never reported.
+const INLINE_ALLOW: &str = "configsync:allow";
+
+/// Minimum length for a token to count as a likely random credential.
+const ENTROPY_MIN_LEN: usize = 20;
+/// How far (bits per character) below a random token of the same length and
+/// alphabet a token's Shannon entropy may fall and still be reported.
+const ENTROPY_MARGIN: f64 = 0.5;
+
+const RULES: &[(&str, &str, &str)] = &[
+    (
+        "private-key",
+        "private key",
+        r"-----BEGIN ((RSA|DSA|EC|OPENSSH|PGP|ENCRYPTED) )?PRIVATE KEY( BLOCK)?-----|AGE-SECRET-KEY-1[0-9A-Z]{58}",
+    ),
+    (
+        "aws-access-key",
+        "AWS access key ID",
+        r"\b(AKIA|ASIA)[0-9A-Z]{16}\b",
+    ),
+    (
+        "aws-secret-key",
+        "AWS secret access key",
+        r#"(?i)aws_secret_access_key\s*[=:]\s*['"]?[A-Za-z0-9/+=]{40}"#,
+    ),
+    ("gcp-api-key", "GCP API key", r"\bAIza[0-9A-Za-z_\-]{35}\b"),
+    (
+        "gcp-service-account",
+        "GCP service account key",
+        r#""type"\s*:\s*"service_account""#,
+    ),
+    (
+        "github-token",
+        "GitHub token",
+        r"\b(ghp|gho|ghu|ghs|ghr)_[A-Za-z0-9]{36}\b|\bgithub_pat_[A-Za-z0-9_]{82}\b",
+    ),
+];
+
+/// A line that looks like it contains a credential. The matched text itself is
+/// never stored so reports cannot leak it.
+pub struct Finding {
+    pub path: String,
+    pub line: usize,
+    pub rule: &'static str,
+    pub description: &'static str,
+}
+
+pub struct Scanner {
+    rules: Vec<(&'static str, &'static str, Regex)>,
+    token: Regex,
+    allow_paths: Vec<glob::Pattern>,
+    allow_patterns: Vec<Regex>,
+    entropy: bool,
+}
+
+impl Scanner {
+    pub fn new(config: &ScanConfig) -> Result<Self> {
+        let disabled = |name: &str| config.disabled_rules.iter().any(|r| r == name);
+        let rules = RULES
+            .iter()
+            .filter(|(name, _, _)| !disabled(name))
+            .map(|(name, description, pattern)| Ok((*name, *description, Regex::new(pattern)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let allow_paths = config
+            .allow_paths
+            .iter()
+            .map(|p| {
+                glob::Pattern::new(p)
+                    .with_context(|| format!("Invalid [scan] allow_paths pattern '{}'", p))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let allow_patterns = config
+            .allow_patterns
+            .iter()
+            .map(|p| {
+                Regex::new(p)
+                    .with_context(|| format!("Invalid [scan] allow_patterns regex '{}'", p))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            rules,
+            token: Regex::new(r"[A-Za-z0-9+/=_\-]{20,}")?,
+            allow_paths,
+            allow_patterns,
+            entropy: !disabled("high-entropy"),
+        })
+    }
+
+    /// True for `allow_paths` matches and for machine records, which configsync
+    /// writes itself and which hold commit ids that look random.
+    pub fn is_allowed_path(&self, label: &str) -> bool {
+        label
+            .strip_prefix(MACHINES_DIR)
+            .is_some_and(|rest| rest.starts_with('/'))
+            || self.allow_paths.iter().any(|p| p.matches(label))
+    }
+
+    /// Scans a file or directory on disk. `label` is the repository-relative
+    /// path used for reports and `allow_paths` matching.
+    pub fn scan_path(&self, path: &Path, label: &str) -> Result<Vec<Finding>> {
+        let mut findings = Vec::new();
+        // Git records a symlink as its target path, so never follow one (and
+        // never loop through a link to a parent directory).
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return Ok(findings);
+        };
+        if metadata.is_dir() {
+            for entry in fs::read_dir(path).with_context(|| format!("Failed to read {:?}", path))? {
+                let entry = entry?;
+                let name = entry.file_name();
+                if name == ".git" {
+                    continue;
+                }
+                let child_label = format!("{}/{}", label, name.to_string_lossy());
+                findings.extend(self.scan_path(&entry.path(), &child_label)?);
+            }
+        } else if metadata.is_file() {
+            if self.is_allowed_path(label) || label.ends_with(".age") {
+                return Ok(findings);
+            }
+            if metadata.len() > MAX_SCAN_BYTES {
+                return Ok(findings);
+            }
+            let content = fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+            findings.extend(self.scan_bytes(label, &content));
+        }
+        Ok(findings)
+    }
+
+    /// Scans file contents that are not on disk (e.g. a committed blob).
+    pub fn scan_bytes(&self, label: &str, content: &[u8]) -> Vec<Finding> {
+        if self.is_allowed_path(label)
+            || label.ends_with(".age")
+            || content.len() as u64 > MAX_SCAN_BYTES
+        {
+            return Vec::new();
+        }
+        // Binary files are skipped.
+        match std::str::from_utf8(content) {
+            Ok(text) => self.scan_text(label, text),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub fn scan_text(&self, label: &str, text: &str) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            if line.contains(INLINE_ALLOW) {
+                continue;
+            }
+            let hit = self
+                .rules
+                .iter()
+                .find(|(_, _, regex)| {
+                    regex
+                        .find_iter(line)
+                        .any(|m| !self.is_allowed_match(m.as_str()))
+                })
+                .map(|(name, description, _)| (*name, *description))
+                .or_else(|| {
+                    let random = self.entropy
+                        && self.token.find_iter(line).any(|m| {
+                            is_high_entropy(m.as_str()) && !self.is_allowed_match(m.as_str())
+                        });
+                    random.then_some(("high-entropy", "high-entropy string"))
+                });
+            if let Some((rule, description)) = hit {
+                findings.push(Finding {
+                    path: label.to_string(),
+                    line: index + 1,
+                    rule,
+                    description,
+                });
+            }
+        }
+        findings
+    }
+
+    fn is_allowed_match(&self, text: &str) -> bool {
+        self.allow_patterns.iter().any(|p| p.is_match(text))
+    }
+}
+
+/// Random-looking tokens: long, mixed letters and digits, and close to uniformly
+/// distributed. Public keys (`age1...`, SSH `AAAA...`) are expected in config and
+/// skipped, as are path- and slug-like tokens with several `/`, `-` or `_`
+/// separators.
+fn is_high_entropy(token: &str) -> bool {
+    if token.len() < ENTROPY_MIN_LEN || token.starts_with("age1") || token.starts_with("AAAA") {
+        return false;
+    }
+    if token.starts_with('/')
+        || ['/', '-', '_']
+            .iter()
+            .any(|&c| token.matches(c).count() > 2)
+    {
+        return false;
+    }
+    if !token.chars().any(|c| c.is_ascii_digit()) || !token.chars().any(|c| c.is_ascii_alphabetic())
+    {
+        return false;
+    }
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in token.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let len = token.len() as f64;
+    let entropy: f64 = counts
+        .values()
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2()
+        })
+        .sum();
+    entropy >= entropy_threshold(token)
+}
+
+/// Entropy a random token of this length would about reach: short tokens
+/// cannot use every symbol of their alphabet, and hex has only 16.
+fn entropy_threshold(token: &str) -> f64 {
+    let alphabet: f64 = if token.chars().all(|c| c.is_ascii_hexdigit()) {
+        16.0
+    } else {
+        64.0
+    };
+    // Expected number of distinct symbols in `len` random draws.
+    let distinct = alphabet * (1.0 - (1.0 - 1.0 / alphabet).powi(token.len() as i32));
+    distinct.log2() - ENTROPY_MARGIN
+}
+
+/// Prints findings without the matched text.
+pub fn report(findings: &[Finding]) {
+    println!("⚠️ Possible plaintext secrets found:");
+    for finding in findings {
+        println!(
+            "  {}:{}: {} ({})",
+            finding.path, finding.line, finding.description, finding.rule
+        );
+    }
+}
+
+pub const OVERRIDE_HINT: &str = "Store credentials with `configsync secrets add <path>` so they are encrypted, \
+allow-list false positives under [scan] in team-config.toml (or mark the line with `configsync:allow`), \
+or pass --allow-secrets to continue anyway.";
+
+/// Scans the files the next commit would record and those changed by
+/// commits not pushed yet.
+pub fn scan_changes(
+    repo: &GitRepository,
+    config_dir: &Path,
+    config: &ScanConfig,
+) -> Result<Vec<Finding>> {
+    let scanner = Scanner::new(config)?;
+    let mut findings = Vec::new();
+    for path in repo.changed_files()? {
+        let label = path.to_string_lossy().replace('\\', "/");
+        findings.extend(scanner.scan_path(&config_dir.join(&path), &label)?);
+    }
+    for (path, content) in repo.unpushed_files()? {
+        let label = path.to_string_lossy().replace('\\', "/");
+        for finding in scanner.scan_bytes(&label, &content) {
+            let duplicate = findings.iter().any(|f| {
+                f.path == finding.path && f.line == finding.line && f.rule == finding.rule
+            });
+            if !duplicate {
+                findings.push(finding);
+            }
+        }
+    }
+    Ok(findings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scanner() -> Scanner {
+        Scanner::new(&ScanConfig::default()).expect("default rules compile")
+    }
+
+    fn rules(text: &str) -> Vec<&'static str> {
+        scanner()
+            .scan_text("config", text)
+            .into_iter()
+            .map(|f| f.rule)
+            .collect()
+    }
+
+    #[test]
+    fn hex_keys_are_high_entropy() {
+        assert_eq!(
+            rules("api_key = 3f9a1c7e5b2d8046af13e9c75b0d2f4a86c1e3b7"),
+            ["high-entropy"]
+        );
+        assert!(rules("commit = 0000000000000000000000000000000000000001").is_empty());
+        assert!(rules("pattern = deadbeefdeadbeefdeadbeef00").is_empty());
+    }
+
+    #[test]
+    fn short_base64_tokens_are_high_entropy() {
+        assert_eq!(rules("token: Zq8Xw2LpT4vRk9Nb7Hs3"), ["high-entropy"]);
+        assert_eq!(rules("TOKEN=q7R+b2Kx/9LmW4pZ8nYc3e=="), ["high-entropy"]);
+    }
+
+    #[test]
+    fn names_paths_and_public_keys_are_not() {
+        for line in [
+            "setting = configsync_allow_paths_value1",
+            "path = /usr/local/share/some-tool/v1234/bin",
+            "image = registry-mirror-europe-west1-prod",
+            "recipient = age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p",
+            "word = aaaaaaaaaaaaaaaaaaaaaaaaaa1",
+        ] {
+            assert!(rules(line).is_empty(), "{}", line);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_directories_are_not_followed() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        std::fs::create_dir(dir.path().join("nested")).expect("create dir");
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("nested/loop"))
+            .expect("create symlink");
+        std::fs::write(
+            dir.path().join("nested/keys"),
+            format!("aws_access_key_id = AKIA{}\n", "ABCDEFGHIJ234567"),
+        )
+        .expect("write file");
+
+        let findings = scanner().scan_path(dir.path(), "repo").expect("scan");
+        let paths: Vec<&str> = findings.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["repo/nested/keys"]);
+    }
+}
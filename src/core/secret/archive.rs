@@ -0,0 +1,119 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Packs a directory into an in-memory tarball for encryption as one secret.
+/// Entries keep their modes; sockets, FIFOs and devices are skipped.
+pub fn pack(dir: &Path) -> Result<Zeroizing<Vec<u8>>> {
+    let mut builder = tar::Builder::new(Vec::new());
+    builder.follow_symlinks(false);
+    append_dir(&mut builder, dir, Path::new(""))?;
+    let data = builder
+        .into_inner()
+        .context("Failed to finish secret archive")?;
+    Ok(Zeroizing::new(data))
+}
+
+fn append_dir(builder: &mut tar::Builder<Vec<u8>>, dir: &Path, relative: &Path) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {:?}", dir))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let name = relative.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            builder
+                .append_path_with_name(&path, &name)
+                .with_context(|| format!("Failed to archive {:?}", path))?;
+            append_dir(builder, &path, &name)?;
+        } else if file_type.is_file() || file_type.is_symlink() {
+            builder
+                .append_path_with_name(&path, &name)
+                .with_context(|| format!("Failed to archive {:?}", path))?;
+        } else {
+            println!("Skipping special file {:?}", path);
+        }
+    }
+    Ok(())
+}
+
+/// SHA-256 over the relative paths and contents of the regular files under
+/// `dir`, in sorted order. Unlike a hash of `pack`, it ignores mtimes and modes.
+pub fn content_digest(dir: &Path) -> Result<String> {
+    use sha2::{Digest, Sha256};
+
+    fn walk(hasher: &mut Sha256, dir: &Path, relative: &Path) -> Result<()> {
+        let mut entries = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read directory {:?}", dir))?
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let name = relative.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                walk(hasher, &entry.path(), &name)?;
+            } else if file_type.is_file() {
+                let data = Zeroizing::new(fs::read(entry.path())?);
+                hasher.update(name.to_string_lossy().as_bytes());
+                hasher.update([0]);
+                hasher.update((data.len() as u64).to_le_bytes());
+                hasher.update(&*data);
+            }
+        }
+        Ok(())
+    }
+
+    let mut hasher = Sha256::new();
+    walk(&mut hasher, dir, Path::new(""))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Restores a tarball produced by `pack` into `dest`, creating it if needed and
+/// restoring entry modes. Files not in the archive are left alone.
+pub fn unpack(data: &[u8], dest: &Path) -> Result<()> {
+    fs::create_dir_all(dest).with_context(|| format!("Failed to create {:?}", dest))?;
+    let mut archive = tar::Archive::new(data);
+    archive.set_preserve_permissions(true);
+    archive.set_overwrite(true);
+    archive
+        .unpack(dest)
+        .with_context(|| format!("Failed to restore secret directory {:?}", dest))
+}
+
+/// Archive entries whose local copy under `dest` is missing or has different
+/// content. Only regular files are compared.
+pub fn drifted(data: &[u8], dest: &Path) -> Result<Vec<PathBuf>> {
+    let mut archive = tar::Archive::new(data);
+    let mut drifted = Vec::new();
+    for entry in archive.entries().context("Failed to read secret archive")? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let mut expected = Zeroizing::new(Vec::new());
+        entry.read_to_end(&mut expected)?;
+        match fs::read(dest.join(&path)).map(Zeroizing::new) {
+            Ok(local) if *local == *expected => {}
+            _ => drifted.push(path),
+        }
+    }
+    Ok(drifted)
+}
+
+/// Lists archive entries as `(mode, path)` without their contents.
+pub fn list(data: &[u8]) -> Result<Vec<(u32, PathBuf)>> {
+    let mut archive = tar::Archive::new(data);
+    let mut listing = Vec::new();
+    for entry in archive.entries().context("Failed to read secret archive")? {
+        let entry = entry?;
+        let mode = entry.header().mode().unwrap_or(0);
+        listing.push((mode, entry.path()?.into_owned()));
+    }
+    Ok(listing)
+}
@@ -0,0 +1,30 @@
+use crate::core::secret::backend::SecretBackend;
+use crate::core::secret::{cipher, keys};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The default store: `.age` files committed to the repository.
+pub struct AgeBackend {
+    config_dir: PathBuf,
+    identity: keys::SecretIdentity,
+}
+
+impl AgeBackend {
+    pub fn new(config_dir: &Path) -> Result<Self> {
+        let identity = keys::load_key()
+            .context("No private key found. Run `configsync secrets init` or restore key.")?;
+        Ok(Self {
+            config_dir: config_dir.to_path_buf(),
+            identity,
+        })
+    }
+}
+
+impl SecretBackend for AgeBackend {
+    fn fetch(&self, path: &str) -> Result<Vec<u8>> {
+        let source_path = self.config_dir.join(path);
+        let encrypted = fs::read(&source_path).context("Failed to read encrypted file")?;
+        cipher::decrypt(&encrypted, &self.identity)
+    }
+}
@@ -0,0 +1,40 @@
+use crate::core::secret::backend::SecretBackend;
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+/// Values from a local dotenv file. Sources are `KEY` (using `secrets.env_file`)
+/// or `path/to/file.env#KEY`.
+pub struct EnvFileBackend {
+    default_file: Option<String>,
+}
+
+impl EnvFileBackend {
+    pub fn new(default_file: Option<String>) -> Self {
+        Self { default_file }
+    }
+}
+
+impl SecretBackend for EnvFileBackend {
+    fn fetch(&self, path: &str) -> Result<Vec<u8>> {
+        let (file, key) = match path.rsplit_once('#') {
+            Some((file, key)) => (file.to_string(), key),
+            None => (
+                self.default_file.clone().context(
+                    "Env backend source has no `file#KEY` form and `secrets.env_file` is not set",
+                )?,
+                path,
+            ),
+        };
+        let file = PathBuf::from(shellexpand::tilde(&file).into_owned());
+
+        let entries = dotenvy::from_path_iter(&file)
+            .with_context(|| format!("Failed to read env file {:?}", file))?;
+        for entry in entries {
+            let (name, value) = entry.with_context(|| format!("Failed to parse {:?}", file))?;
+            if name == key {
+                return Ok(value.into_bytes());
+            }
+        }
+        anyhow::bail!("Key '{}' not found in {:?}", key, file)
+    }
+}
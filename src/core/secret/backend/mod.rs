@@ -0,0 +1,81 @@
+pub mod age;
+pub mod env;
+pub mod pass;
+pub mod vault;
+
+use crate::core::config::schema::{FileConfig, SecretsConfig};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_BACKEND: &str = "age";
+pub const BACKENDS: &[&str] = &["age", "pass", "gopass", "env", "vault"];
+
+/// A place secret values are fetched from at apply time.
+pub trait SecretBackend {
+    /// Returns the plaintext stored at `path` (the entry's `source`).
+    fn fetch(&self, path: &str) -> Result<Vec<u8>>;
+}
+
+/// Resolves which backend serves a secret entry: its own `backend`, then
+/// `secrets.vault_type` when `vault_enabled`, then the in-repo age store.
+pub fn backend_name<'a>(file: &'a FileConfig, secrets: &'a SecretsConfig) -> &'a str {
+    if let Some(name) = file.backend.as_deref() {
+        return name;
+    }
+    if secrets.vault_enabled && !secrets.vault_type.is_empty() {
+        return &secrets.vault_type;
+    }
+    DEFAULT_BACKEND
+}
+
+/// True for secrets stored as `.age` files inside the repository.
+pub fn is_repo_secret(file: &FileConfig, secrets: &SecretsConfig) -> bool {
+    file.file_type.is_secret() && backend_name(file, secrets) == DEFAULT_BACKEND
+}
+
+pub fn create(
+    name: &str,
+    config_dir: &Path,
+    secrets: &SecretsConfig,
+) -> Result<Box<dyn SecretBackend>> {
+    match name {
+        "age" => Ok(Box::new(age::AgeBackend::new(config_dir)?)),
+        "pass" | "gopass" => Ok(Box::new(pass::PassBackend::new(name))),
+        "env" => Ok(Box::new(env::EnvFileBackend::new(secrets.env_file.clone()))),
+        "vault" => Ok(Box::new(vault::VaultBackend::new(
+            secrets.vault_address.clone(),
+        )?)),
+        other => anyhow::bail!(
+            "Unknown secret backend '{}'. Expected one of: {}.",
+            other,
+            BACKENDS.join(", ")
+        ),
+    }
+}
+
+/// Backends created on first use and reused for the rest of a command.
+pub struct Backends<'a> {
+    config_dir: PathBuf,
+    secrets: &'a SecretsConfig,
+    loaded: HashMap<String, Box<dyn SecretBackend>>,
+}
+
+impl<'a> Backends<'a> {
+    pub fn new(config_dir: &Path, secrets: &'a SecretsConfig) -> Self {
+        Self {
+            config_dir: config_dir.to_path_buf(),
+            secrets,
+            loaded: HashMap::new(),
+        }
+    }
+
+    pub fn fetch(&mut self, file: &FileConfig) -> Result<Vec<u8>> {
+        let name = backend_name(file, self.secrets).to_string();
+        if !self.loaded.contains_key(&name) {
+            let backend = create(&name, &self.config_dir, self.secrets)?;
+            self.loaded.insert(name.clone(), backend);
+        }
+        self.loaded[&name].fetch(&file.source)
+    }
+}
@@ -0,0 +1,35 @@
+use crate::core::secret::backend::SecretBackend;
+use anyhow::{Context, Result};
+use std::process::Command;
+
+/// `pass` / `gopass` password stores, read through their CLI.
+pub struct PassBackend {
+    program: String,
+}
+
+impl PassBackend {
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+        }
+    }
+}
+
+impl SecretBackend for PassBackend {
+    fn fetch(&self, path: &str) -> Result<Vec<u8>> {
+        let output = Command::new(&self.program)
+            .args(["show", path])
+            .output()
+            .with_context(|| format!("Failed to run `{} show {}`", self.program, path))?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "`{} show {}` failed: {}",
+                self.program,
+                path,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output.stdout)
+    }
+}
@@ -0,0 +1,47 @@
+use crate::core::secret::backend::SecretBackend;
+use anyhow::{Context, Result};
+
+/// HashiCorp Vault KV v2 (or a compatible server). Sources are `mount/path#field`;
+/// the field defaults to `value`. The token comes from `VAULT_TOKEN`.
+pub struct VaultBackend {
+    address: String,
+    token: String,
+}
+
+impl VaultBackend {
+    pub fn new(address: Option<String>) -> Result<Self> {
+        let address = address
+            .or_else(|| std::env::var("VAULT_ADDR").ok())
+            .context("Vault address not set. Set `secrets.vault_address` or VAULT_ADDR.")?;
+        let token = std::env::var("VAULT_TOKEN").context("VAULT_TOKEN is not set")?;
+        Ok(Self {
+            address: address.trim_end_matches('/').to_string(),
+            token,
+        })
+    }
+}
+
+impl SecretBackend for VaultBackend {
+    fn fetch(&self, path: &str) -> Result<Vec<u8>> {
+        let (path, field) = path.rsplit_once('#').unwrap_or((path, "value"));
+        let (mount, key) = path
+            .trim_matches('/')
+            .split_once('/')
+            .with_context(|| format!("Vault source {:?} must be `mount/path#field`", path))?;
+        let url = format!("{}/v1/{}/data/{}", self.address, mount, key);
+
+        let response = ureq::get(&url)
+            .set("X-Vault-Token", &self.token)
+            .call()
+            .with_context(|| format!("Vault request to {} failed", url))?;
+        let body: serde_json::Value = response
+            .into_json()
+            .context("Failed to parse Vault response")?;
+
+        match body.pointer(&format!("/data/data/{}", field)) {
+            Some(serde_json::Value::String(value)) => Ok(value.clone().into_bytes()),
+            Some(other) => Ok(other.to_string().into_bytes()),
+            None => anyhow::bail!("Field '{}' not found at {}", field, path),
+        }
+    }
+}
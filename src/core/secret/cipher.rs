@@ -1,26 +1,85 @@
+use crate::core::secret::keys::{SecretIdentity, SecretRecipient};
 use anyhow::Result;
+use secrecy::SecretString;
 use std::io::{Read, Write};
 
-pub fn encrypt(data: &[u8], recipient: &age::x25519::Recipient) -> Result<Vec<u8>> {
-    let encryptor = age::Encryptor::with_recipients(vec![Box::new(recipient.clone())])
-        .expect("we provided a recipient");
+pub fn encrypt(data: &[u8], recipients: &[SecretRecipient], armor: bool) -> Result<Vec<u8>> {
+    let recipients = recipients.iter().map(SecretRecipient::boxed).collect();
+    let encryptor = age::Encryptor::with_recipients(recipients)
+        .ok_or_else(|| anyhow::anyhow!("No recipients to encrypt to"))?;
+
+    let format = if armor {
+        age::armor::Format::AsciiArmor
+    } else {
+        age::armor::Format::Binary
+    };
 
     let mut encrypted = vec![];
-    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    let armored = age::armor::ArmoredWriter::wrap_output(&mut encrypted, format)?;
+    let mut writer = encryptor.wrap_output(armored)?;
     writer.write_all(data)?;
-    writer.finish()?;
+    writer.finish()?.finish()?;
 
     Ok(encrypted)
 }
 
-pub fn decrypt(encrypted_data: &[u8], identity: &age::x25519::Identity) -> Result<Vec<u8>> {
-    let decryptor = match age::Decryptor::new(encrypted_data)? {
+pub fn is_armored(data: &[u8]) -> bool {
+    data.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----")
+}
+
+/// Re-encodes a binary age file as ASCII armor. No key is needed: armor only
+/// changes the encoding, not the ciphertext.
+pub fn armor(data: &[u8]) -> Result<Vec<u8>> {
+    if is_armored(data) {
+        return Ok(data.to_vec());
+    }
+    let mut armored = vec![];
+    let mut writer =
+        age::armor::ArmoredWriter::wrap_output(&mut armored, age::armor::Format::AsciiArmor)?;
+    writer.write_all(data)?;
+    writer.finish()?;
+    Ok(armored)
+}
+
+pub fn decrypt(encrypted_data: &[u8], identity: &SecretIdentity) -> Result<Vec<u8>> {
+    let decryptor = match age::Decryptor::new(age::armor::ArmoredReader::new(encrypted_data))? {
         age::Decryptor::Recipients(d) => d,
         _ => anyhow::bail!("Unsupported encrypted format"),
     };
 
     let mut decrypted = vec![];
-    let mut reader = decryptor.decrypt(std::iter::once(identity as &dyn age::Identity))?;
+    let mut reader = decryptor.decrypt(std::iter::once(identity.as_identity()))?;
+    reader.read_to_end(&mut decrypted)?;
+
+    Ok(decrypted)
+}
+
+/// Encrypts `data` with an scrypt passphrase and returns ASCII-armored output.
+pub fn encrypt_with_passphrase(data: &[u8], passphrase: &SecretString) -> Result<Vec<u8>> {
+    let encryptor = age::Encryptor::with_user_passphrase(passphrase.clone());
+
+    let mut encrypted = vec![];
+    let armored =
+        age::armor::ArmoredWriter::wrap_output(&mut encrypted, age::armor::Format::AsciiArmor)?;
+    let mut writer = encryptor.wrap_output(armored)?;
+    writer.write_all(data)?;
+    writer.finish()?.finish()?;
+
+    Ok(encrypted)
+}
+
+/// Decrypts passphrase-encrypted data, armored or binary.
+pub fn decrypt_with_passphrase(
+    encrypted_data: &[u8],
+    passphrase: &SecretString,
+) -> Result<Vec<u8>> {
+    let decryptor = match age::Decryptor::new(age::armor::ArmoredReader::new(encrypted_data))? {
+        age::Decryptor::Passphrase(d) => d,
+        _ => anyhow::bail!("Data is not passphrase-encrypted"),
+    };
+
+    let mut decrypted = vec![];
+    let mut reader = decryptor.decrypt(passphrase, None)?;
     reader.read_to_end(&mut decrypted)?;
 
     Ok(decrypted)
@@ -0,0 +1,277 @@
+use crate::core::config::schema::{FileConfig, TeamConfig};
+use crate::core::git::repository::GitRepository;
+use crate::core::roles::RoleResolver;
+use anyhow::{Context, Result};
+use chrono::{Days, NaiveDate};
+
+/// Secrets expiring or due for rotation within this many days are reported.
+pub const WARNING_DAYS: i64 = 14;
+
+/// Accepts `YYYY-MM-DD` or an RFC 3339 timestamp.
+pub fn parse_date(value: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| chrono::DateTime::parse_from_rfc3339(value).map(|d| d.date_naive()))
+        .with_context(|| format!("Invalid date '{}' (expected YYYY-MM-DD)", value))
+}
+
+/// Parses intervals such as "90d", "12w", "6m" or "1y" into days
+/// (months count as 30 days, years as 365).
+pub fn parse_interval(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let invalid = || {
+        format!(
+            "Invalid interval '{}' (expected e.g. 90d, 12w, 6m, 1y)",
+            value
+        )
+    };
+    let unit = value.chars().last().with_context(invalid)?;
+    let count: u64 = value[..value.len() - unit.len_utf8()]
+        .parse()
+        .with_context(invalid)?;
+    let days = match unit {
+        'd' => 1,
+        'w' => 7,
+        'm' => 30,
+        'y' => 365,
+        _ => anyhow::bail!(invalid()),
+    };
+    count.checked_mul(days).with_context(invalid)
+}
+
+pub fn today() -> NaiveDate {
+    chrono::Local::now().date_naive()
+}
+
+/// When the secret was last rotated: `rotated_at`, or for entries tracked
+/// before it was recorded, the date of the last commit that changed the source.
+pub fn rotated_on(file: &FileConfig, repo: Option<&GitRepository>) -> Result<Option<NaiveDate>> {
+    if let Some(rotated) = file.rotated_at.as_deref() {
+        return parse_date(rotated).map(Some);
+    }
+    let Some(repo) = repo else {
+        return Ok(None);
+    };
+    Ok(repo
+        .last_commit_touching(&file.source)?
+        .and_then(|commit| repo.commit_time(&commit))
+        .and_then(|seconds| chrono::DateTime::from_timestamp(seconds, 0))
+        .map(|time| time.date_naive()))
+}
+
+/// A secret's next deadline: the earlier of `expires_at` and
+/// `rotated_on + rotate_every`.
+pub struct Deadline {
+    pub date: NaiveDate,
+    /// "expires" or "rotation due"
+    pub kind: &'static str,
+}
+
+pub fn deadline(file: &FileConfig, rotated_on: Option<NaiveDate>) -> Result<Option<Deadline>> {
+    let expires = file
+        .expires_at
+        .as_deref()
+        .map(parse_date)
+        .transpose()?
+        .map(|date| Deadline {
+            date,
+            kind: "expires",
+        });
+    let rotation = match (file.rotate_every.as_deref(), rotated_on) {
+        (Some(every), Some(rotated)) => {
+            let date = rotated
+                .checked_add_days(Days::new(parse_interval(every)?))
+                .context("Rotation date out of range")?;
+            Some(Deadline {
+                date,
+                kind: "rotation due",
+            })
+        }
+        _ => None,
+    };
+    Ok(match (expires, rotation) {
+        (Some(a), Some(b)) => Some(if b.date < a.date { b } else { a }),
+        (a, b) => a.or(b),
+    })
+}
+
+/// Human-readable distance to a deadline, e.g. "in 5 day(s)" or "3 day(s) ago".
+pub fn describe(deadline: &Deadline, today: NaiveDate) -> String {
+    let days = (deadline.date - today).num_days();
+    let when = match days {
+        0 => "today".to_string(),
+        d if d > 0 => format!("in {} day(s)", d),
+        d => format!("{} day(s) ago", -d),
+    };
+    format!("{} {} ({})", deadline.kind, when, deadline.date)
+}
+
+pub struct Reminder<'a> {
+    pub file: &'a FileConfig,
+    /// `None` when the secret has `rotate_every` but no known rotation date.
+    pub deadline: Option<Deadline>,
+    pub overdue: bool,
+}
+
+impl Reminder<'_> {
+    pub fn describe(&self, today: NaiveDate) -> String {
+        match &self.deadline {
+            Some(deadline) => describe(deadline, today),
+            None => format!(
+                "should be rotated every {} but was never recorded as rotated",
+                self.file.rotate_every.as_deref().unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Secrets applying to this machine that are past, or within `WARNING_DAYS`
+/// of, their deadline, or that have `rotate_every` but no known rotation date.
+pub fn reminders<'a>(
+    config: &'a TeamConfig,
+    resolver: &RoleResolver,
+    repo: Option<&GitRepository>,
+    today: NaiveDate,
+) -> Result<Vec<Reminder<'a>>> {
+    let mut reminders = Vec::new();
+    let secrets = config
+        .files
+        .iter()
+        .filter(|f| f.file_type.is_secret() && resolver.matches(f));
+    for file in secrets {
+        let invalid = || format!("Invalid expiry settings for '{}'", file.name());
+        let rotated = rotated_on(file, repo).with_context(invalid)?;
+        if file.rotate_every.is_some() && rotated.is_none() {
+            reminders.push(Reminder {
+                file,
+                deadline: None,
+                overdue: false,
+            });
+        }
+        if let Some(deadline) = deadline(file, rotated).with_context(invalid)? {
+            let days = (deadline.date - today).num_days();
+            if days <= WARNING_DAYS {
+                reminders.push(Reminder {
+                    file,
+                    overdue: days < 0,
+                    deadline: Some(deadline),
+                });
+            }
+        }
+    }
+    Ok(reminders)
+}
+
+/// Prints a warning line per reminder. Returns how many were printed and
+/// whether any secret is overdue.
+pub fn print_reminders(
+    config: &TeamConfig,
+    resolver: &RoleResolver,
+    repo: Option<&GitRepository>,
+) -> Result<(usize, bool)> {
+    let today = today();
+    let reminders = reminders(config, resolver, repo, today)?;
+    for reminder in &reminders {
+        let icon = if reminder.overdue { "❌" } else { "⚠️" };
+        println!(
+            "{} Secret '{}' {}",
+            icon,
+            reminder.file.name(),
+            reminder.describe(today)
+        );
+    }
+    Ok((reminders.len(), reminders.iter().any(|r| r.overdue)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        parse_date(value).expect("valid date")
+    }
+
+    #[test]
+    fn parse_interval_converts_units_to_days() {
+        assert_eq!(parse_interval("90d").unwrap(), 90);
+        assert_eq!(parse_interval("12w").unwrap(), 84);
+        assert_eq!(parse_interval("6m").unwrap(), 180);
+        assert_eq!(parse_interval("1y").unwrap(), 365);
+        assert_eq!(parse_interval(" 3d ").unwrap(), 3);
+        assert_eq!(parse_interval("0d").unwrap(), 0);
+    }
+
+    #[test]
+    fn parse_interval_rejects_malformed_input() {
+        for input in [
+            "",
+            "d",
+            "90",
+            "90x",
+            "-5d",
+            "1.5w",
+            "d90",
+            "90 d",
+            "90D",
+            "3000000000000000000y",
+        ] {
+            let err = parse_interval(input).expect_err(input);
+            assert!(
+                err.to_string().starts_with("Invalid interval"),
+                "{}: {}",
+                input,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn parse_date_accepts_dates_and_timestamps() {
+        assert_eq!(
+            date("2026-10-19"),
+            NaiveDate::from_ymd_opt(2026, 10, 19).unwrap()
+        );
+        assert_eq!(date("2026-10-19T23:30:00+02:00"), date("2026-10-19"));
+        assert!(parse_date("19/10/2026").is_err());
+        assert!(parse_date("2026-02-30").is_err());
+    }
+
+    fn config(files: &str) -> TeamConfig {
+        toml::from_str(&format!(
+            "[team]\nname = \"t\"\nmaintainers = []\n[repository]\nurl = \"\"\nbranch = \"main\"\n{}",
+            files
+        ))
+        .expect("valid config")
+    }
+
+    #[test]
+    fn deadline_is_the_earlier_of_expiry_and_rotation() {
+        let config = config(
+            "[[files]]\nsource = \"secrets/a.age\"\ndestination = \"~/a\"\ntype = \"secret\"\nexpires_at = \"2026-03-01\"\nrotate_every = \"30d\"\n",
+        );
+        let file = &config.files[0];
+        let due = deadline(file, Some(date("2026-01-01"))).unwrap().unwrap();
+        assert_eq!((due.date, due.kind), (date("2026-01-31"), "rotation due"));
+        let due = deadline(file, None).unwrap().unwrap();
+        assert_eq!((due.date, due.kind), (date("2026-03-01"), "expires"));
+    }
+
+    #[test]
+    fn reminders_skip_other_machines_and_flag_unknown_rotation() {
+        let config = config(concat!(
+            "[[files]]\nsource = \"secrets/work.age\"\ndestination = \"~/work\"\ntype = \"secret\"\nroles = [\"work\"]\nexpires_at = \"2020-01-01\"\n",
+            "[[files]]\nsource = \"secrets/ci.age\"\ndestination = \"~/ci\"\ntype = \"secret\"\nroles = [\"ci\"]\nexpires_at = \"2020-01-01\"\n",
+            "[[files]]\nsource = \"secrets/old.age\"\ndestination = \"~/old\"\ntype = \"secret\"\nrotate_every = \"90d\"\n",
+        ));
+        let resolver = RoleResolver::new(&["work".to_string()], &config.roles);
+        let today = date("2026-10-19");
+        let reminders = reminders(&config, &resolver, None, today).unwrap();
+
+        let names: Vec<&str> = reminders.iter().map(|r| r.file.name()).collect();
+        assert_eq!(names, ["work", "old"]);
+        assert!(reminders[0].overdue);
+        assert!(!reminders[1].overdue);
+        assert!(reminders[1]
+            .describe(today)
+            .contains("never recorded as rotated"));
+    }
+}
@@ -1,7 +1,15 @@
+use crate::core::config::schema::SecretsConfig;
 use anyhow::{Context, Result};
 use directories::ProjectDirs;
+use secrecy::SecretString;
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const PASSPHRASE_ENV: &str = "CONFIGSYNC_KEY_PASSPHRASE";
+const BACKUP_PASSPHRASE_ENV: &str = "CONFIGSYNC_BACKUP_PASSPHRASE";
 
 pub fn get_key_path() -> Result<PathBuf> {
     let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
@@ -18,6 +26,19 @@ pub fn generate_key() -> Result<String> {
 }
 
 pub fn save_key(key: &str) -> Result<()> {
+    write_key_file(key.as_bytes())
+}
+
+/// Saves the identity encrypted with an age scrypt passphrase (ASCII-armored).
+pub fn save_protected_key(key: &str, passphrase: &SecretString) -> Result<()> {
+    let encrypted =
+        crate::core::secret::cipher::encrypt_with_passphrase(key.as_bytes(), passphrase)?;
+    write_key_file(&encrypted)?;
+    cache_passphrase(passphrase.clone());
+    Ok(())
+}
+
+fn write_key_file(contents: &[u8]) -> Result<()> {
     let path = get_key_path()?;
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
@@ -29,7 +50,7 @@ pub fn save_key(key: &str) -> Result<()> {
         // checking permissions on the parent dir would be good too, but let's focus on file
     }
 
-    fs::write(&path, key).context("Failed to write key file")?;
+    fs::write(&path, contents).context("Failed to write key file")?;
 
     #[cfg(unix)]
     {
@@ -42,12 +63,223 @@ pub fn save_key(key: &str) -> Result<()> {
     Ok(())
 }
 
-pub fn load_key() -> Result<age::x25519::Identity> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyForm {
+    Plain,
+    Passphrase,
+}
+
+/// Reports how the key file on disk is stored.
+pub fn key_form() -> Result<KeyForm> {
+    let path = get_key_path()?;
+    let content = fs::read(&path).context("Failed to read key file")?;
+    Ok(if is_protected(&content) {
+        KeyForm::Passphrase
+    } else {
+        KeyForm::Plain
+    })
+}
+
+fn is_protected(content: &[u8]) -> bool {
+    content.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----")
+        || content.starts_with(b"age-encryption.org/")
+}
+
+/// Passphrase remembered for the rest of this process, so we only prompt once per command.
+static PASSPHRASE: Mutex<Option<SecretString>> = Mutex::new(None);
+
+fn cache_passphrase(passphrase: SecretString) {
+    if let Ok(mut cached) = PASSPHRASE.lock() {
+        *cached = Some(passphrase);
+    }
+}
+
+fn forget_passphrase() {
+    if let Ok(mut cached) = PASSPHRASE.lock() {
+        *cached = None;
+    }
+}
+
+/// Returns the key passphrase from the process cache, `CONFIGSYNC_KEY_PASSPHRASE`,
+/// or an interactive prompt, in that order.
+pub fn passphrase() -> Result<SecretString> {
+    if let Ok(cached) = PASSPHRASE.lock() {
+        if let Some(p) = cached.as_ref() {
+            return Ok(p.clone());
+        }
+    }
+
+    let passphrase =
+        read_passphrase("Key passphrase: ").context("Key file is passphrase-protected")?;
+
+    cache_passphrase(passphrase.clone());
+    Ok(passphrase)
+}
+
+/// Reads a passphrase from `CONFIGSYNC_KEY_PASSPHRASE` or prompts for it on the terminal.
+pub fn read_passphrase(prompt: &str) -> Result<SecretString> {
+    prompt_passphrase(PASSPHRASE_ENV, prompt)
+}
+
+/// Asks for a new passphrase (twice when interactive).
+pub fn new_passphrase() -> Result<SecretString> {
+    prompt_new_passphrase(PASSPHRASE_ENV)
+}
+
+/// Reads the passphrase of a key backup from `CONFIGSYNC_BACKUP_PASSPHRASE` or
+/// a prompt. It is deliberately separate from the key file's passphrase.
+pub fn read_backup_passphrase() -> Result<SecretString> {
+    prompt_passphrase(BACKUP_PASSPHRASE_ENV, "Backup passphrase: ")
+}
+
+/// Asks for the passphrase a new key backup is encrypted with.
+pub fn new_backup_passphrase() -> Result<SecretString> {
+    prompt_new_passphrase(BACKUP_PASSPHRASE_ENV)
+}
+
+fn prompt_passphrase(env: &str, prompt: &str) -> Result<SecretString> {
+    if let Ok(value) = std::env::var(env) {
+        return Ok(SecretString::new(value));
+    }
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!(
+            "Cannot prompt for a passphrase. Set {} for non-interactive use.",
+            env
+        );
+    }
+    Ok(SecretString::new(
+        rpassword::prompt_password(prompt).context("Failed to read passphrase")?,
+    ))
+}
+
+fn prompt_new_passphrase(env: &str) -> Result<SecretString> {
+    if let Ok(value) = std::env::var(env) {
+        return Ok(SecretString::new(value));
+    }
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!(
+            "Cannot prompt for a passphrase. Set {} for non-interactive use.",
+            env
+        );
+    }
+
+    let first =
+        rpassword::prompt_password("New passphrase: ").context("Failed to read passphrase")?;
+    let second =
+        rpassword::prompt_password("Confirm passphrase: ").context("Failed to read passphrase")?;
+    if first != second {
+        anyhow::bail!("Passphrases do not match");
+    }
+    if first.is_empty() {
+        anyhow::bail!("Passphrase must not be empty");
+    }
+    Ok(SecretString::new(first))
+}
+
+/// A private key that can decrypt secrets: the configsync age key or an SSH key.
+#[derive(Clone)]
+pub enum SecretIdentity {
+    Age(age::x25519::Identity),
+    Ssh {
+        identity: Box<age::ssh::Identity>,
+        recipient: age::ssh::Recipient,
+        path: PathBuf,
+    },
+}
+
+impl SecretIdentity {
+    pub fn as_identity(&self) -> &dyn age::Identity {
+        match self {
+            SecretIdentity::Age(identity) => identity,
+            SecretIdentity::Ssh { identity, .. } => identity.as_ref(),
+        }
+    }
+
+    pub fn to_recipient(&self) -> SecretRecipient {
+        match self {
+            SecretIdentity::Age(identity) => SecretRecipient::Age(identity.to_public()),
+            SecretIdentity::Ssh { recipient, .. } => SecretRecipient::Ssh(recipient.clone()),
+        }
+    }
+}
+
+/// A public key secrets are encrypted to: an `age1...` key or an `ssh-ed25519`/`ssh-rsa` key.
+#[derive(Clone)]
+pub enum SecretRecipient {
+    Age(age::x25519::Recipient),
+    Ssh(age::ssh::Recipient),
+}
+
+impl SecretRecipient {
+    pub fn boxed(&self) -> Box<dyn age::Recipient + Send> {
+        match self {
+            SecretRecipient::Age(r) => Box::new(r.clone()),
+            SecretRecipient::Ssh(r) => Box::new(r.clone()),
+        }
+    }
+}
+
+impl std::str::FromStr for SecretRecipient {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.starts_with("ssh-") {
+            // Published keys usually carry a trailing comment; age only wants "type base64".
+            let key = s.split_whitespace().take(2).collect::<Vec<_>>().join(" ");
+            key.parse::<age::ssh::Recipient>()
+                .map(SecretRecipient::Ssh)
+                .map_err(|e| anyhow::anyhow!("Invalid SSH recipient {:?}: {:?}", s, e))
+        } else {
+            s.parse::<age::x25519::Recipient>()
+                .map(SecretRecipient::Age)
+                .map_err(|e| anyhow::anyhow!("Invalid recipient {:?}: {}", s, e))
+        }
+    }
+}
+
+impl std::fmt::Display for SecretRecipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SecretRecipient::Age(r) => write!(f, "{}", r),
+            SecretRecipient::Ssh(r) => write!(f, "{}", r),
+        }
+    }
+}
+
+/// Loads the configsync key file, falling back to `~/.ssh/id_ed25519` / `~/.ssh/id_rsa`.
+pub fn load_key() -> Result<SecretIdentity> {
     let path = get_key_path()?;
-    if !path.exists() {
-        anyhow::bail!("No key found. Run `configsync secrets init` first.");
+    if path.exists() {
+        return load_key_file(&path).map(SecretIdentity::Age);
     }
-    let content = fs::read_to_string(path)?;
+
+    for candidate in ssh_key_paths() {
+        if candidate.exists() {
+            return load_ssh_key(&candidate);
+        }
+    }
+
+    anyhow::bail!(
+        "No key found. Run `configsync secrets init` first, or create an SSH key at ~/.ssh/id_ed25519."
+    );
+}
+
+pub fn load_key_file(path: &Path) -> Result<age::x25519::Identity> {
+    let content = fs::read(path)?;
+    let content = if is_protected(&content) {
+        let passphrase = passphrase()?;
+        match crate::core::secret::cipher::decrypt_with_passphrase(&content, &passphrase) {
+            Ok(decrypted) => decrypted,
+            Err(e) => {
+                forget_passphrase();
+                return Err(e.context("Failed to unlock key file (wrong passphrase?)"));
+            }
+        }
+    } else {
+        content
+    };
+    let content = String::from_utf8(content).context("Key file is not valid UTF-8")?;
     let key = content
         .trim()
         .parse::<age::x25519::Identity>()
@@ -55,6 +287,146 @@ pub fn load_key() -> Result<age::x25519::Identity> {
     Ok(key)
 }
 
-pub fn get_public_key(identity: &age::x25519::Identity) -> age::x25519::Recipient {
-    identity.to_public()
+pub fn ssh_key_paths() -> Vec<PathBuf> {
+    ["~/.ssh/id_ed25519", "~/.ssh/id_rsa"]
+        .iter()
+        .map(|p| PathBuf::from(shellexpand::tilde(p).into_owned()))
+        .collect()
+}
+
+fn load_ssh_key(path: &Path) -> Result<SecretIdentity> {
+    let file = fs::File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let identity = age::ssh::Identity::from_buffer(
+        std::io::BufReader::new(file),
+        Some(path.to_string_lossy().to_string()),
+    )
+    .with_context(|| format!("Failed to parse SSH key {:?}", path))?;
+
+    let identity = match identity {
+        age::ssh::Identity::Encrypted(key) => {
+            if !std::io::stdin().is_terminal() {
+                anyhow::bail!(
+                    "SSH key {:?} is passphrase-protected and no terminal is available to prompt.",
+                    path
+                );
+            }
+            let passphrase = rpassword::prompt_password(format!("Passphrase for {:?}: ", path))
+                .context("Failed to read passphrase")?;
+            let decrypted = key
+                .decrypt(SecretString::new(passphrase))
+                .map_err(|e| anyhow::anyhow!("Failed to decrypt SSH key {:?}: {}", path, e))?;
+            age::ssh::Identity::Unencrypted(decrypted)
+        }
+        age::ssh::Identity::Unsupported(_) => {
+            anyhow::bail!("SSH key {:?} has an unsupported type", path)
+        }
+        unencrypted => unencrypted,
+    };
+
+    let recipient = age::ssh::Recipient::try_from(identity.clone())
+        .map_err(|e| anyhow::anyhow!("Failed to derive public key for {:?}: {:?}", path, e))?;
+
+    Ok(SecretIdentity::Ssh {
+        identity: Box::new(identity),
+        recipient,
+        path: path.to_path_buf(),
+    })
+}
+
+pub fn get_public_key(identity: &SecretIdentity) -> SecretRecipient {
+    identity.to_recipient()
+}
+
+/// Returns the recipients a secret is encrypted to: the local public key, plus the
+/// per-role sets for `roles` when any are configured, otherwise `secrets.recipients`.
+pub fn recipients_for(
+    identity: &SecretIdentity,
+    secrets: &SecretsConfig,
+    implies: &BTreeMap<String, Vec<String>>,
+    roles: Option<&[String]>,
+) -> Result<Vec<SecretRecipient>> {
+    let own = get_public_key(identity);
+    let mut recipients = vec![own.clone()];
+    let entries = role_recipient_entries(secrets, implies, roles)
+        .unwrap_or_else(|| secrets.recipients.iter().collect());
+    for entry in entries {
+        let recipient = entry.parse::<SecretRecipient>()?;
+        if !recipients
+            .iter()
+            .any(|r| r.to_string() == recipient.to_string())
+        {
+            recipients.push(recipient);
+        }
+    }
+    Ok(recipients)
+}
+
+/// The union of `secrets.role_recipients` for every role that can make the
+/// entry's `roles` expressions true (including roles implying them through
+/// `implies`), or `None` when none of those roles has a dedicated recipient set.
+pub fn role_recipient_entries<'a>(
+    secrets: &'a SecretsConfig,
+    implies: &BTreeMap<String, Vec<String>>,
+    roles: Option<&[String]>,
+) -> Option<Vec<&'a String>> {
+    let scoped = crate::core::roles::scoped_roles(roles.unwrap_or_default(), implies);
+    let sets: Vec<&Vec<String>> = scoped
+        .iter()
+        .filter_map(|role| secrets.role_recipients.get(role))
+        .collect();
+    if sets.is_empty() {
+        return None;
+    }
+    let mut entries: Vec<&String> = Vec::new();
+    for entry in sets.into_iter().flatten() {
+        if !entries.contains(&entry) {
+            entries.push(entry);
+        }
+    }
+    Some(entries)
+}
+
+pub fn get_archive_dir() -> Result<PathBuf> {
+    let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
+        .context("Could not determine project directories")?;
+    Ok(proj_dirs.data_local_dir().join("key-archive"))
+}
+
+/// Copies the current key file into the archive directory and returns the archived path.
+/// Never overwrites an earlier archive: names that are taken get a numeric suffix.
+pub fn archive_key() -> Result<PathBuf> {
+    use std::io::Write;
+
+    let path = get_key_path()?;
+    let archive_dir = get_archive_dir()?;
+    fs::create_dir_all(&archive_dir).context("Failed to create key archive directory")?;
+    let contents = fs::read(&path).context("Failed to read key file")?;
+
+    let stamp = chrono::Local::now().format("%Y%m%d%H%M%S");
+    let mut attempt = 0;
+    loop {
+        let name = match attempt {
+            0 => format!("key-{}.txt", stamp),
+            n => format!("key-{}-{}.txt", stamp, n),
+        };
+        let archived = archive_dir.join(name);
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        match options.open(&archived) {
+            Ok(mut file) => {
+                if let Err(e) = file.write_all(&contents) {
+                    let _ = fs::remove_file(&archived);
+                    return Err(e).context("Failed to archive key file");
+                }
+                return Ok(archived);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e).context("Failed to archive key file"),
+        }
+    }
 }
@@ -1,2 +1,6 @@
+pub mod archive;
+pub mod backend;
 pub mod cipher;
+pub mod expiry;
 pub mod keys;
+pub mod values;
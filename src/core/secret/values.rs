@@ -0,0 +1,132 @@
+use crate::core::config::schema::SecretsConfig;
+use crate::core::secret::{cipher, keys};
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Encrypted key/value store backing `{{ secret "name" }}` placeholders.
+pub const VALUES_SOURCE: &str = "secrets/values.age";
+
+const PLACEHOLDER: &str = r#"\{\{\s*secret\s+"([^"]+)"\s*\}\}"#;
+
+/// Decrypted inline values, wiped on drop.
+#[derive(Default)]
+pub struct Values(BTreeMap<String, String>);
+
+impl Drop for Values {
+    fn drop(&mut self) {
+        for value in self.0.values_mut() {
+            value.zeroize();
+        }
+    }
+}
+
+impl Values {
+    /// Loads the store, or an empty one if none has been created yet.
+    pub fn load(config_dir: &Path, identity: &keys::SecretIdentity) -> Result<Self> {
+        let path = config_dir.join(VALUES_SOURCE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let encrypted = fs::read(&path).context("Failed to read inline secret values")?;
+        let plaintext = Zeroizing::new(cipher::decrypt(&encrypted, identity)?);
+        let text = std::str::from_utf8(&plaintext).context("Inline secret values are not UTF-8")?;
+        let values = toml::from_str(text).context("Failed to parse inline secret values")?;
+        Ok(Self(values))
+    }
+
+    /// Encrypts the store to the global recipients.
+    pub fn save(
+        &self,
+        config_dir: &Path,
+        identity: &keys::SecretIdentity,
+        secrets: &SecretsConfig,
+    ) -> Result<()> {
+        let plaintext =
+            Zeroizing::new(toml::to_string(&self.0).context("Failed to serialize values")?);
+        let recipients = keys::recipients_for(identity, secrets, &Default::default(), None)?;
+        let encrypted = cipher::encrypt(plaintext.as_bytes(), &recipients, secrets.armor)?;
+        let path = config_dir.join(VALUES_SOURCE);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, encrypted).context("Failed to write inline secret values")
+    }
+
+    pub fn set(&mut self, name: &str, value: String) {
+        if let Some(mut old) = self.0.insert(name.to_string(), value) {
+            old.zeroize();
+        }
+    }
+
+    /// Removes a value; returns false if it did not exist.
+    pub fn remove(&mut self, name: &str) -> bool {
+        match self.0.remove(name) {
+            Some(mut old) => {
+                old.zeroize();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.0.keys()
+    }
+
+    /// Substitutes every placeholder in `template`, failing on unknown names.
+    pub fn render(&self, template: &str) -> Result<Zeroizing<String>> {
+        let missing: Vec<String> = placeholders(template)
+            .into_iter()
+            .filter(|name| !self.0.contains_key(name))
+            .collect();
+        if !missing.is_empty() {
+            anyhow::bail!(
+                "No value for placeholder(s) {}. Set them with `configsync secrets set <name>`.",
+                missing.join(", ")
+            );
+        }
+        let regex = Regex::new(PLACEHOLDER)?;
+        let rendered =
+            regex.replace_all(template, |caps: &regex::Captures| self.0[&caps[1]].clone());
+        Ok(Zeroizing::new(rendered.into_owned()))
+    }
+}
+
+/// Names referenced by `{{ secret "name" }}` placeholders, in order of appearance.
+pub fn placeholders(template: &str) -> Vec<String> {
+    let regex = Regex::new(PLACEHOLDER).expect("placeholder pattern is valid");
+    let mut names: Vec<String> = Vec::new();
+    for caps in regex.captures_iter(template) {
+        if !names.iter().any(|n| n == &caps[1]) {
+            names.push(caps[1].to_string());
+        }
+    }
+    names
+}
+
+/// Renders the template stored at `source`, loading the value store into
+/// `cache` on first use (templates without placeholders need no key).
+pub fn render_file(
+    config_dir: &Path,
+    source: &str,
+    cache: &mut Option<Values>,
+) -> Result<Zeroizing<String>> {
+    let template = fs::read_to_string(config_dir.join(source))
+        .with_context(|| format!("Failed to read template {:?}", source))?;
+    if placeholders(&template).is_empty() {
+        return Ok(Zeroizing::new(template));
+    }
+    let values = match cache {
+        Some(values) => values,
+        None => {
+            let identity = keys::load_key()
+                .context("Failed to load secret key. Have you run `configsync secrets init`?")?;
+            cache.insert(Values::load(config_dir, &identity)?)
+        }
+    };
+    values.render(&template)
+}
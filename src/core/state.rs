@@ -1,9 +1,9 @@
-use anyhow::{Context, Result};
-use directories::ProjectDirs;
+use crate::core::db::StateDb;
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::PathBuf;
 
+/// The machine's roles. Stored in the state database; the `Deserialize` impl
+/// reads the old `state.toml` during migration.
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct LocalState {
     pub roles: Vec<String>,
@@ -11,33 +11,15 @@ pub struct LocalState {
 
 impl LocalState {
     pub fn load() -> Result<Self> {
-        let path = Self::get_path()?;
-        if !path.exists() {
-            return Ok(Self::default());
-        }
-
-        let content = fs::read_to_string(&path).context("Failed to read local state file")?;
-        let state: LocalState =
-            toml::from_str(&content).context("Failed to parse local state file")?;
-        Ok(state)
+        Self::from_db(&StateDb::open()?)
     }
 
-    pub fn save(&self) -> Result<()> {
-        let path = Self::get_path()?;
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).context("Failed to create local state directory")?;
-        }
-
-        let content = toml::to_string_pretty(self).context("Failed to serialize local state")?;
-        fs::write(path, content).context("Failed to write local state file")?;
-        Ok(())
+    pub fn from_db(db: &StateDb) -> Result<Self> {
+        Ok(Self { roles: db.roles()? })
     }
 
-    pub fn get_path() -> Result<PathBuf> {
-        let proj_dirs = ProjectDirs::from("com", "configsync", "configsync")
-            .context("Could not determine project directories")?;
-        // Use data_local_dir for machine-specific state (not synced)
-        Ok(proj_dirs.data_local_dir().join("state.toml"))
+    pub fn save(&self) -> Result<()> {
+        StateDb::open()?.set_roles(&self.roles)
     }
 
     pub fn add_role(&mut self, role: &str) {
@@ -46,6 +28,13 @@ impl LocalState {
         }
     }
 
+    /// Returns false if the machine did not have the role.
+    pub fn remove_role(&mut self, role: &str) -> bool {
+        let before = self.roles.len();
+        self.roles.retain(|r| r != role);
+        self.roles.len() != before
+    }
+
     pub fn has_role(&self, role: &str) -> bool {
         self.roles.iter().any(|r| r == role)
     }
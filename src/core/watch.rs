@@ -1,8 +1,12 @@
+use crate::core::config::loader::ConfigLoader;
 use crate::core::git::repository::GitRepository;
+use crate::core::scan;
+use crate::core::secret::expiry;
 use anyhow::{Context, Result};
 use directories::ProjectDirs;
 use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode};
-use std::sync::mpsc::channel;
+use std::path::Path;
+use std::sync::mpsc::{channel, RecvTimeoutError};
 use std::time::Duration;
 
 pub fn start() -> Result<()> {
@@ -31,8 +35,21 @@ pub fn start() -> Result<()> {
         .watch(config_dir, RecursiveMode::Recursive)
         .context("Failed to start watcher")?;
 
-    // Since this is a CLI tool, we just block on the receiver loop
-    for result in rx {
+    // Secret expiry reminders are shown at start-up and then once per day.
+    let mut reminded_on = None;
+    remind_expiring_secrets(config_dir, &mut reminded_on);
+
+    // Since this is a CLI tool, we just block on the receiver loop, waking up
+    // hourly to check whether a new day's reminders are due.
+    loop {
+        let result = match rx.recv_timeout(Duration::from_secs(3600)) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                remind_expiring_secrets(config_dir, &mut reminded_on);
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
         match result {
             Ok(events) => {
                 // Filter out .git changes
@@ -42,7 +59,7 @@ pub fn start() -> Result<()> {
 
                 if has_relevant_changes {
                     println!("Changes detected. Syncing...");
-                    match sync_changes(&repo) {
+                    match sync_changes(&repo, config_dir) {
                         Ok(_) => println!("Synced successfully."),
                         Err(e) => eprintln!("Failed to sync: {:#}", e),
                     }
@@ -55,7 +72,34 @@ pub fn start() -> Result<()> {
     Ok(())
 }
 
-fn sync_changes(repo: &GitRepository) -> Result<()> {
+fn remind_expiring_secrets(config_dir: &Path, reminded_on: &mut Option<chrono::NaiveDate>) {
+    let today = expiry::today();
+    if *reminded_on == Some(today) {
+        return;
+    }
+    *reminded_on = Some(today);
+    let result = ConfigLoader::load(config_dir.join("team-config.toml")).and_then(|config| {
+        let state = crate::core::state::LocalState::load()?;
+        let resolver = crate::core::roles::RoleResolver::for_machine(&state, &config);
+        let repo = GitRepository::open(config_dir).ok();
+        expiry::print_reminders(&config, &resolver, repo.as_ref())
+    });
+    if let Err(e) = result {
+        eprintln!("Failed to check secret expiry: {:#}", e);
+    }
+}
+
+fn sync_changes(repo: &GitRepository, config_dir: &Path) -> Result<()> {
+    // Unattended syncs never override the leak guard.
+    let config = ConfigLoader::load(config_dir.join("team-config.toml"))?;
+    let findings = scan::scan_changes(repo, config_dir, &config.scan)?;
+    if !findings.is_empty() {
+        scan::report(&findings);
+        anyhow::bail!(
+            "Auto-sync paused: possible plaintext secrets. Review them, then run `configsync push` (with --allow-secrets if they are false positives)."
+        );
+    }
+
     // We attempt to commit. If there are no changes, commit_all might fail or do nothing.
     // Ideally we should check status, but for MVP let's just try.
     // GitRepository::commit_all currently adds all and commits.
@@ -247,3 +247,2247 @@ fn apply_reports_conflict_for_existing_regular_file_destination() {
     );
     assert!(output_text(&apply).contains("Destination exists and is not a symlink."));
 }
+
+#[test]
+fn secrets_rotate_reencrypts_and_archives_old_key() {
+    let home = make_temp_home("secret-rotate");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(
+        keygen.status.success(),
+        "secrets init failed: {}",
+        output_text(&keygen)
+    );
+
+    let secret_path = home.join("token.env");
+    fs::write(&secret_path, "TOKEN=abc").expect("failed to create secret file");
+    let add_secret = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            secret_path.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(
+        add_secret.status.success(),
+        "secrets add failed: {}",
+        output_text(&add_secret)
+    );
+
+    let key_path = home.join(".local/share/configsync/key.txt");
+    let old_key = fs::read_to_string(&key_path).expect("key should exist");
+
+    let rotate = run(&home, &["secrets", "rotate"]);
+    assert!(
+        rotate.status.success(),
+        "secrets rotate failed: {}",
+        output_text(&rotate)
+    );
+
+    let new_key = fs::read_to_string(&key_path).expect("key should exist");
+    assert_ne!(old_key, new_key, "rotation should replace the key");
+    let archived = fs::read_dir(home.join(".local/share/configsync/key-archive"))
+        .expect("archive dir should exist")
+        .count();
+    assert_eq!(archived, 1);
+
+    // The secret must still decrypt with the new key.
+    fs::remove_file(&secret_path).expect("failed to remove plaintext");
+    let apply = run(&home, &["apply"]);
+    assert!(
+        apply.status.success(),
+        "apply failed: {}",
+        output_text(&apply)
+    );
+    assert_eq!(
+        fs::read_to_string(&secret_path).expect("secret should be restored"),
+        "TOKEN=abc"
+    );
+
+    let history = run(&home, &["history"]);
+    assert!(output_text(&history).contains("Rotate secret key"));
+}
+
+#[test]
+fn secrets_rotate_rolls_back_when_commit_fails() {
+    let home = make_temp_home("secret-rotate-rollback");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+
+    let secret_path = home.join("token.env");
+    fs::write(&secret_path, "TOKEN=abc").expect("failed to create secret file");
+    let add_secret = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            secret_path.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(add_secret.status.success());
+
+    let config_dir = home.join(".config/configsync");
+    let key_path = home.join(".local/share/configsync/key.txt");
+    let archive_dir = home.join(".local/share/configsync/key-archive");
+    let encrypted = config_dir.join("secrets/token.env.age");
+    let old_key = fs::read(&key_path).expect("key should exist");
+    let old_secret = fs::read(&encrypted).expect("encrypted secret should exist");
+    let old_config = fs::read(config_dir.join("team-config.toml")).expect("config should exist");
+
+    // A stale index lock makes the final commit fail after the key was archived.
+    let lock = config_dir.join(".git/index.lock");
+    fs::write(&lock, "").expect("failed to create index lock");
+    let rotate = run(&home, &["secrets", "rotate"]);
+    assert!(!rotate.status.success());
+    assert!(output_text(&rotate).contains("rolling back"));
+    assert_eq!(fs::read(&key_path).expect("key"), old_key);
+    assert_eq!(fs::read(&encrypted).expect("secret"), old_secret);
+    assert_eq!(
+        fs::read(config_dir.join("team-config.toml")).expect("config"),
+        old_config
+    );
+    let archives = || {
+        fs::read_dir(&archive_dir)
+            .map(|entries| entries.count())
+            .unwrap_or(0)
+    };
+    assert_eq!(archives(), 0, "the rolled-back archive must be removed");
+
+    // Back-to-back rotations keep both archived keys, even within one second.
+    fs::remove_file(&lock).expect("failed to remove index lock");
+    for _ in 0..2 {
+        let rotate = run(&home, &["secrets", "rotate"]);
+        assert!(rotate.status.success(), "{}", output_text(&rotate));
+    }
+    assert_eq!(archives(), 2);
+}
+
+#[test]
+fn passphrase_protected_key_decrypts_with_env_passphrase() {
+    let home = make_temp_home("secret-passphrase");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+
+    let run_with_passphrase = |args: &[&str]| {
+        Command::new(bin_path())
+            .args(args)
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", home.join(".config"))
+            .env("XDG_DATA_HOME", home.join(".local/share"))
+            .env("CONFIGSYNC_KEY_PASSPHRASE", "correct horse")
+            .output()
+            .expect("failed to execute configsync")
+    };
+
+    let keygen = run_with_passphrase(&["secrets", "init", "--passphrase"]);
+    assert!(
+        keygen.status.success(),
+        "secrets init failed: {}",
+        output_text(&keygen)
+    );
+    let key_text =
+        fs::read_to_string(home.join(".local/share/configsync/key.txt")).expect("key should exist");
+    assert!(key_text.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+    assert!(!key_text.contains("AGE-SECRET-KEY-"));
+
+    let secret_path = home.join("token.env");
+    fs::write(&secret_path, "TOKEN=abc").expect("failed to create secret file");
+    let add_secret = run_with_passphrase(&[
+        "secrets",
+        "add",
+        secret_path.to_str().expect("utf-8 path expected"),
+    ]);
+    assert!(
+        add_secret.status.success(),
+        "secrets add failed: {}",
+        output_text(&add_secret)
+    );
+
+    // Without the passphrase (and without a terminal) the key cannot be unlocked.
+    let locked = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            secret_path.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(!locked.status.success());
+    assert!(output_text(&locked).contains("CONFIGSYNC_KEY_PASSPHRASE"));
+
+    fs::remove_file(&secret_path).expect("failed to remove plaintext");
+    let apply = run_with_passphrase(&["apply"]);
+    assert!(
+        apply.status.success(),
+        "apply failed: {}",
+        output_text(&apply)
+    );
+    assert_eq!(
+        fs::read_to_string(&secret_path).expect("secret should be restored"),
+        "TOKEN=abc"
+    );
+
+    let doctor = run_with_passphrase(&["doctor"]);
+    assert!(output_text(&doctor).contains("Key file is passphrase-protected"));
+}
+
+#[test]
+fn ssh_key_works_as_identity_and_recipient() {
+    let home = make_temp_home("secret-ssh");
+    let ssh_dir = home.join(".ssh");
+    fs::create_dir_all(&ssh_dir).expect("failed to create .ssh");
+    let keygen = Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+        .arg(ssh_dir.join("id_ed25519"))
+        .output();
+    match keygen {
+        Ok(output) if output.status.success() => {}
+        // ssh-keygen is not available everywhere (e.g. minimal CI images).
+        _ => return,
+    }
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+
+    // No `secrets init`: the SSH key is used directly.
+    let secret_path = home.join("token.env");
+    fs::write(&secret_path, "TOKEN=abc").expect("failed to create secret file");
+    let add_secret = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            secret_path.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(
+        add_secret.status.success(),
+        "secrets add failed: {}",
+        output_text(&add_secret)
+    );
+
+    let teammate = home.join("teammate");
+    let teammate_keygen = Command::new("ssh-keygen")
+        .args([
+            "-q",
+            "-t",
+            "ed25519",
+            "-N",
+            "",
+            "-C",
+            "teammate@example",
+            "-f",
+        ])
+        .arg(&teammate)
+        .output()
+        .expect("ssh-keygen should run");
+    assert!(teammate_keygen.status.success());
+
+    let grant = run(
+        &home,
+        &[
+            "secrets",
+            "grant",
+            teammate
+                .with_extension("pub")
+                .to_str()
+                .expect("utf-8 path expected"),
+        ],
+    );
+    assert!(
+        grant.status.success(),
+        "grant failed: {}",
+        output_text(&grant)
+    );
+
+    let config = fs::read_to_string(home.join(".config/configsync/team-config.toml"))
+        .expect("config should exist");
+    assert!(config.contains("ssh-ed25519 "));
+    assert!(!config.contains("teammate@example"));
+
+    // Roles without their own recipients already use the global list.
+    let pubkey = teammate.with_extension("pub");
+    let again = run(
+        &home,
+        &[
+            "secrets",
+            "grant",
+            pubkey.to_str().expect("utf-8 path expected"),
+            "--role",
+            "work",
+        ],
+    );
+    assert!(output_text(&again).contains("already a recipient of `work` secrets"));
+    let config = fs::read_to_string(home.join(".config/configsync/team-config.toml"))
+        .expect("config should exist");
+    assert_eq!(config.matches("ssh-ed25519 ").count(), 1, "{}", config);
+
+    fs::remove_file(&secret_path).expect("failed to remove plaintext");
+    let apply = run(&home, &["apply"]);
+    assert!(
+        apply.status.success(),
+        "apply failed: {}",
+        output_text(&apply)
+    );
+    assert_eq!(
+        fs::read_to_string(&secret_path).expect("secret should be restored"),
+        "TOKEN=abc"
+    );
+}
+
+#[test]
+fn export_and_import_key_round_trip() {
+    let home = make_temp_home("secret-backup");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+
+    let secret_path = home.join("token.env");
+    fs::write(&secret_path, "TOKEN=abc").expect("failed to create secret file");
+    let add_secret = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            secret_path.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(add_secret.status.success());
+
+    let run_with_passphrase = |args: &[&str]| {
+        Command::new(bin_path())
+            .args(args)
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", home.join(".config"))
+            .env("XDG_DATA_HOME", home.join(".local/share"))
+            .env("CONFIGSYNC_BACKUP_PASSPHRASE", "backup pass")
+            .output()
+            .expect("failed to execute configsync")
+    };
+
+    let backup_path = home.join("key-backup.age");
+    let export = run_with_passphrase(&[
+        "secrets",
+        "export-key",
+        "--output",
+        backup_path.to_str().expect("utf-8 path expected"),
+    ]);
+    assert!(
+        export.status.success(),
+        "export failed: {}",
+        output_text(&export)
+    );
+    let backup = fs::read_to_string(&backup_path).expect("backup should exist");
+    assert!(backup.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+
+    // Simulate a fresh machine.
+    let key_path = home.join(".local/share/configsync/key.txt");
+    let original_key = fs::read_to_string(&key_path).expect("key should exist");
+    fs::remove_file(&key_path).expect("failed to remove key");
+
+    let import = run_with_passphrase(&[
+        "secrets",
+        "import-key",
+        backup_path.to_str().expect("utf-8 path expected"),
+    ]);
+    assert!(
+        import.status.success(),
+        "import failed: {}",
+        output_text(&import)
+    );
+    assert!(output_text(&import).contains("Key decrypts 1 of 1 tracked secret(s)."));
+    assert_eq!(
+        fs::read_to_string(&key_path).expect("key should be restored"),
+        original_key
+    );
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&key_path)
+            .expect("key metadata")
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
+
+#[test]
+fn import_key_over_protected_key_keeps_it_protected() {
+    let home = make_temp_home("secret-backup-protected");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+
+    let run_with = |args: &[&str], vars: &[(&str, &str)]| {
+        Command::new(bin_path())
+            .args(args)
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", home.join(".config"))
+            .env("XDG_DATA_HOME", home.join(".local/share"))
+            .envs(vars.iter().copied())
+            .output()
+            .expect("failed to execute configsync")
+    };
+
+    // Back up a plain key, then replace it with a protected one.
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+    let secret_path = home.join("token.env");
+    fs::write(&secret_path, "TOKEN=abc").expect("failed to create secret file");
+    let add_secret = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            secret_path.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(add_secret.status.success());
+    let backup_path = home.join("key-backup.age");
+    let export = run_with(
+        &[
+            "secrets",
+            "export-key",
+            "--output",
+            backup_path.to_str().expect("utf-8 path expected"),
+        ],
+        &[("CONFIGSYNC_BACKUP_PASSPHRASE", "backup pass")],
+    );
+    assert!(
+        export.status.success(),
+        "export failed: {}",
+        output_text(&export)
+    );
+    let keygen = run_with(
+        &["secrets", "init", "--passphrase"],
+        &[("CONFIGSYNC_KEY_PASSPHRASE", "key pass")],
+    );
+    assert!(keygen.status.success());
+
+    // The key passphrase does not unlock the backup.
+    let import_args = [
+        "secrets",
+        "import-key",
+        backup_path.to_str().expect("utf-8 path expected"),
+        "--force",
+    ];
+    let wrong = run_with(&import_args, &[("CONFIGSYNC_KEY_PASSPHRASE", "key pass")]);
+    assert!(!wrong.status.success());
+    assert!(output_text(&wrong).contains("CONFIGSYNC_BACKUP_PASSPHRASE"));
+
+    let import = run_with(
+        &import_args,
+        &[
+            ("CONFIGSYNC_KEY_PASSPHRASE", "key pass"),
+            ("CONFIGSYNC_BACKUP_PASSPHRASE", "backup pass"),
+        ],
+    );
+    assert!(
+        import.status.success(),
+        "import failed: {}",
+        output_text(&import)
+    );
+
+    assert!(output_text(&import).contains("Key decrypts 1 of 1 tracked secret(s)."));
+
+    let key_text =
+        fs::read_to_string(home.join(".local/share/configsync/key.txt")).expect("key should exist");
+    assert!(key_text.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+    assert!(!key_text.contains("AGE-SECRET-KEY-"));
+
+    // The imported key unlocks with the old key passphrase.
+    let show = run_with(
+        &["secrets", "show", "token.env"],
+        &[("CONFIGSYNC_KEY_PASSPHRASE", "key pass")],
+    );
+    assert!(show.status.success(), "show failed: {}", output_text(&show));
+    assert_eq!(String::from_utf8_lossy(&show.stdout), "TOKEN=abc");
+}
+
+#[test]
+fn secrets_armor_converts_existing_binary_secrets() {
+    let home = make_temp_home("secret-armor");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+
+    let secret_path = home.join("token.env");
+    fs::write(&secret_path, "TOKEN=abc").expect("failed to create secret file");
+    let add_secret = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            secret_path.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(add_secret.status.success());
+
+    let encrypted_path = home.join(".config/configsync/secrets/token.env.age");
+    let binary = fs::read(&encrypted_path).expect("encrypted file should exist");
+    assert!(binary.starts_with(b"age-encryption.org/v1"));
+
+    let armor = run(&home, &["secrets", "armor"]);
+    assert!(
+        armor.status.success(),
+        "secrets armor failed: {}",
+        output_text(&armor)
+    );
+    let armored = fs::read_to_string(&encrypted_path).expect("armored file should be text");
+    assert!(armored.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+
+    fs::remove_file(&secret_path).expect("failed to remove plaintext");
+    let apply = run(&home, &["apply"]);
+    assert!(apply.status.success());
+    assert_eq!(
+        fs::read_to_string(&secret_path).expect("secret should be restored"),
+        "TOKEN=abc"
+    );
+}
+
+#[test]
+fn env_backend_secret_is_fetched_on_apply() {
+    let home = make_temp_home("backend-env");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+
+    let env_file = home.join("team.env");
+    fs::write(&env_file, "NPM_TOKEN=npm_abc123\nOTHER=x\n").expect("failed to write env file");
+
+    let dest = home.join(".npm-token");
+    let source = format!("{}#NPM_TOKEN", env_file.display());
+    let add = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            dest.to_str().expect("utf-8 path expected"),
+            "--backend",
+            "env",
+            "--source",
+            &source,
+        ],
+    );
+    assert!(add.status.success(), "add failed: {}", output_text(&add));
+
+    let apply = run(&home, &["apply"]);
+    assert!(
+        apply.status.success(),
+        "apply failed: {}",
+        output_text(&apply)
+    );
+    assert_eq!(
+        fs::read_to_string(&dest).expect("secret should be written"),
+        "npm_abc123"
+    );
+}
+
+#[test]
+fn vault_backend_secret_is_fetched_from_kv_server() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    let home = make_temp_home("backend-vault");
+
+    // Minimal stand-in for a Vault KV v2 server: answers a single request.
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind stand-in server");
+    let address = format!("http://{}", listener.local_addr().expect("local addr"));
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().expect("no request received");
+        let mut reader = BufReader::new(stream.try_clone().expect("clone stream"));
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).expect("read request");
+        let mut token = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).expect("read header");
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some(value) = line.to_ascii_lowercase().strip_prefix("x-vault-token:") {
+                token = value.trim().to_string();
+            }
+        }
+        let (status, body) =
+            if request_line.starts_with("GET /v1/secret/data/npm ") && token == "test-token" {
+                ("200 OK", r#"{"data":{"data":{"token":"vault_abc"}}}"#)
+            } else {
+                ("403 Forbidden", r#"{"errors":["permission denied"]}"#)
+            };
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+        .expect("write response");
+    });
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+
+    let dest = home.join(".npm-token");
+    let add = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            dest.to_str().expect("utf-8 path expected"),
+            "--backend",
+            "vault",
+            "--source",
+            "secret/npm#token",
+        ],
+    );
+    assert!(add.status.success(), "add failed: {}", output_text(&add));
+
+    let apply = Command::new(bin_path())
+        .arg("apply")
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_DATA_HOME", home.join(".local/share"))
+        .env("VAULT_ADDR", &address)
+        .env("VAULT_TOKEN", "test-token")
+        .output()
+        .expect("failed to execute configsync");
+    assert!(
+        apply.status.success(),
+        "apply failed: {}",
+        output_text(&apply)
+    );
+    server.join().expect("stand-in server panicked");
+    assert_eq!(
+        fs::read_to_string(&dest).expect("secret should be written"),
+        "vault_abc"
+    );
+}
+
+#[test]
+fn role_and_platform_scoped_secrets_are_skipped_on_other_machines() {
+    let home = make_temp_home("secret-scoped");
+
+    let init = run(&home, &["init", "--role", "personal"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+
+    let teammate = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p";
+    let grant = run(&home, &["secrets", "grant", teammate, "--role", "work"]);
+    assert!(
+        grant.status.success(),
+        "grant failed: {}",
+        output_text(&grant)
+    );
+    let again = run(&home, &["secrets", "grant", teammate, "--role", "work"]);
+    assert!(output_text(&again).contains("already a recipient of `work` secrets"));
+    let config = fs::read_to_string(home.join(".config/configsync/team-config.toml"))
+        .expect("config should exist");
+    assert_eq!(config.matches(teammate).count(), 1, "{}", config);
+
+    let work_secret = home.join("work.env");
+    fs::write(&work_secret, "TOKEN=work").expect("failed to create secret file");
+    let add_work = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            work_secret.to_str().expect("utf-8 path expected"),
+            "--role",
+            "work",
+        ],
+    );
+    assert!(
+        add_work.status.success(),
+        "secrets add failed: {}",
+        output_text(&add_work)
+    );
+
+    let plan9_secret = home.join("plan9.env");
+    fs::write(&plan9_secret, "TOKEN=plan9").expect("failed to create secret file");
+    let add_plan9 = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            plan9_secret.to_str().expect("utf-8 path expected"),
+            "--platform",
+            "plan9",
+        ],
+    );
+    assert!(add_plan9.status.success());
+
+    // Scoped by an expression, the secret still goes only to the role's set.
+    let laptop_secret = home.join("laptop.env");
+    fs::write(&laptop_secret, "TOKEN=laptop").expect("failed to create secret file");
+    let add_laptop = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            laptop_secret.to_str().expect("utf-8 path expected"),
+            "--role",
+            "work && !ci",
+        ],
+    );
+    assert!(
+        add_laptop.status.success(),
+        "secrets add failed: {}",
+        output_text(&add_laptop)
+    );
+    let list = run(&home, &["secrets", "list"]);
+    assert_eq!(output_text(&list).matches(teammate).count(), 2);
+
+    let config = fs::read_to_string(home.join(".config/configsync/team-config.toml"))
+        .expect("config should exist");
+    assert!(config.contains("[secrets.role_recipients]"));
+    assert!(config.contains(teammate));
+    assert!(config.contains("roles = [\"work\"]"));
+
+    fs::remove_file(&laptop_secret).expect("failed to remove plaintext");
+    fs::remove_file(&work_secret).expect("failed to remove plaintext");
+    fs::remove_file(&plan9_secret).expect("failed to remove plaintext");
+    let apply = run(&home, &["apply"]);
+    assert!(
+        apply.status.success(),
+        "apply failed: {}",
+        output_text(&apply)
+    );
+    let text = output_text(&apply);
+    assert!(text.contains("role `work` is false (work: not active)"));
+    assert!(text.contains("platforms [\"plan9\"] do not include"));
+    assert!(!work_secret.exists());
+    assert!(!plan9_secret.exists());
+}
+
+#[cfg(unix)]
+fn age_decrypt(path: &Path, identity: &age::x25519::Identity) -> Option<String> {
+    use std::io::Read;
+    let encrypted = fs::read(path).expect("encrypted secret should exist");
+    let decryptor = match age::Decryptor::new(age::armor::ArmoredReader::new(&encrypted[..])) {
+        Ok(age::Decryptor::Recipients(d)) => d,
+        _ => return None,
+    };
+    let mut reader = decryptor
+        .decrypt(std::iter::once(identity as &dyn age::Identity))
+        .ok()?;
+    let mut plaintext = String::new();
+    reader.read_to_string(&mut plaintext).ok()?;
+    Some(plaintext)
+}
+
+#[test]
+fn role_grant_keeps_global_recipients_and_this_machine() {
+    let home = make_temp_home("secret-role-grant");
+    let init = run(&home, &["init", "--role", "work"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    assert!(run(&home, &["secrets", "init"]).status.success());
+
+    let teammate = age::x25519::Identity::generate();
+    let teammate_key = teammate.to_public().to_string();
+    let grant = run(&home, &["secrets", "grant", &teammate_key]);
+    assert!(grant.status.success(), "{}", output_text(&grant));
+
+    let secret_path = home.join("work.env");
+    fs::write(&secret_path, "TOKEN=work").expect("failed to create secret file");
+    let add = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            secret_path.to_str().expect("utf-8 path expected"),
+            "--role",
+            "work",
+        ],
+    );
+    assert!(add.status.success(), "{}", output_text(&add));
+
+    let newcomer = age::x25519::Identity::generate();
+    let grant = run(
+        &home,
+        &[
+            "secrets",
+            "grant",
+            &newcomer.to_public().to_string(),
+            "--role",
+            "work",
+        ],
+    );
+    let text = output_text(&grant);
+    assert!(grant.status.success(), "{}", text);
+    assert!(!text.contains("Skipping"), "{}", text);
+
+    let encrypted = home.join(".config/configsync/secrets/work.env.age");
+    assert_eq!(
+        age_decrypt(&encrypted, &teammate).as_deref(),
+        Some("TOKEN=work")
+    );
+    assert_eq!(
+        age_decrypt(&encrypted, &newcomer).as_deref(),
+        Some("TOKEN=work")
+    );
+
+    // This machine stays in the set, so later grants and rotations still reach it.
+    let rotate = run(&home, &["secrets", "rotate"]);
+    let text = output_text(&rotate);
+    assert!(rotate.status.success(), "{}", text);
+    assert!(!text.contains("Skipping"), "{}", text);
+    fs::remove_file(&secret_path).expect("failed to remove plaintext");
+    let apply = run(&home, &["apply"]);
+    assert!(apply.status.success(), "{}", output_text(&apply));
+    assert_eq!(
+        fs::read_to_string(&secret_path).expect("secret should be restored"),
+        "TOKEN=work"
+    );
+}
+
+#[test]
+fn exec_injects_dotenv_secret_without_writing_plaintext() {
+    let home = make_temp_home("secret-exec");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+
+    let secret_path = home.join("app.env");
+    fs::write(&secret_path, "API_TOKEN=s3cret\n").expect("failed to create secret file");
+    let add_secret = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            secret_path.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(add_secret.status.success());
+    fs::remove_file(&secret_path).expect("failed to remove plaintext");
+
+    let exec = run(
+        &home,
+        &[
+            "exec",
+            "--secret",
+            "app.env",
+            "--",
+            "sh",
+            "-c",
+            "printf %s \"$API_TOKEN\"; exit 3",
+        ],
+    );
+    assert_eq!(exec.status.code(), Some(3), "{}", output_text(&exec));
+    assert_eq!(String::from_utf8_lossy(&exec.stdout), "s3cret");
+    assert!(!secret_path.exists(), "exec must not write plaintext");
+
+    let unknown = run(&home, &["exec", "--secret", "missing", "--", "true"]);
+    assert!(!unknown.status.success());
+    assert!(output_text(&unknown).contains("No tracked secret named 'missing'"));
+
+    let ci_path = home.join("ci.env");
+    fs::write(&ci_path, "CI_TOKEN=ci\n").expect("failed to create secret file");
+    let add_ci = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            ci_path.to_str().expect("utf-8 path expected"),
+            "--role",
+            "ci",
+        ],
+    );
+    assert!(add_ci.status.success(), "{}", output_text(&add_ci));
+    let other_role = run(&home, &["exec", "--secret", "ci.env", "--", "true"]);
+    let text = output_text(&other_role);
+    assert!(!other_role.status.success(), "{}", text);
+    assert!(
+        text.contains("Secret 'ci.env' does not apply to this machine: role `ci` is false"),
+        "{}",
+        text
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn secrets_list_show_edit_remove() {
+    let home = make_temp_home("secret-manage");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+
+    let secret_path = home.join("token.env");
+    fs::write(&secret_path, "TOKEN=abc").expect("failed to create secret file");
+    let add_secret = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            secret_path.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(add_secret.status.success());
+    let config_path = home.join(".config/configsync/team-config.toml");
+    let config = fs::read_to_string(&config_path).expect("config should exist");
+    assert!(config.contains("encrypted_files = [\"secrets/token.env.age\"]"));
+
+    let list = run(&home, &["secrets", "list"]);
+    assert!(list.status.success(), "list failed: {}", output_text(&list));
+    assert!(output_text(&list).contains("local:       matches"));
+
+    let show = run(&home, &["secrets", "show", "token.env"]);
+    assert!(show.status.success());
+    assert_eq!(String::from_utf8_lossy(&show.stdout), "TOKEN=abc");
+
+    let edit = Command::new(bin_path())
+        .args(["secrets", "edit", "token.env"])
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_DATA_HOME", home.join(".local/share"))
+        .env("EDITOR", "sed -i s/abc/xyz/")
+        .output()
+        .expect("failed to execute configsync");
+    assert!(edit.status.success(), "edit failed: {}", output_text(&edit));
+    let show = run(&home, &["secrets", "show", "token.env"]);
+    assert_eq!(String::from_utf8_lossy(&show.stdout), "TOKEN=xyz");
+    let list = run(&home, &["secrets", "list"]);
+    assert!(output_text(&list).contains("local:       differs from repository"));
+
+    let remove = run(&home, &["secrets", "remove", "token.env", "--delete-local"]);
+    assert!(
+        remove.status.success(),
+        "remove failed: {}",
+        output_text(&remove)
+    );
+    assert!(!secret_path.exists());
+    assert!(!home
+        .join(".config/configsync/secrets/token.env.age")
+        .exists());
+    let config = fs::read_to_string(&config_path).expect("config should exist");
+    assert!(!config.contains("token.env"));
+}
+
+#[test]
+fn leak_guard_blocks_plaintext_credentials_on_add_and_push() {
+    let home = make_temp_home("leak-guard");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+
+    let credentials = home.join("credentials");
+    fs::write(
+        &credentials,
+        format!(
+            "[default]\naws_access_key_id = AKIA{}\n",
+            "ABCDEFGHIJ234567"
+        ),
+    )
+    .expect("failed to create credentials file");
+    let blocked = run(
+        &home,
+        &["add", credentials.to_str().expect("utf-8 path expected")],
+    );
+    assert!(!blocked.status.success());
+    let text = output_text(&blocked);
+    assert!(text.contains("credentials:2: AWS access key ID"));
+    assert!(text.contains("configsync secrets add"));
+    assert!(
+        !text.contains("ABCDEFGHIJ234567"),
+        "findings must not echo the secret"
+    );
+    assert!(credentials.is_file() && !credentials.is_symlink());
+
+    let allowed = run(
+        &home,
+        &[
+            "add",
+            credentials.to_str().expect("utf-8 path expected"),
+            "--allow-secrets",
+        ],
+    );
+    assert!(allowed.status.success(), "{}", output_text(&allowed));
+    let config = fs::read_to_string(home.join(".config/configsync/team-config.toml"))
+        .expect("config should exist");
+    assert!(
+        !config.contains("allow_paths = [\"credentials\"]"),
+        "the override must not be recorded"
+    );
+    // The override covered that `add` only.
+    let push = run(&home, &["push"]);
+    assert!(!push.status.success());
+    assert!(output_text(&push).contains("credentials:2: AWS access key ID"));
+    let push = run(&home, &["push", "--allow-secrets"]);
+    assert!(push.status.success(), "{}", output_text(&push));
+
+    let notes = home.join("notes.txt");
+    fs::write(&notes, "nothing to see\n").expect("failed to create notes");
+    let add = run(
+        &home,
+        &["add", notes.to_str().expect("utf-8 path expected")],
+    );
+    assert!(add.status.success(), "{}", output_text(&add));
+    // Written through the symlink into the repository.
+    fs::write(&notes, format!("token = ghp_{}\n", "a1B2".repeat(9))).expect("write notes");
+
+    let blocked = run(&home, &["push"]);
+    assert!(!blocked.status.success());
+    assert!(output_text(&blocked).contains("notes.txt:1: GitHub token"));
+
+    let forced = run(&home, &["push", "--allow-secrets"]);
+    assert!(forced.status.success(), "{}", output_text(&forced));
+}
+
+#[test]
+fn push_scans_commits_not_yet_pushed() {
+    let home = make_temp_home("leak-guard-unpushed");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let config_dir = home.join(".config/configsync");
+    let remote = home.join("remote.git");
+    git2::Repository::init_bare(&remote).expect("failed to create remote");
+    let repo = git2::Repository::open(&config_dir).expect("config repo should exist");
+    repo.remote("origin", remote.to_str().expect("utf-8 path expected"))
+        .expect("failed to add origin");
+
+    let first = run(&home, &["push"]);
+    assert!(first.status.success(), "{}", output_text(&first));
+
+    // Committed by hand, so the working tree is clean when `push` runs.
+    fs::write(
+        config_dir.join("notes.txt"),
+        format!("token = ghp_{}\n", "a1B2".repeat(9)),
+    )
+    .expect("write notes");
+    let mut index = repo.index().expect("index");
+    index
+        .add_path(Path::new("notes.txt"))
+        .expect("failed to stage notes");
+    index.write().expect("failed to write index");
+    let tree = repo
+        .find_tree(index.write_tree().expect("tree"))
+        .expect("tree");
+    let parent = repo
+        .head()
+        .and_then(|h| h.peel_to_commit())
+        .expect("head commit");
+    let signature = git2::Signature::now("Test", "test@example.com").expect("signature");
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "Add notes",
+        &tree,
+        &[&parent],
+    )
+    .expect("failed to commit");
+
+    let blocked = run(&home, &["push"]);
+    assert!(!blocked.status.success());
+    assert!(output_text(&blocked).contains("notes.txt:1: GitHub token"));
+
+    let forced = run(&home, &["push", "--allow-secrets"]);
+    assert!(forced.status.success(), "{}", output_text(&forced));
+    assert!(output_text(&forced).contains("Successfully pushed"));
+
+    // Once pushed, the commit is no longer scanned.
+    let again = run(&home, &["push"]);
+    assert!(again.status.success(), "{}", output_text(&again));
+
+    // Nor on a new branch without an upstream: it is already on origin.
+    let head = repo
+        .head()
+        .and_then(|h| h.peel_to_commit())
+        .expect("head commit");
+    repo.branch("feature", &head, false).expect("create branch");
+    repo.set_head("refs/heads/feature").expect("switch branch");
+    let branch = run(&home, &["push"]);
+    assert!(branch.status.success(), "{}", output_text(&branch));
+}
+
+#[cfg(unix)]
+#[test]
+fn doctor_secrets_reports_drift_permissions_and_wrong_key() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let home = make_temp_home("doctor-secrets");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+
+    let secret_path = home.join("token.env");
+    fs::write(&secret_path, "TOKEN=abc").expect("failed to create secret file");
+    fs::set_permissions(&secret_path, fs::Permissions::from_mode(0o600)).expect("chmod");
+    let add_secret = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            secret_path.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(add_secret.status.success());
+
+    let doctor = run(&home, &["doctor", "--secrets"]);
+    let text = output_text(&doctor);
+    assert!(text.contains("matches the repository"), "{}", text);
+    assert!(text.contains("All systems operational."));
+
+    fs::write(&secret_path, "TOKEN=changed").expect("failed to modify secret");
+    fs::set_permissions(&secret_path, fs::Permissions::from_mode(0o644)).expect("chmod");
+    let doctor = run(&home, &["doctor", "--secrets"]);
+    let text = output_text(&doctor);
+    assert!(
+        text.contains("has drifted from the repository version"),
+        "{}",
+        text
+    );
+    assert!(text.contains("permissions unsafe: 644"));
+    assert!(!text.contains("TOKEN="), "doctor must not print plaintext");
+    assert_eq!(
+        text.matches("`configsync secrets add` it again").count(),
+        1,
+        "the suggestion is printed once: {}",
+        text
+    );
+
+    // A different key cannot decrypt the secret.
+    fs::remove_file(home.join(".local/share/configsync/key.txt")).expect("remove key");
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+    let doctor = run(&home, &["doctor", "--secrets"]);
+    assert!(output_text(&doctor).contains("Cannot decrypt secret"));
+}
+
+#[cfg(unix)]
+#[test]
+fn secret_directory_round_trips_with_modes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let home = make_temp_home("secret-dir");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+
+    let kube = home.join(".kube");
+    fs::create_dir_all(kube.join("bin")).expect("failed to create secret dir");
+    fs::write(kube.join("config"), "token: abc\n").expect("write config");
+    fs::set_permissions(kube.join("config"), fs::Permissions::from_mode(0o640)).expect("chmod");
+    fs::write(kube.join("bin/login.sh"), "#!/bin/sh\n").expect("write script");
+    fs::set_permissions(kube.join("bin/login.sh"), fs::Permissions::from_mode(0o755))
+        .expect("chmod");
+
+    let add = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            kube.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(add.status.success(), "add failed: {}", output_text(&add));
+    assert!(home
+        .join(".config/configsync/secrets/.kube.tar.age")
+        .exists());
+    let config = fs::read_to_string(home.join(".config/configsync/team-config.toml"))
+        .expect("config should exist");
+    assert!(config.contains("type = \"secretdir\""));
+
+    let show = run(&home, &["secrets", "show", ".kube"]);
+    let listing = output_text(&show);
+    assert!(listing.contains("755 bin/login.sh"), "{}", listing);
+    assert!(listing.contains("640 config"));
+
+    fs::remove_dir_all(&kube).expect("failed to remove plaintext dir");
+    let apply = run(&home, &["apply"]);
+    assert!(
+        apply.status.success(),
+        "apply failed: {}",
+        output_text(&apply)
+    );
+    assert_eq!(
+        fs::read_to_string(kube.join("config")).expect("config restored"),
+        "token: abc\n"
+    );
+    let mode = |p: &Path| fs::metadata(p).expect("metadata").permissions().mode() & 0o777;
+    assert_eq!(mode(&kube.join("config")), 0o640);
+    assert_eq!(mode(&kube.join("bin/login.sh")), 0o755);
+
+    let doctor = run(&home, &["doctor", "--secrets"]);
+    assert!(output_text(&doctor).contains("Secret directory"));
+    assert!(output_text(&doctor).contains("matches the repository"));
+
+    // Replaced by a file: the tarball and its records go.
+    fs::remove_dir_all(&kube).expect("failed to remove dir");
+    fs::write(&kube, "token: file\n").expect("write file");
+    let readd = |extra: &[&str]| {
+        let mut args = vec![
+            "secrets",
+            "add",
+            kube.to_str().expect("utf-8 path expected"),
+        ];
+        args.extend(extra);
+        let add = run(&home, &args);
+        assert!(add.status.success(), "add failed: {}", output_text(&add));
+        fs::read_to_string(home.join(".config/configsync/team-config.toml"))
+            .expect("config should exist")
+    };
+    let config = readd(&["--platform", "linux"]);
+    assert!(!home
+        .join(".config/configsync/secrets/.kube.tar.age")
+        .exists());
+    assert!(home.join(".config/configsync/secrets/.kube.age").exists());
+    assert!(!config.contains(".kube.tar.age"), "{}", config);
+    assert!(config.contains("type = \"secret\""), "{}", config);
+    assert!(config.contains("platforms = [\"linux\"]"), "{}", config);
+
+    // Platforms are kept unless given, and `*` resets them.
+    assert!(readd(&[]).contains("platforms = [\"linux\"]"));
+    assert!(readd(&["--platform", "*"]).contains("platforms = [\"*\"]"));
+
+    let doctor = run(&home, &["doctor"]);
+    let text = output_text(&doctor);
+    assert!(!text.contains("has changed since"), "{}", text);
+    assert!(!text.contains("no entry uses it"), "{}", text);
+}
+
+#[cfg(unix)]
+#[test]
+fn secret_modes_are_recorded_and_restored() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let home = make_temp_home("secret-modes");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+
+    let script = home.join("deploy.sh");
+    fs::write(&script, "#!/bin/sh\necho deploy\n").expect("failed to create script");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o750)).expect("chmod");
+    let service = home.join("service.conf");
+    fs::write(&service, "password=abc\n").expect("failed to create config");
+    fs::set_permissions(&service, fs::Permissions::from_mode(0o644)).expect("chmod");
+    let shared = home.join("shared.conf");
+    fs::write(&shared, "password=abc\n").expect("failed to create config");
+    fs::set_permissions(&shared, fs::Permissions::from_mode(0o640)).expect("chmod");
+
+    for (path, extra) in [
+        (&script, None),
+        (&service, Some("--sensitive")),
+        (&shared, None),
+    ] {
+        let mut args = vec![
+            "secrets",
+            "add",
+            path.to_str().expect("utf-8 path expected"),
+        ];
+        args.extend(extra);
+        let add = run(&home, &args);
+        assert!(add.status.success(), "add failed: {}", output_text(&add));
+    }
+    let config = fs::read_to_string(home.join(".config/configsync/team-config.toml"))
+        .expect("config should exist");
+    assert!(config.contains("mode = \"750\""));
+    assert!(config.contains("sensitive = true"));
+    // Defaults are left out of saved configs.
+    for noise in [
+        "sensitive = false",
+        "[roles]",
+        "[scan]",
+        "[secrets.role_recipients]",
+    ] {
+        assert!(!config.contains(noise), "{}", config);
+    }
+
+    fs::remove_file(&script).expect("remove script");
+    fs::remove_file(&service).expect("remove config");
+    fs::remove_file(&shared).expect("remove config");
+    let apply = run(&home, &["apply"]);
+    assert!(
+        apply.status.success(),
+        "apply failed: {}",
+        output_text(&apply)
+    );
+    let mode = |p: &Path| fs::metadata(p).expect("metadata").permissions().mode() & 0o777;
+    assert_eq!(mode(&script), 0o750);
+    // Sensitive entries never get group/other access.
+    assert_eq!(mode(&service), 0o600);
+    // Other secrets keep their recorded group access.
+    assert_eq!(mode(&shared), 0o640);
+
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o777)).expect("chmod");
+    let doctor = run(&home, &["doctor"]);
+    assert!(output_text(&doctor).contains("permissions unsafe: 777 (Should be 750)"));
+}
+
+#[test]
+fn secret_expiry_and_rotation_reminders() {
+    let home = make_temp_home("secret-expiry");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+
+    let expired = home.join("old.env");
+    fs::write(&expired, "TOKEN=old").expect("failed to create secret file");
+    let rotating = home.join("rotating.env");
+    fs::write(&rotating, "TOKEN=new").expect("failed to create secret file");
+
+    let bad = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            expired.to_str().expect("utf-8 path expected"),
+            "--expires-at",
+            "soon",
+        ],
+    );
+    assert!(!bad.status.success());
+    assert!(output_text(&bad).contains("Invalid date 'soon'"));
+
+    for (path, flag, value) in [
+        (&expired, "--expires-at", "2020-01-01"),
+        (&rotating, "--rotate-every", "1d"),
+    ] {
+        let add = run(
+            &home,
+            &[
+                "secrets",
+                "add",
+                path.to_str().expect("utf-8 path expected"),
+                flag,
+                value,
+            ],
+        );
+        assert!(add.status.success(), "add failed: {}", output_text(&add));
+    }
+
+    let status = run(&home, &["status"]);
+    assert!(status.status.success(), "{}", output_text(&status));
+    let text = output_text(&status);
+    assert!(text.contains("Secret 'old.env' expires"), "{}", text);
+    assert!(text.contains("ago (2020-01-01)"));
+    assert!(text.contains("Secret 'rotating.env' rotation due in 1 day(s)"));
+
+    let doctor = run(&home, &["doctor"]);
+    let text = output_text(&doctor);
+    assert!(text.contains("❌ Secret 'old.env' expires"));
+    assert!(text.contains("Issues found"));
+
+    let list = run(&home, &["secrets", "list"]);
+    let text = output_text(&list);
+    assert!(text.contains("expires:     2020-01-01"));
+    assert!(text.contains("rotation:    every 1d"));
+
+    // Secrets for other machines are not this machine's to rotate.
+    let config_path = home.join(".config/configsync/team-config.toml");
+    let config = fs::read_to_string(&config_path).expect("config should exist");
+    let scoped = config.replacen(
+        "expires_at = \"2020-01-01\"",
+        "expires_at = \"2020-01-01\"\nroles = [\"ci\"]",
+        1,
+    );
+    fs::write(&config_path, &scoped).expect("failed to write config");
+    let status = run(&home, &["status"]);
+    assert!(
+        !output_text(&status).contains("old.env"),
+        "{}",
+        output_text(&status)
+    );
+
+    // Entries from before `rotated_at` was recorded fall back to git history.
+    let push = run(&home, &["push"]);
+    assert!(push.status.success(), "push failed: {}", output_text(&push));
+    let unrecorded: String = scoped
+        .lines()
+        .filter(|line| !line.starts_with("rotated_at"))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    fs::write(&config_path, unrecorded).expect("failed to write config");
+    let list = run(&home, &["secrets", "list"]);
+    let text = output_text(&list);
+    assert!(text.contains("from git history)"), "{}", text);
+    let status = run(&home, &["status"]);
+    assert!(
+        output_text(&status).contains("Secret 'rotating.env' rotation due in 1 day(s)"),
+        "{}",
+        output_text(&status)
+    );
+}
+
+#[test]
+fn template_placeholders_are_rendered_from_inline_values() {
+    let home = make_temp_home("template");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+
+    let npmrc = home.join(".npmrc");
+    let template = "color=true\n//registry.npmjs.org/:_authToken={{ secret \"npm_token\" }}\n";
+    fs::write(&npmrc, template).expect("failed to write template");
+
+    let missing = run(
+        &home,
+        &[
+            "add",
+            npmrc.to_str().expect("utf-8 path expected"),
+            "--template",
+        ],
+    );
+    assert!(!missing.status.success());
+    assert!(output_text(&missing).contains("No value for placeholder(s) npm_token"));
+
+    let set = run(&home, &["secrets", "set", "npm_token", "npm_s3cret"]);
+    assert!(set.status.success(), "set failed: {}", output_text(&set));
+    assert!(home.join(".config/configsync/secrets/values.age").exists());
+
+    let add = run(
+        &home,
+        &[
+            "add",
+            npmrc.to_str().expect("utf-8 path expected"),
+            "--template",
+        ],
+    );
+    assert!(add.status.success(), "add failed: {}", output_text(&add));
+    let rendered = "color=true\n//registry.npmjs.org/:_authToken=npm_s3cret\n";
+    assert!(!npmrc.is_symlink());
+    assert_eq!(fs::read_to_string(&npmrc).expect("rendered"), rendered);
+    assert_eq!(
+        fs::read_to_string(home.join(".config/configsync/.npmrc")).expect("repo copy"),
+        template
+    );
+
+    fs::remove_file(&npmrc).expect("remove rendered file");
+    let apply = run(&home, &["apply"]);
+    assert!(
+        apply.status.success(),
+        "apply failed: {}",
+        output_text(&apply)
+    );
+    assert_eq!(fs::read_to_string(&npmrc).expect("rendered"), rendered);
+
+    let doctor = run(&home, &["doctor", "--secrets"]);
+    assert!(output_text(&doctor).contains("matches the repository"));
+
+    let list = run(&home, &["secrets", "list"]);
+    assert!(output_text(&list).contains("Inline values (secrets/values.age): npm_token"));
+
+    // Findings about the rendered copy name it as a template.
+    fs::write(&npmrc, "color=false\n").expect("edit rendered file");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&npmrc, fs::Permissions::from_mode(0o666)).expect("chmod");
+    }
+    let doctor = run(&home, &["doctor"]);
+    let text = output_text(&doctor);
+    assert!(
+        text.contains("Template \"") && text.contains("has changed since it was last applied"),
+        "{}",
+        text
+    );
+    assert!(!text.contains("Secret \""), "{}", text);
+}
+
+#[test]
+fn local_state_is_migrated_to_sqlite_and_records_applies() {
+    let home = make_temp_home("state-db");
+    let data_dir = home.join(".local/share/configsync");
+    fs::create_dir_all(&data_dir).expect("failed to create data dir");
+    fs::write(data_dir.join("state.toml"), "roles = [\"work\"]\n").expect("legacy state");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    assert!(data_dir.join("state.db").exists());
+    assert!(!data_dir.join("state.toml").exists());
+    assert!(data_dir.join("state.toml.migrated").exists());
+    assert!(String::from_utf8_lossy(&init.stderr).contains("Migrated local state"));
+
+    let state_before = fs::read(data_dir.join("state.db")).expect("state db");
+    let status = run(&home, &["status"]);
+    let text = output_text(&status);
+    assert!(text.contains("Machine roles: work"), "{}", text);
+    assert!(text.contains("Last apply:"), "{}", text);
+    let doctor = run(&home, &["doctor"]);
+    assert!(doctor.status.success(), "{}", output_text(&doctor));
+    assert_eq!(
+        fs::read(data_dir.join("state.db")).expect("state db"),
+        state_before,
+        "read-only commands must not write local state"
+    );
+
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+    let token = home.join(".token");
+    fs::write(&token, "first").expect("failed to write secret");
+    let add = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            token.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(add.status.success(), "add failed: {}", output_text(&add));
+    let apply = run(&home, &["apply"]);
+    assert!(apply.status.success());
+
+    let status = run(&home, &["status"]);
+    assert!(output_text(&status).contains("(1 entries on this machine)"));
+
+    let doctor = run(&home, &["doctor"]);
+    let text = output_text(&doctor);
+    assert!(text.contains("State database"), "{}", text);
+    assert!(
+        !text.contains("changed since it was last applied"),
+        "{}",
+        text
+    );
+
+    fs::write(&token, "edited locally").expect("failed to edit secret");
+    let doctor = run(&home, &["doctor"]);
+    assert!(output_text(&doctor).contains("has changed since it was last applied"));
+}
+
+/// Drops the `[[files]]` table whose source is `source` from team-config.toml.
+fn untrack(home: &Path, source: &str) {
+    let config_path = home.join(".config/configsync/team-config.toml");
+    let config = fs::read_to_string(&config_path).expect("config should exist");
+    let kept: Vec<&str> = config
+        .split("[[files]]")
+        .filter(|table| !table.contains(&format!("source = \"{}\"", source)))
+        .collect();
+    fs::write(&config_path, kept.join("[[files]]")).expect("failed to write config");
+}
+
+#[test]
+fn apply_prunes_links_for_removed_entries() {
+    let home = make_temp_home("prune");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+
+    let aliases = home.join(".aliases");
+    let vimrc = home.join(".vimrc");
+    fs::write(&aliases, "alias ll='ls -l'\n").expect("failed to write aliases");
+    fs::write(&vimrc, "set number\n").expect("failed to write vimrc");
+    for path in [&aliases, &vimrc] {
+        let add = run(&home, &["add", path.to_str().expect("utf-8 path expected")]);
+        assert!(add.status.success(), "add failed: {}", output_text(&add));
+    }
+    let apply = run(&home, &["apply"]);
+    assert!(apply.status.success());
+
+    // An untouched link is removed; one replaced by a local file is kept.
+    untrack(&home, ".aliases");
+    untrack(&home, ".vimrc");
+    fs::remove_file(&vimrc).expect("failed to remove link");
+    fs::write(&vimrc, "set relativenumber\n").expect("failed to write vimrc");
+
+    let doctor = run(&home, &["doctor"]);
+    assert!(
+        output_text(&doctor).contains("no longer applies to this machine (run `configsync apply`")
+    );
+
+    let apply = run(&home, &["apply"]);
+    let text = output_text(&apply);
+    assert!(apply.status.success(), "apply failed: {}", text);
+    assert!(
+        text.contains("(no longer applies to this machine)"),
+        "{}",
+        text
+    );
+    assert!(fs::symlink_metadata(&aliases).is_err());
+    assert!(
+        text.contains("changed since configsync wrote it"),
+        "{}",
+        text
+    );
+    assert_eq!(
+        fs::read_to_string(&vimrc).expect("vimrc kept"),
+        "set relativenumber\n"
+    );
+}
+
+#[test]
+fn role_commands_link_and_unlink_entries() {
+    let home = make_temp_home("roles");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+
+    let work = home.join(".work-aliases");
+    fs::write(&work, "alias vpn='openvpn work.ovpn'\n").expect("failed to write file");
+    let add = run(
+        &home,
+        &[
+            "add",
+            work.to_str().expect("utf-8 path expected"),
+            "--role",
+            "work",
+        ],
+    );
+    assert!(add.status.success(), "add failed: {}", output_text(&add));
+
+    let role_add = run(&home, &["role", "add", "work", "--apply"]);
+    let text = output_text(&role_add);
+    assert!(role_add.status.success(), "role add failed: {}", text);
+    assert!(text.contains("Role 'work' enables 1 entries"), "{}", text);
+    assert!(work.is_symlink());
+
+    let list = run(&home, &["role", "list"]);
+    let text = output_text(&list);
+    assert!(text.contains("This machine's roles: work"), "{}", text);
+    assert!(text.contains("* work (assigned, 1 entries)"), "{}", text);
+
+    let role_remove = run(&home, &["role", "remove", "work", "--apply"]);
+    let text = output_text(&role_remove);
+    assert!(role_remove.status.success(), "role remove failed: {}", text);
+    assert!(text.contains("1 entries no longer apply"), "{}", text);
+    assert!(fs::symlink_metadata(&work).is_err());
+    assert!(home.join(".config/configsync/.work-aliases").exists());
+
+    let again = run(&home, &["role", "remove", "work"]);
+    assert!(!again.status.success());
+    assert!(output_text(&again).contains("does not have role 'work'"));
+}
+
+#[test]
+fn role_expressions_and_implied_roles() {
+    let home = make_temp_home("role-expr");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let config_path = home.join(".config/configsync/team-config.toml");
+    let mut config = fs::read_to_string(&config_path).expect("config should exist");
+    assert!(!config.contains("[roles]"));
+    config.push_str("\n[roles]\nwork-laptop = [\"work\", \"laptop\"]\n");
+    fs::write(&config_path, config).expect("failed to write config");
+
+    let vpn = home.join(".vpn");
+    let games = home.join(".games");
+    for (path, role) in [(&vpn, "work && !ci"), (&games, "personal || ci")] {
+        fs::write(path, "x\n").expect("failed to write file");
+        let add = run(
+            &home,
+            &[
+                "add",
+                path.to_str().expect("utf-8 path expected"),
+                "--role",
+                role,
+            ],
+        );
+        assert!(add.status.success(), "add failed: {}", output_text(&add));
+    }
+
+    let role_add = run(&home, &["role", "add", "work-laptop", "--apply"]);
+    let text = output_text(&role_add);
+    assert!(role_add.status.success(), "role add failed: {}", text);
+    assert!(
+        text.contains("Role 'work-laptop' implies: laptop, work"),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains("Role 'work-laptop' enables 1 entries"),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains("role `personal || ci` is false (personal: not active, ci: not active)"),
+        "{}",
+        text
+    );
+
+    let status = run(&home, &["status"]);
+    let text = output_text(&status);
+    assert!(text.contains("work (implied by work-laptop)"), "{}", text);
+    assert!(text.contains("(platform)"), "{}", text);
+
+    // `ci` turns the first entry off and the second on.
+    let role_add = run(&home, &["role", "add", "ci", "--apply"]);
+    let text = output_text(&role_add);
+    assert!(role_add.status.success(), "role add failed: {}", text);
+    assert!(text.contains("Removed"), "{}", text);
+    assert!(fs::symlink_metadata(&vpn).is_err());
+
+    let mut config = fs::read_to_string(&config_path).expect("config should exist");
+    config = config.replace("personal || ci", "personal || (ci");
+    fs::write(&config_path, config).expect("failed to write config");
+    let doctor = run(&home, &["doctor"]);
+    assert!(output_text(&doctor).contains("Invalid role expression `personal || (ci`: missing `)`"));
+    // The entry would never apply, so commands refuse the config.
+    let apply = run(&home, &["apply"]);
+    assert!(!apply.status.success());
+    assert!(output_text(&apply).contains("Invalid config file"));
+    let config = fs::read_to_string(&config_path).expect("config should exist");
+    fs::write(
+        &config_path,
+        config.replace("personal || (ci", "personal || ci"),
+    )
+    .expect("failed to write config");
+
+    // Names that would not parse as expressions are refused.
+    for name in ["a&&b", "!ci", "work laptop"] {
+        let role_add = run(&home, &["role", "add", name]);
+        assert!(!role_add.status.success(), "role add {:?} succeeded", name);
+        assert!(output_text(&role_add).contains("Invalid role name"));
+    }
+    let status = run(&home, &["status"]);
+    assert!(status.status.success(), "{}", output_text(&status));
+    assert!(!output_text(&status).contains("a&&b"));
+
+    let mut config = fs::read_to_string(&config_path).expect("config should exist");
+    config = config.replacen("[roles]\n", "[roles]\n\"!ci\" = [\"work\"]\n", 1);
+    fs::write(&config_path, config).expect("failed to write config");
+    let validate = run(&home, &["config", "validate"]);
+    assert!(
+        output_text(&validate).contains("Invalid role name `!ci`"),
+        "{}",
+        output_text(&validate)
+    );
+}
+
+#[test]
+fn machines_register_on_init_and_push() {
+    let home = make_temp_home("machines");
+    let repo_dir = home.join(".config/configsync");
+
+    let invalid = run(&home, &["init", "--role", "work && ci"]);
+    assert!(!invalid.status.success());
+    assert!(output_text(&invalid).contains("Invalid role name `work && ci`"));
+    assert!(!repo_dir.exists());
+
+    let init = run(&home, &["init", "--role", "work"]);
+    let text = output_text(&init);
+    assert!(init.status.success(), "init failed: {}", text);
+    assert!(text.contains("Registered this machine as"), "{}", text);
+    let records: Vec<_> = fs::read_dir(repo_dir.join("machines"))
+        .expect("machines directory")
+        .collect();
+    assert_eq!(records.len(), 1);
+
+    let machines = run(&home, &["machines"]);
+    let text = output_text(&machines);
+    assert!(machines.status.success(), "machines failed: {}", text);
+    assert!(text.contains("(this machine)"), "{}", text);
+    assert!(text.contains("roles: work"), "{}", text);
+    assert!(text.contains("up to date"), "{}", text);
+
+    // A teammate that synced long ago, before the next change.
+    fs::write(
+        repo_dir.join("machines/0123456789abcdef.toml"),
+        "id = \"0123456789abcdef\"\nhostname = \"old-box\"\nos = \"linux\"\narch = \"x86_64\"\nroles = [\"ci\"]\nconfigsync_version = \"0.3.0\"\nlast_sync_at = \"2020-01-01T00:00:00Z\"\n",
+    )
+    .expect("failed to write machine record");
+    let push = run(&home, &["push"]);
+    assert!(push.status.success(), "push failed: {}", output_text(&push));
+
+    let notes = home.join(".notes");
+    fs::write(&notes, "hello\n").expect("failed to write file");
+    let add = run(
+        &home,
+        &["add", notes.to_str().expect("utf-8 path expected")],
+    );
+    assert!(add.status.success(), "add failed: {}", output_text(&add));
+    let push = run(&home, &["push"]);
+    assert!(push.status.success(), "push failed: {}", output_text(&push));
+
+    let machines = run(&home, &["machines"]);
+    let text = output_text(&machines);
+    assert!(text.contains("2 machine(s) registered"), "{}", text);
+    assert!(text.contains("old-box [0123456789abcdef]"), "{}", text);
+    assert!(text.contains("1 commit(s) behind HEAD"), "{}", text);
+    assert!(text.contains("stale: no sync for"), "{}", text);
+    assert_eq!(text.matches("up to date").count(), 1, "{}", text);
+
+    // Nothing changed, so pushing again does not create a commit.
+    let commits = || {
+        let repo = git2::Repository::open(&repo_dir).expect("config repo");
+        let mut walk = repo.revwalk().expect("revwalk");
+        walk.push_head().expect("head");
+        walk.count()
+    };
+    let before = commits();
+    let push = run(&home, &["push"]);
+    assert!(push.status.success(), "push failed: {}", output_text(&push));
+    assert!(output_text(&push).contains("Nothing to commit."));
+    assert_eq!(commits(), before);
+
+    // A push blocked by the leak scan leaves the machine record alone.
+    let own_record = fs::read_dir(repo_dir.join("machines"))
+        .expect("machines directory")
+        .map(|entry| entry.expect("machine record").path())
+        .find(|path| !path.ends_with("0123456789abcdef.toml"))
+        .expect("own machine record");
+    let recorded = fs::read_to_string(&own_record).expect("machine record");
+    let leak = repo_dir.join("credentials");
+    fs::write(
+        &leak,
+        format!("aws_access_key_id = AKIA{}\n", "ABCDEFGHIJ234567"),
+    )
+    .expect("failed to write credentials");
+    let blocked = run(&home, &["push"]);
+    assert!(!blocked.status.success(), "{}", output_text(&blocked));
+    assert_eq!(
+        fs::read_to_string(&own_record).expect("machine record"),
+        recorded
+    );
+    fs::remove_file(&leak).expect("failed to remove credentials");
+
+    // A record that has not been refreshed for a while is, even with nothing
+    // else to commit, so the machine does not look stale.
+    let backdated = recorded
+        .lines()
+        .map(|line| {
+            if line.starts_with("last_sync_at") {
+                "last_sync_at = \"2020-01-01T00:00:00Z\"".to_string()
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    fs::write(&own_record, backdated).expect("failed to write machine record");
+    let repo = git2::Repository::open(&repo_dir).expect("config repo");
+    let mut index = repo.index().expect("index");
+    index
+        .add_all(["machines"], git2::IndexAddOption::DEFAULT, None)
+        .expect("stage record");
+    index.write().expect("write index");
+    let tree = repo
+        .find_tree(index.write_tree().expect("write tree"))
+        .expect("tree");
+    let head = repo.head().expect("head").peel_to_commit().expect("commit");
+    let signature = git2::Signature::now("Test", "test@example.com").expect("signature");
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "Backdate",
+        &tree,
+        &[&head],
+    )
+    .expect("commit");
+
+    let before = commits();
+    let push = run(&home, &["push"]);
+    assert!(push.status.success(), "push failed: {}", output_text(&push));
+    assert_eq!(commits(), before + 1);
+    let machines = run(&home, &["machines"]);
+    assert_eq!(
+        output_text(&machines).matches("stale: no sync for").count(),
+        1,
+        "{}",
+        output_text(&machines)
+    );
+}
+
+#[test]
+fn explain_traces_why_an_entry_is_applied_or_not() {
+    let home = make_temp_home("explain");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+
+    let tmux = home.join(".tmux.conf");
+    fs::write(&tmux, "set -g mouse on\n").expect("failed to write tmux.conf");
+    let tmux_arg = tmux.to_str().expect("utf-8 path expected");
+    let add = run(&home, &["add", tmux_arg, "--role", "work"]);
+    assert!(add.status.success(), "add failed: {}", output_text(&add));
+
+    let explain = run(&home, &["explain", tmux_arg]);
+    let text = output_text(&explain);
+    assert!(explain.status.success(), "explain failed: {}", text);
+    assert!(
+        text.contains("❌ Roles: do not match this machine"),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains("role `work` is false (work: not active)"),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains("=> apply skips this entry on this machine."),
+        "{}",
+        text
+    );
+
+    let role = run(&home, &["role", "add", "work", "--apply"]);
+    assert!(
+        role.status.success(),
+        "role add failed: {}",
+        output_text(&role)
+    );
+    let explain = run(&home, &["explain", ".tmux.conf"]);
+    let text = output_text(&explain);
+    assert!(text.contains("✅ Roles: match this machine"), "{}", text);
+    assert!(text.contains("✅ Source:"), "{}", text);
+    assert!(
+        text.contains("already linked; apply leaves it alone"),
+        "{}",
+        text
+    );
+    assert!(text.contains("ℹ️ Last applied"), "{}", text);
+
+    fs::remove_file(&tmux).expect("failed to remove link");
+    fs::write(&tmux, "set -g mouse off\n").expect("failed to write tmux.conf");
+    let explain = run(&home, &["explain", tmux_arg]);
+    let text = output_text(&explain);
+    assert!(text.contains("exists and is not a symlink"), "{}", text);
+    assert!(text.contains("apply refuses to overwrite it"), "{}", text);
+
+    let unknown = run(&home, &["explain", "~/.nothing"]);
+    assert!(!unknown.status.success());
+    assert!(output_text(&unknown).contains("No tracked entry matches"));
+
+    // The key check decrypts the entry rather than just finding a key file.
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+    let token = home.join(".token");
+    fs::write(&token, "t0ken").expect("failed to write token");
+    let add = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            token.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(add.status.success(), "add failed: {}", output_text(&add));
+    let explain = run(&home, &["explain", ".token"]);
+    assert!(
+        output_text(&explain).contains("decrypts this entry"),
+        "{}",
+        output_text(&explain)
+    );
+
+    // A passphrase-protected key that cannot be unlocked.
+    let protect = Command::new(bin_path())
+        .args(["secrets", "protect"])
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_DATA_HOME", home.join(".local/share"))
+        .env("CONFIGSYNC_KEY_PASSPHRASE", "key pass")
+        .output()
+        .expect("failed to execute configsync");
+    assert!(protect.status.success(), "{}", output_text(&protect));
+    let explain = run(&home, &["explain", ".token"]);
+    let text = output_text(&explain);
+    assert!(text.contains("❌ Key:"), "{}", text);
+    assert!(text.contains("CONFIGSYNC_KEY_PASSPHRASE"), "{}", text);
+
+    // A key that is not a recipient.
+    fs::remove_file(home.join(".local/share/configsync/key.txt")).expect("remove key");
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+    let explain = run(&home, &["explain", ".token"]);
+    let text = output_text(&explain);
+    assert!(text.contains("cannot decrypt this entry"), "{}", text);
+    assert!(text.contains("apply skips this entry"), "{}", text);
+}
+
+#[cfg(unix)]
+#[test]
+fn doctor_fix_repairs_safe_issues_and_leaves_conflicts() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let home = make_temp_home("doctor-fix");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let keygen = run(&home, &["secrets", "init"]);
+    assert!(keygen.status.success());
+
+    let bashrc = home.join(".bashrc");
+    let gitconfig = home.join(".gitconfig");
+    let token = home.join(".token");
+    fs::write(&bashrc, "export EDITOR=vim\n").expect("failed to write bashrc");
+    fs::write(&gitconfig, "[user]\n").expect("failed to write gitconfig");
+    fs::write(&token, "t0ken").expect("failed to write token");
+    for path in [&bashrc, &gitconfig] {
+        let add = run(&home, &["add", path.to_str().expect("utf-8 path expected")]);
+        assert!(add.status.success(), "add failed: {}", output_text(&add));
+    }
+    let add = run(
+        &home,
+        &[
+            "secrets",
+            "add",
+            token.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(add.status.success(), "add failed: {}", output_text(&add));
+    let apply = run(&home, &["apply"]);
+    assert!(apply.status.success());
+
+    // Dangling link, missing secret, loose key file, and a real conflict.
+    fs::remove_file(&bashrc).expect("failed to remove link");
+    std::os::unix::fs::symlink(home.join(".config/configsync/gone"), &bashrc)
+        .expect("failed to create dangling link");
+    fs::remove_file(&token).expect("failed to remove token");
+    let key = home.join(".local/share/configsync/key.txt");
+    fs::set_permissions(&key, fs::Permissions::from_mode(0o644)).expect("chmod key");
+    fs::remove_file(&gitconfig).expect("failed to remove link");
+    fs::write(&gitconfig, "[user]\n  name = me\n").expect("failed to write gitconfig");
+
+    let doctor = run(&home, &["doctor"]);
+    assert!(output_text(&doctor).contains("can be repaired automatically"));
+
+    let preview = run(&home, &["doctor", "--fix", "--dry-run"]);
+    let text = output_text(&preview);
+    assert!(text.contains("Would make 3 fix(es)"), "{}", text);
+    assert!(text.contains("🔧 restore"), "{}", text);
+    assert!(!token.exists());
+    let mode = fs::metadata(&key).expect("key").permissions().mode() & 0o777;
+    assert_eq!(mode, 0o644);
+
+    let fix = run(&home, &["doctor", "--fix"]);
+    let text = output_text(&fix);
+    // The conflicting .gitconfig is left alone, so doctor still fails.
+    assert_eq!(fix.status.code(), Some(1), "{}", text);
+    assert!(text.contains("🔧 Fixed: relink"), "{}", text);
+    assert!(text.contains("🔧 Fixed: chmod 600"), "{}", text);
+    assert_eq!(fs::read_to_string(&token).expect("token restored"), "t0ken");
+    assert_eq!(
+        fs::read_link(&bashrc).expect("bashrc relinked"),
+        home.join(".config/configsync/.bashrc")
+    );
+    let mode = fs::metadata(&key).expect("key").permissions().mode() & 0o777;
+    assert_eq!(mode, 0o600);
+    assert_eq!(
+        fs::read_to_string(&gitconfig).expect("conflict kept"),
+        "[user]\n  name = me\n"
+    );
+
+    let doctor = run(&home, &["doctor"]);
+    let text = output_text(&doctor);
+    assert!(!text.contains("can be repaired automatically"), "{}", text);
+    assert!(text.contains("is NOT a symlink"), "{}", text);
+}
+
+#[cfg(unix)]
+#[test]
+fn doctor_reports_json_and_exits_non_zero_on_errors() {
+    let home = make_temp_home("doctor-json");
+
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let bashrc = home.join(".bashrc");
+    fs::write(&bashrc, "export EDITOR=vim\n").expect("failed to write bashrc");
+    let add = run(
+        &home,
+        &["add", bashrc.to_str().expect("utf-8 path expected")],
+    );
+    assert!(add.status.success(), "add failed: {}", output_text(&add));
+
+    let doctor = run(&home, &["doctor", "--json"]);
+    assert!(doctor.status.success(), "{}", output_text(&doctor));
+    let report: serde_json::Value =
+        serde_json::from_slice(&doctor.stdout).expect("doctor --json prints JSON");
+    assert_eq!(report["ok"], true);
+    assert_eq!(report["errors"], 0);
+
+    fs::remove_file(&bashrc).expect("failed to remove link");
+    let doctor = run(&home, &["doctor", "--json"]);
+    assert_eq!(doctor.status.code(), Some(1), "{}", output_text(&doctor));
+    let report: serde_json::Value =
+        serde_json::from_slice(&doctor.stdout).expect("doctor --json prints JSON");
+    assert_eq!(report["ok"], false);
+    let missing = report["findings"]
+        .as_array()
+        .expect("findings array")
+        .iter()
+        .find(|f| f["severity"] == "error")
+        .expect("an error finding");
+    assert_eq!(missing["check"], "files");
+    assert_eq!(
+        missing["subject"],
+        bashrc.to_str().expect("utf-8 path expected")
+    );
+    assert_eq!(missing["repairable"], true);
+    assert!(missing["fix"]
+        .as_str()
+        .expect("suggested fix")
+        .starts_with("restore"));
+
+    // Only the selected checks run, so the missing link does not count.
+    let git_only = run(&home, &["doctor", "--check", "git", "--check", "state"]);
+    let text = output_text(&git_only);
+    assert!(git_only.status.success(), "{}", text);
+    assert!(text.contains("Git repository valid"), "{}", text);
+    assert!(!text.contains("Destination missing"), "{}", text);
+
+    let unknown = run(&home, &["doctor", "--check", "links"]);
+    assert!(!unknown.status.success());
+    assert!(output_text(&unknown).contains("Available checks: config, git"));
+}
+
+#[test]
+fn doctor_checks_repository_state_and_entry_sources() {
+    let origin_home = make_temp_home("doctor-repo-origin");
+    let init = run(&origin_home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let origin = origin_home.join(".config/configsync");
+
+    let home = make_temp_home("doctor-repo");
+    let repo_dir = home.join(".config/configsync");
+    let init = run(
+        &home,
+        &[
+            "init",
+            "--url",
+            origin.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+
+    // Registering this machine is a local commit not yet on origin.
+    let doctor = run(&home, &["doctor", "--check", "git"]);
+    let text = output_text(&doctor);
+    assert!(doctor.status.success(), "{}", text);
+    assert!(text.contains("Remote origin: "), "{}", text);
+    assert!(text.contains("1 commit(s) ahead of origin/"), "{}", text);
+    assert!(text.contains("Working tree clean"), "{}", text);
+
+    // A committed file nothing uses, and an entry reaching outside the repo.
+    fs::write(repo_dir.join("stray.txt"), "left over\n").expect("failed to write file");
+    let doctor = run(&home, &["doctor", "--check", "git"]);
+    assert!(output_text(&doctor).contains("1 uncommitted change(s): stray.txt"));
+    let push = run(&home, &["push"]);
+    assert!(push.status.success(), "push failed: {}", output_text(&push));
+    let config_path = repo_dir.join("team-config.toml");
+    let config = fs::read_to_string(&config_path)
+        .expect("failed to read config")
+        .replacen(
+            "files = []\n",
+            "[[files]]\nsource = \"../../.ssh/config\"\ndestination = \"~/.ssh/config\"\ntype = \"file\"\n",
+            1,
+        );
+    fs::write(&config_path, config).expect("failed to write config");
+
+    let doctor = run(&home, &["doctor", "--check", "sources"]);
+    let text = output_text(&doctor);
+    assert_eq!(doctor.status.code(), Some(1), "{}", text);
+    assert!(
+        text.contains("Source \"../../.ssh/config\" of ~/.ssh/config is outside the repository"),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains("\"stray.txt\" is in the repository but no entry uses it"),
+        "{}",
+        text
+    );
+    assert!(
+        !text.contains("team-config.toml\" is in the repository"),
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn config_validate_reports_problems_with_lines() {
+    let home = make_temp_home("config-validate");
+    let init = run(&home, &["init"]);
+    assert!(init.status.success(), "init failed: {}", output_text(&init));
+    let config_path = home.join(".config/configsync/team-config.toml");
+    let repo_dir = home.join(".config/configsync");
+    for source in [".bashrc", "token.txt", "token.age"] {
+        fs::write(repo_dir.join(source), "").expect("failed to write source");
+    }
+
+    let header = "[team]\nname = \"team\"\nmaintainers = []\n\n[repository]\nurl = \"\"\nbranch = \"main\"\n";
+    let bashrc =
+        "\n[[files]]\nsource = \".bashrc\"\ndestination = \"~/.bashrc\"\ntype = \"file\"\n";
+    let duplicate =
+        "\n[[files]]\nsource = \"other\"\ndestination = \"~/.bashrc\"\ntype = \"file\"\n";
+    let token = "\n[[files]]\nsource = \"token.txt\"\ndestination = \"~/.token\"\ntype = \"secret\"\nplatforms = [\"mac\"]\nroles = [\"wrk\"]\n";
+    fs::write(
+        &config_path,
+        format!("{}{}{}{}", header, bashrc, duplicate, token),
+    )
+    .expect("failed to write config");
+
+    let validate = run(&home, &["config", "validate"]);
+    let text = output_text(&validate);
+    assert!(!validate.status.success(), "{}", text);
+    assert!(
+        text.contains("team-config.toml:16:15: Destination `~/.bashrc` is already used by the entry at line 11"),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains(
+            "team-config.toml:20:10: Secret source `token.txt` is not an encrypted `.age` file"
+        ),
+        "{}",
+        text
+    );
+    assert!(text.contains("23:13: Unknown platform `mac`"), "{}", text);
+    assert!(text.contains("24:9: Role `wrk` is not defined"), "{}", text);
+    assert!(
+        text.contains("15:10: Source `other` does not exist in the repository"),
+        "{}",
+        text
+    );
+    assert!(text.contains("has 2 error(s) and 3 warning(s)"), "{}", text);
+
+    // Every command refuses a config with errors.
+    let status = run(&home, &["status"]);
+    let text = output_text(&status);
+    assert!(!status.status.success(), "{}", text);
+    assert!(text.contains("Invalid config file"), "{}", text);
+    assert!(text.contains(":16:15: Destination"), "{}", text);
+
+    // Warnings alone pass, for use in a pre-commit hook.
+    fs::write(
+        &config_path,
+        format!(
+            "{}{}{}",
+            header,
+            bashrc,
+            token.replace("token.txt", "token.age")
+        ),
+    )
+    .expect("failed to write config");
+    let validate = run(
+        &home,
+        &[
+            "config",
+            "validate",
+            config_path.to_str().expect("utf-8 path expected"),
+        ],
+    );
+    let text = output_text(&validate);
+    assert!(validate.status.success(), "{}", text);
+    assert!(text.contains("2 warning(s), no errors."), "{}", text);
+    let status = run(&home, &["status"]);
+    assert!(status.status.success(), "{}", output_text(&status));
+}